@@ -4,18 +4,35 @@ import React from "react";
 import { objName } from "./utils";
 import { RequestEmitter } from "./wasm_loader";
 
-const SPHERE = "Sphere";
-const BOX    = "Box";
+const SPHERE   = "Sphere";
+const BOX      = "Box";
+const CYLINDER = "Cylinder";
+const CONE     = "Cone";
+const CAPSULE  = "Capsule";
+const TORUS    = "Torus";
 
-const SPHERE_DEFAULT = { Sphere: { radius: 0.5, center: { x: 0.0, y: 0.5, z: 0.0 } } }
+const SPHERE_DEFAULT   = { Sphere: { radius: 0.5, center: { x: 0.0, y: 0.5, z: 0.0 } } }
+const CYLINDER_DEFAULT = { Cylinder: { base: { x: 0.0, y: 0.0, z: 0.0 }, top: { x: 0.0, y: 1.0, z: 0.0 }, radius: 0.5, capped: true } }
+const CONE_DEFAULT     = { Cone: { base: { x: 0.0, y: 0.0, z: 0.0 }, apex: { x: 0.0, y: 1.0, z: 0.0 }, radius: 0.5, capped: true } }
+const CAPSULE_DEFAULT  = { Capsule: { base: { x: 0.0, y: 0.3, z: 0.0 }, top: { x: 0.0, y: 0.7, z: 0.0 }, radius: 0.3 } }
+const TORUS_DEFAULT    = { Torus: { center: { x: 0.0, y: 0.5, z: 0.0 }, axis: { x: 0.0, y: 0.0, z: 1.0 }, major_radius: 0.35, minor_radius: 0.15 } }
 
 const ShapeDisplay = ({ shape, onShapeChange }) => {
     let shapeType = objName(shape);
 
     const displayOptions = () => {
         switch (shapeType) {
-            case SPHERE: return displaySphere(shape.Sphere);
-            case BOX:    return displayBox(shape.Box);
+            case SPHERE:   return displaySphere(shape.Sphere);
+            case BOX:      return displayBox(shape.Box);
+            case CYLINDER: return displaySlider("Radius", "radius", 0.05, 1.0);
+            case CONE:     return displaySlider("Radius", "radius", 0.05, 1.0);
+            case CAPSULE:  return displaySlider("Radius", "radius", 0.05, 0.5);
+            case TORUS:    return (
+                <div>
+                    {displaySlider("Major radius", "major_radius", 0.1, 1.0)}
+                    {displaySlider("Minor radius", "minor_radius", 0.05, 0.5)}
+                </div>
+            );
         }
 
         return <>Invalid shape</> 
@@ -56,6 +73,36 @@ const ShapeDisplay = ({ shape, onShapeChange }) => {
         )
     }
     
+    // slider for a numeric field of the current shape
+    const displaySlider = (label: string, field: string, min: number, max: number) : ReactJSXElement => {
+        let value = shape[shapeType][field];
+
+        let updateValue = (_e, newValue: number, __e) => {
+            if (value === newValue) return;
+
+            let shapeClone = structuredClone(shape);
+            shapeClone[shapeType][field] = newValue;
+
+            onShapeChange(shapeClone);
+        }
+
+        return (
+            <div>
+                <div className="config-slider-label">{`${label} (${value})`}</div>
+                <Slider 
+                    min={min}
+                    max={max}
+                    step={0.05}
+                    value={value}
+                    aria-label="Default" 
+                    size={"small"}
+                    valueLabelDisplay="auto" 
+                    onChange={updateValue}
+                />
+            </div>
+        )
+    }
+
     const ShapeChoice = ({ name }) : ReactJSXElement => {
         let checked = name === shapeType;
 
@@ -73,8 +120,12 @@ const ShapeDisplay = ({ shape, onShapeChange }) => {
         if (newMatType === shapeType) return;
 
         switch (newMatType) {
-            case SPHERE: { onShapeChange(SPHERE_DEFAULT); break; }
-            case BOX:    { onShapeChange(new RequestEmitter().get_default_box());    break; }
+            case SPHERE:   { onShapeChange(SPHERE_DEFAULT); break; }
+            case BOX:      { onShapeChange(new RequestEmitter().get_default_box());    break; }
+            case CYLINDER: { onShapeChange(CYLINDER_DEFAULT); break; }
+            case CONE:     { onShapeChange(CONE_DEFAULT);     break; }
+            case CAPSULE:  { onShapeChange(CAPSULE_DEFAULT);  break; }
+            case TORUS:    { onShapeChange(TORUS_DEFAULT);    break; }
         }
     }
 
@@ -90,6 +141,10 @@ const ShapeDisplay = ({ shape, onShapeChange }) => {
                 >
                     <ShapeChoice name={SPHERE} />
                     <ShapeChoice name={BOX} />
+                    <ShapeChoice name={CYLINDER} />
+                    <ShapeChoice name={CONE} />
+                    <ShapeChoice name={CAPSULE} />
+                    <ShapeChoice name={TORUS} />
                 </RadioGroup>
             </div>
             <div className="shape-options-container">
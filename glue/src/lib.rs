@@ -1,6 +1,6 @@
 use js_sys::Promise;
 use wasm_bindgen::prelude::*;
-use ray_tracer::{Camera, Box, WorkerPool, Scene, RayTracer, Element, MaterialType, Metal, Sphere, Vec3, Color, ShapeType, CameraConfig, RayTracerConfig, TextureType, CheckeredTexture, Lambertian};
+use ray_tracer::{Camera, Box, WorkerPool, Scene, RayTracer, Element, MaterialType, Metal, Sphere, Vec3, Color, ShapeType, CameraConfig, RayTracerConfig, TextureType, CheckeredTexture, Lambertian, Image, ImageFormat};
 use web_sys::console::log_1;
 
 use serde::{Deserialize, Serialize};
@@ -80,6 +80,13 @@ impl RequestEmitter {
         ray_tracer.render_scene_wasm(scene, camera, CANVAS_WIDTH, CANVAS_HEIGHT, pool)
     }
 
+    /// encode a rendered (serialized) image as a PNG, e.g. to be downloaded
+    pub fn encode_png(&self, image: JsValue) -> Result<Vec<u8>, JsValue> {
+        let image = image.into_serde::<Image>().map_err(|e| JsValue::from(e.to_string()))?;
+
+        image.encode(ImageFormat::Png).map_err(|e| JsValue::from(e.to_string()))
+    }
+
     /// TESTING - get serialized element
     pub fn get_element(&self) -> Result<JsValue, JsValue> {
         let element = Element::new( 
@@ -4,6 +4,7 @@ pub trait Boundable {
     fn bound(&self) -> AABB;
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct AABB {
     minimum: Vec3,
     maximum: Vec3
@@ -14,19 +15,64 @@ impl AABB {
         AABB { minimum, maximum }
     }
 
+    /// box that contains nothing, the identity for `AABB::surrounding`
+    pub fn empty() -> AABB {
+        AABB {
+            minimum: Vec3::ones() * f64::INFINITY,
+            maximum: Vec3::ones() * f64::NEG_INFINITY,
+        }
+    }
+
     pub fn min(&self) -> Vec3 { self.minimum }
     pub fn max(&self) -> Vec3 { self.maximum }
 
+    /// smallest box containing both boxes
+    pub fn surrounding(a: AABB, b: AABB) -> AABB {
+        AABB {
+            minimum: Vec3::new(
+                f64::min(a.minimum.x, b.minimum.x),
+                f64::min(a.minimum.y, b.minimum.y),
+                f64::min(a.minimum.z, b.minimum.z),
+            ),
+            maximum: Vec3::new(
+                f64::max(a.maximum.x, b.maximum.x),
+                f64::max(a.maximum.y, b.maximum.y),
+                f64::max(a.maximum.z, b.maximum.z),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// false for boxes of unbounded shapes (e.g. planes)
+    pub fn is_finite(&self) -> bool {
+        (0..3).all(|i| self.minimum[i].is_finite() && self.maximum[i].is_finite())
+    }
+
     // doesn't implemenent Collidable because this collision function
     // ONLY checks if a collision occurs, and doesn't produce a collision
     // record
 
     /// check for collisions, using the slab method
     pub fn collide(&self, ray: Ray) -> bool {
-        let mut t_min = 0.0; 
-        let mut t_max = f64::MAX; 
+        self.hit(ray, 0.0, f64::MAX)
+    }
 
-        for i in 0..3 as usize {
+    /// check for collisions inside of the interval [t_min, t_max], using the slab method
+    pub fn hit(&self, ray: Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for i in 0..3 {
             /*
             The ray does not change its position along the i axis. Therefore,
             the origin of the ray determines whether the ray is going to be.
@@ -38,60 +84,21 @@ impl AABB {
                     return false;
                 }
                 continue
-            } 
+            }
 
             // compute intersection times
-            let mut t0 = (self.minimum[i] - ray.origin[i]) / ray.direction[i];
-            let mut t1 = (self.maximum[i] - ray.origin[i]) / ray.direction[i];
-            
-            (t0, t1) = (f64::min(t0, t1), f64::max(t0, t1));
+            let t0 = (self.minimum[i] - ray.origin[i]) / ray.direction[i];
+            let t1 = (self.maximum[i] - ray.origin[i]) / ray.direction[i];
 
-            t_min = f64::max(t_min, t0);
-            t_max = f64::min(t_max, t1);
+            t_min = f64::max(t_min, f64::min(t0, t1));
+            t_max = f64::min(t_max, f64::max(t0, t1));
 
             if t_min > t_max {
                 return false;
             }
         }
 
-
-        // intersection times all overlap 
+        // intersection times all overlap
         true
     }
-
-    /*
-   /// check for collisions, using the slab method
-     fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
-         for i in 0..3 as usize {
-             let (t0, t1) = if ray.direction()[i] == 0f64 {
-                 /*
-                 The ray does not change its position along the i axis. Therefore,
-                 the origin of the ray determines whether the ray is going to be.
-                 If the ray is inside the interval, then it could intersect on
-                 a different axis.
-                 */
-                 if ray.origin()[i] >= self.maximum[i] && ray.origin()[i] < self.minimum[i] {
-                     return false;
-                 }
-
-                 (ray.time(), ray.time())
-             } else {
-                 let t0 = (self.minimum[i] - ray.origin()[i]) / ray.direction()[i];
-                 let t1 = (self.maximum[i] - ray.origin()[i]) / ray.direction()[i];
-
-                 (f64::min(t0, t1), f64::max(t0, t1))
-             };
-
-             t_min = f64::max(t_min, t0);
-             t_max = f64::min(t_max, t1);
-
-             if t_min >= t_max {
-                 return false;
-             }
-         }
-         true
-     } 
-    
-    */
 }
-
@@ -0,0 +1,326 @@
+//! Keyframed animation of a scene, rendered frame by frame.
+//!
+//! An animation lists tracks (see `motion::Track`) for the camera and for
+//! elements, which are found by id. Frame `n` shows the scene at time
+//! `n / frame_rate`. Elements are placed by their transform track, on top of
+//! their own shape, and material tracks change one parameter each.
+//!
+//! In a scene file:
+//!
+//! ```toml
+//! [animation]
+//! frame_rate = 24.0
+//! end_frame = 47
+//! camera.origin = [
+//!     { time = 0.0, value = { x = 0.0, y = 1.0, z = 4.0 }, curve = "EaseInOut" },
+//!     { time = 2.0, value = { x = 4.0, y = 1.0, z = 0.0 } },
+//! ]
+//!
+//! [[animation.elements]]
+//! id = { id = 1 }
+//! transform = [
+//!     { time = 0.0, value = {} },
+//!     { time = 2.0, value = { rotation_degrees = { x = 0.0, y = 180.0, z = 0.0 } } },
+//! ]
+//! material = [{ Color = [
+//!     { time = 0.0, value = { red = 0.8, green = 0.3, blue = 0.3 } },
+//!     { time = 2.0, value = { red = 0.3, green = 0.3, blue = 0.8 } },
+//! ] }]
+//! ```
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::camera::CameraConfig;
+use crate::image::{Color, ImageError, ImageFormat};
+use crate::instance::Instance;
+use crate::material::MaterialParameter;
+use crate::motion::Track;
+use crate::scene::{ElementId, Scene};
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub enum AnimationError {
+    UnknownElement { id: u64 },
+    /// the element's material doesn't have the animated parameter
+    UnsupportedParameter { id: u64, parameter: &'static str },
+    Image { error: ImageError },
+    Io { path: PathBuf, error: std::io::Error },
+}
+
+impl Display for AnimationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnimationError::UnknownElement { id } => write!(f, "no element with id {} to animate", id),
+            AnimationError::UnsupportedParameter { id, parameter } => {
+                write!(f, "the material of element {} has no {} to animate", id, parameter)
+            }
+            AnimationError::Image { error } => error.fmt(f),
+            AnimationError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Animation {
+    /// frames per unit of time
+    pub frame_rate: f64,
+    /// first frame rendered by default
+    pub start_frame: u32,
+    /// last frame rendered by default, inclusive
+    pub end_frame: u32,
+    pub camera: CameraTracks,
+    pub elements: Vec<ElementTracks>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            frame_rate: 24.0,
+            start_frame: 0,
+            end_frame: 0,
+            camera: CameraTracks::default(),
+            elements: vec![],
+        }
+    }
+}
+
+/// Tracks without keyframes leave the camera as it is
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraTracks {
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub origin: Track<Vec3>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub look_at: Track<Vec3>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub vertical_fov_degrees: Track<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ElementTracks {
+    pub id: ElementId,
+    /// applied on top of the element's shape
+    #[serde(default, skip_serializing_if = "Track::is_empty")]
+    pub transform: Track<Transform>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub material: Vec<MaterialTrack>,
+}
+
+/// Track of one of the parameters of `MaterialParameter`
+#[derive(Clone, Serialize, Deserialize)]
+pub enum MaterialTrack {
+    Color(Track<Color>),
+    Fuzz(Track<f64>),
+    RefractiveIndex(Track<f64>),
+    Brightness(Track<f64>),
+    Roughness(Track<f64>),
+}
+
+impl MaterialTrack {
+    pub fn value_at(&self, time: f64) -> Option<MaterialParameter> {
+        match self {
+            MaterialTrack::Color(t)           => t.value_at(time).map(MaterialParameter::Color),
+            MaterialTrack::Fuzz(t)            => t.value_at(time).map(MaterialParameter::Fuzz),
+            MaterialTrack::RefractiveIndex(t) => t.value_at(time).map(MaterialParameter::RefractiveIndex),
+            MaterialTrack::Brightness(t)      => t.value_at(time).map(MaterialParameter::Brightness),
+            MaterialTrack::Roughness(t)       => t.value_at(time).map(MaterialParameter::Roughness),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            MaterialTrack::Color(_)           => "color",
+            MaterialTrack::Fuzz(_)            => "fuzz",
+            MaterialTrack::RefractiveIndex(_) => "refractive index",
+            MaterialTrack::Brightness(_)      => "brightness",
+            MaterialTrack::Roughness(_)       => "roughness",
+        }
+    }
+}
+
+impl Animation {
+    /// moment shown by the frame
+    pub fn frame_time(&self, frame: u32) -> f64 {
+        frame as f64 / self.frame_rate
+    }
+
+    /// The camera and scene at the frame. The camera's shutter interval is
+    /// relative to the frame's time, so moving shapes blur along their paths.
+    pub fn frame(&self, frame: u32, camera: &CameraConfig, scene: &Scene) -> Result<(CameraConfig, Scene), AnimationError> {
+        let time = self.frame_time(frame);
+
+        let mut camera = camera.clone();
+        camera.origin = self.camera.origin.value_at(time).unwrap_or(camera.origin);
+        camera.look_at = self.camera.look_at.value_at(time).unwrap_or(camera.look_at);
+        camera.vertical_fov_degrees = self.camera.vertical_fov_degrees.value_at(time).unwrap_or(camera.vertical_fov_degrees);
+        camera.shutter_open += time;
+        camera.shutter_close += time;
+
+        let mut scene = scene.clone();
+
+        for tracks in &self.elements {
+            let id = tracks.id.id;
+            if !scene.objects.iter().any(|e| e.id == tracks.id) {
+                return Err(AnimationError::UnknownElement { id });
+            }
+
+            let element = scene.get_element_mut(tracks.id);
+
+            if let Some(transform) = tracks.transform.value_at(time) {
+                element.set_shape(Instance::new(element.shape.clone(), transform).into());
+            }
+
+            let mut material = element.material.clone();
+            for track in &tracks.material {
+                if let Some(parameter) = track.value_at(time) {
+                    if !material.set_parameter(parameter) {
+                        return Err(AnimationError::UnsupportedParameter { id, parameter: track.name() });
+                    }
+                }
+            }
+            element.set_material(material);
+        }
+
+        scene.rebuild();
+
+        Ok((camera, scene))
+    }
+}
+
+/// Where the frames of an animation are saved, and at what size
+#[derive(Clone)]
+pub struct ImageSequence {
+    /// see `frame_path`
+    pub pattern: PathBuf,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Path of a frame of an image sequence. The last run of '#' in the file name
+/// is replaced by the zero padded frame number, e.g. "frames/shot_####.png"
+/// becomes "frames/shot_0012.png". Without one, "_0012" is appended to the
+/// file's stem.
+pub fn frame_path<P: AsRef<Path>>(pattern: P, frame: u32) -> PathBuf {
+    let pattern = pattern.as_ref();
+    let name = pattern.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+
+    let name = match name.rfind('#') {
+        Some(end) => {
+            let start = name[..end].trim_end_matches('#').len();
+            let width = end + 1 - start;
+
+            format!("{}{:0width$}{}", &name[..start], frame, &name[end + 1..], width = width)
+        }
+        None => {
+            let stem = pattern.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+            match pattern.extension() {
+                Some(extension) => format!("{}_{:04}.{}", stem, frame, extension.to_string_lossy()),
+                None            => format!("{}_{:04}", stem, frame),
+            }
+        }
+    };
+
+    pattern.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, MaterialType};
+    use crate::motion::Keyframe;
+    use crate::ray::Ray;
+    use crate::scene::Element;
+    use crate::shape::{ShapeType, Sphere};
+
+    fn sphere() -> Element {
+        Element::new(
+            MaterialType::Lambertian(Lambertian::new(Color::white().into())),
+            ShapeType::Sphere(Sphere::new(Vec3::zeros(), 1.0)),
+        )
+    }
+
+    fn animation(tracks: ElementTracks) -> Animation {
+        Animation { frame_rate: 1.0, end_frame: 2, elements: vec![tracks], ..Animation::default() }
+    }
+
+    #[test]
+    fn frame_path_replaces_hashes() {
+        assert_eq!(frame_path("frames/shot_####.png", 12), PathBuf::from("frames/shot_0012.png"));
+        assert_eq!(frame_path("frames/shot_###_v2.png", 7), PathBuf::from("frames/shot_007_v2.png"));
+        assert_eq!(frame_path("take#2/shot_#.exr", 3), PathBuf::from("take#2/shot_3.exr"));
+        assert_eq!(frame_path("a#_##.png", 5), PathBuf::from("a#_05.png"));
+        assert_eq!(frame_path("shot_##.png", 123), PathBuf::from("shot_123.png"));
+    }
+
+    #[test]
+    fn frame_path_appends_number() {
+        assert_eq!(frame_path("frames/shot.png", 12), PathBuf::from("frames/shot_0012.png"));
+        assert_eq!(frame_path("frames/shot", 12), PathBuf::from("frames/shot_0012"));
+        assert_eq!(frame_path("shot.tar.gz", 1), PathBuf::from("shot.tar_0001.gz"));
+    }
+
+    #[test]
+    fn unknown_element() {
+        let scene = Scene::new(vec![sphere()]);
+        let id = ElementId::new();
+        let animation = animation(ElementTracks { id, transform: Track::default(), material: vec![] });
+
+        match animation.frame(0, &CameraConfig::default(), &scene) {
+            Err(AnimationError::UnknownElement { id: found }) => assert_eq!(found, id.id),
+            _ => panic!("expected an unknown element error"),
+        }
+    }
+
+    #[test]
+    fn unsupported_parameter() {
+        let element = sphere();
+        let id = element.id;
+        let scene = Scene::new(vec![element]);
+        let fuzz = MaterialTrack::Fuzz(Track::new(vec![Keyframe::new(0.0, 0.5)]));
+        let animation = animation(ElementTracks { id, transform: Track::default(), material: vec![fuzz] });
+
+        match animation.frame(0, &CameraConfig::default(), &scene) {
+            Err(AnimationError::UnsupportedParameter { id: found, parameter }) => {
+                assert_eq!(found, id.id);
+                assert_eq!(parameter, "fuzz");
+            }
+            _ => panic!("expected an unsupported parameter error"),
+        }
+    }
+
+    #[test]
+    fn frame_moves_camera_and_elements() {
+        let element = sphere();
+        let id = element.id;
+        let scene = Scene::new(vec![element]);
+        let transform = Track::new(vec![
+            Keyframe::new(0.0, Transform::identity()),
+            Keyframe::new(2.0, Transform::translation(Vec3::new(4.0, 0.0, 0.0))),
+        ]);
+        let mut animation = animation(ElementTracks { id, transform, material: vec![] });
+        animation.camera.origin = Track::new(vec![
+            Keyframe::new(0.0, Vec3::new(0.0, 0.0, 5.0)),
+            Keyframe::new(2.0, Vec3::new(0.0, 0.0, 9.0)),
+        ]);
+
+        let camera = CameraConfig { shutter_close: 0.5, ..CameraConfig::default() };
+        let (camera, scene) = animation.frame(1, &camera, &scene).unwrap();
+
+        assert!((camera.origin - Vec3::new(0.0, 0.0, 7.0)).length() < 1e-9);
+        assert_eq!((camera.shutter_open, camera.shutter_close), (1.0, 1.5));
+
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert!(scene.collide(Ray::new(Vec3::new(2.0, 5.0, 0.0), down)).is_some());
+        assert!(scene.collide(Ray::new(Vec3::new(0.0, 5.0, 0.0), down)).is_none());
+    }
+}
@@ -1,17 +1,348 @@
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
 use ray_tracer::*;
+use ray_tracer::scene_file::SceneFile;
+use ray_tracer::utils::seed_random;
 
-fn main() {
-    let width = 400u32;
-    let height = width; 
+/// Render a scene with the Lucis ray tracer
+#[derive(Parser)]
+#[command(name = "lucis")]
+struct Args {
+    /// built-in scene to render
+    #[arg(long, value_enum, default_value_t = BuiltinScene::Rectangles, conflicts_with = "file")]
+    scene: BuiltinScene,
+    /// scene file (.json or .toml) to render instead of a built-in scene
+    #[arg(long)]
+    file: Option<PathBuf>,
+    #[arg(long, default_value_t = 400)]
+    width: u32,
+    /// defaults to the width
+    #[arg(long)]
+    height: Option<u32>,
+    /// samples per pixel
+    #[arg(long)]
+    samples: Option<u32>,
+    /// maximum number of times a ray can bounce
+    #[arg(long)]
+    depth: Option<u32>,
+    /// background color as "r,g,b", with channels in [0, 1]
+    #[arg(long, value_parser = parse_color, conflicts_with = "environment")]
+    background: Option<Color>,
+    /// equirectangular image (.hdr, .png or .ppm) to light the scene with
+    #[arg(long, conflicts_with = "sun")]
+    environment: Option<PathBuf>,
+    /// light the scene with a daylight sky, the sun's position is given as
+    /// "elevation,azimuth" in degrees, with azimuth 0 towards -z
+    #[arg(long, value_parser = parse_sun, conflicts_with = "background")]
+    sun: Option<Vec3>,
+    /// haziness of the sky, from 2 (clear) to 10 (hazy)
+    #[arg(long, default_value_t = 3.0, requires = "sun")]
+    turbidity: f64,
+    /// exposure adjustment in stops
+    #[arg(long)]
+    exposure: Option<f64>,
+    #[arg(long, value_enum)]
+    tone_map: Option<ToneMapArg>,
+    /// gamma to encode colors with, "srgb" or "linear"
+    #[arg(long, value_parser = parse_transfer)]
+    transfer: Option<Transfer>,
+    /// output file, the image is written to stdout when omitted
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// defaults to the output file's extension, or ppm
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+    /// seed for the random number generator, makes renders reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+    /// number of threads to render with, defaults to the number of cpus
+    #[arg(long)]
+    threads: Option<usize>,
+    /// render the scene file's animation to numbered images. The last run of
+    /// '#' in the output file name is replaced by the frame number, e.g.
+    /// "frames/shot_####.png". Frames that already exist are skipped, so an
+    /// interrupted render can be resumed
+    #[arg(long, requires_all = ["file", "output"])]
+    animate: bool,
+    /// frames to render as "first-last", defaults to the animation's range
+    #[arg(long, value_parser = parse_frames, requires = "animate")]
+    frames: Option<RangeInclusive<u32>>,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum BuiltinScene {
+    OneSphere,
+    TwoSpheres,
+    Simple,
+    Materials,
+    Rectangles,
+    CornellBox,
+    CornellSmoke,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum OutputFormat {
+    /// ASCII (P3) PPM
+    Ppm,
+    /// binary (P6) PPM
+    P6,
+    Png,
+    /// Radiance RGBE, linear radiance without post processing
+    Hdr,
+    /// OpenEXR, linear radiance without post processing
+    Exr,
+}
+
+/// formats are either post processed images, or linear radiance
+enum Output {
+    Image(ImageFormat),
+    Hdr(HdrFormat),
+}
+
+impl From<OutputFormat> for Output {
+    fn from(format: OutputFormat) -> Output {
+        match format {
+            OutputFormat::Ppm => Output::Image(ImageFormat::Ppm),
+            OutputFormat::P6  => Output::Image(ImageFormat::P6),
+            OutputFormat::Png => Output::Image(ImageFormat::Png),
+            OutputFormat::Hdr => Output::Hdr(HdrFormat::Radiance),
+            OutputFormat::Exr => Output::Hdr(HdrFormat::Exr),
+        }
+    }
+}
+
+/// the format given by the arguments, or the output file's extension
+fn output_format(args: &Args) -> Output {
+    if let Some(format) = args.format {
+        return format.into();
+    }
+
+    let path = match &args.output {
+        Some(path) => path,
+        None       => return Output::Image(ImageFormat::Ppm),
+    };
+
+    match (ImageFormat::from_path(path), HdrFormat::from_path(path)) {
+        (Some(format), _) => Output::Image(format),
+        (_, Some(format)) => Output::Hdr(format),
+        _ => Output::Image(ImageFormat::Ppm),
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum ToneMapArg {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+impl From<ToneMapArg> for ToneMap {
+    fn from(tone_map: ToneMapArg) -> ToneMap {
+        match tone_map {
+            ToneMapArg::Clamp    => ToneMap::Clamp,
+            ToneMapArg::Reinhard => ToneMap::Reinhard,
+            ToneMapArg::Aces     => ToneMap::Aces,
+        }
+    }
+}
+
+fn parse_transfer(value: &str) -> Result<Transfer, String> {
+    match value {
+        "srgb"   => Ok(Transfer::Srgb),
+        "linear" => Ok(Transfer::Linear),
+        gamma => match gamma.parse::<f64>() {
+            Ok(gamma) if gamma > 0.0 => Ok(Transfer::Gamma(gamma)),
+            _ => Err(format!("expected a positive gamma, 'srgb' or 'linear', found '{}'", value)),
+        },
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    let channels = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f64>().map_err(|_| format!("invalid channel '{}'", channel)))
+        .collect::<Result<Vec<f64>, String>>()?;
+
+    match channels[..] {
+        [red, green, blue] => Ok(Color::new(red, green, blue)),
+        _ => Err(format!("expected 3 channels, found {}", channels.len())),
+    }
+}
+
+fn parse_sun(value: &str) -> Result<Vec3, String> {
+    let angles = value
+        .split(',')
+        .map(|angle| angle.trim().parse::<f64>().map_err(|_| format!("invalid angle '{}'", angle)))
+        .collect::<Result<Vec<f64>, String>>()?;
+
+    match angles[..] {
+        [elevation, azimuth] => Ok(Sky::sun_at(elevation, azimuth)),
+        _ => Err(format!("expected elevation and azimuth, found {} values", angles.len())),
+    }
+}
 
-    let (camera, scene) = Scene::rectangles(); 
+fn parse_frames(value: &str) -> Result<RangeInclusive<u32>, String> {
+    let frame = |frame: &str| frame.trim().parse::<u32>().map_err(|_| format!("invalid frame '{}'", frame));
 
-    let image = RayTracer::new(RayTracerConfig::default()).render_scene(
+    match value.split_once('-') {
+        Some((first, last)) => Ok(frame(first)?..=frame(last)?),
+        None                => Ok(frame(value)?..=frame(value)?),
+    }
+}
+
+fn load_scene(args: &Args) -> Result<(CameraConfig, RayTracerConfig, Scene, Option<Animation>), String> {
+    if let Some(path) = &args.file {
+        let file = SceneFile::load(path).map_err(|e| e.to_string())?;
+        let scene = Scene::new(file.elements).with_lights(file.lights);
+
+        return Ok((file.camera, file.renderer, scene, file.animation));
+    }
+
+    let (camera, scene) = match args.scene {
+        BuiltinScene::OneSphere    => (CameraConfig::default(), Scene::one_sphere()),
+        BuiltinScene::TwoSpheres   => (CameraConfig::default(), Scene::two_spheres()),
+        BuiltinScene::Simple       => (CameraConfig::default(), Scene::simple()),
+        BuiltinScene::Materials    => (CameraConfig::default(), Scene::materials()),
+        BuiltinScene::Rectangles   => Scene::rectangles(),
+        BuiltinScene::CornellBox   => Scene::cornell_box(),
+        BuiltinScene::CornellSmoke => Scene::cornell_smoke(),
+    };
+
+    Ok((camera, RayTracerConfig::default(), scene, None))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|e| e.to_string())?;
+    }
+
+    // procedural textures are generated while the scene is built
+    if let Some(seed) = args.seed {
+        seed_random(seed);
+    }
+
+    let width = args.width;
+    let height = args.height.unwrap_or(width);
+
+    if width < 2 || height < 2 {
+        return Err("the image must be at least 2x2 pixels".to_string());
+    }
+
+    let load_start = Instant::now();
+    let (mut camera_config, mut config, scene, animation) = load_scene(&args)?;
+    eprintln!("loaded scene with {} elements in {:.2?}", scene.objects.len(), load_start.elapsed());
+
+    camera_config.aspect = width as f64 / height as f64;
+    config.samples = args.samples.unwrap_or(config.samples);
+    config.max_bounce_depth = args.depth.unwrap_or(config.max_bounce_depth);
+    if let Some(color) = args.background {
+        config.background = Background::Color(color);
+    }
+    if let Some(path) = &args.environment {
+        config.background = Background::Map(EnvironmentMap::load(path).map_err(|e| e.to_string())?);
+    }
+    if let Some(sun) = args.sun {
+        config.background = Background::Sky(Sky::new(sun, args.turbidity, Color::new(0.3, 0.3, 0.3)));
+    }
+    config.seed = args.seed.or(config.seed);
+    config.post_process.exposure = args.exposure.unwrap_or(config.post_process.exposure);
+    config.post_process.tone_map = args.tone_map.map_or(config.post_process.tone_map, ToneMap::from);
+    config.post_process.transfer = args.transfer.unwrap_or(config.post_process.transfer);
+
+    eprintln!(
+        "rendering {}x{} with {} samples per pixel and {} bounces",
+        width, height, config.samples, config.max_bounce_depth
+    );
+
+    if args.animate {
+        let animation = animation.ok_or("the scene file has no animation")?;
+        return render_animation(&args, config, &camera_config, &scene, &animation, width, height);
+    }
+
+    let post_process = config.post_process;
+
+    let render_start = Instant::now();
+    let hdr = RayTracer::new(config).render_hdr_with_progress(
         &scene,
-        camera,
+        Camera::new(camera_config),
         width,
-        height
+        height,
+        |rows, total| {
+            // only report when the percentage changes
+            if rows * 100 / total != (rows - 1) * 100 / total {
+                eprint!("\rrendering: {:>3}%", rows * 100 / total);
+            }
+        },
     );
+    eprintln!("\rrendered in {:.2?}", render_start.elapsed());
+
+    let mut stdout = std::io::stdout().lock();
+    let result = match (output_format(&args), &args.output) {
+        (Output::Image(format), Some(path)) => post_process.to_image(&hdr).save_as(path, format),
+        (Output::Image(format), None)       => post_process.to_image(&hdr).write_to(&mut stdout, format),
+        (Output::Hdr(format), Some(path))   => hdr.save_as(path, format),
+        (Output::Hdr(format), None)         => hdr.write_to(&mut stdout, format),
+    };
+    result.map_err(|e| e.to_string())?;
+
+    if let Some(path) = &args.output {
+        eprintln!("saved to {}", path.display());
+    }
+
+    Ok(())
+}
 
-    image.write_as_ppm();
+fn render_animation(
+    args: &Args,
+    config: RayTracerConfig,
+    camera: &CameraConfig,
+    scene: &Scene,
+    animation: &Animation,
+    width: u32,
+    height: u32,
+) -> Result<(), String> {
+    let format = match output_format(args) {
+        Output::Image(format) => format,
+        Output::Hdr(_) => return Err("animations can only be rendered to ppm and png images".to_string()),
+    };
+    let sequence = ImageSequence {
+        pattern: args.output.clone().ok_or("animations need an output file")?,
+        format,
+        width,
+        height,
+    };
+    let frames = args.frames.clone().unwrap_or(animation.start_frame..=animation.end_frame);
+
+    let render_start = Instant::now();
+    RayTracer::new(config)
+        .render_sequence(scene, camera, animation, frames, &sequence, |progress| match progress {
+            SequenceProgress::Skipped { frame, path } => {
+                eprintln!("frame {}: {} already exists, skipping", frame, path.display());
+            }
+            SequenceProgress::Rows { frame, rows, total } => {
+                if rows * 100 / total != (rows - 1) * 100 / total {
+                    eprint!("\rframe {}: {:>3}%", frame, rows * 100 / total);
+                }
+            }
+            SequenceProgress::Saved { frame, path } => {
+                eprintln!("\rframe {}: saved to {}", frame, path.display());
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    eprintln!("rendered in {:.2?}", render_start.elapsed());
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
 }
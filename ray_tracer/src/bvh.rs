@@ -0,0 +1,375 @@
+use crate::aabb::{AABB, Boundable};
+use crate::collisions::{CollisionRecord, MIN_INTERSECTION_T};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Bounding volume hierarchy over a list of boundable items.
+///
+/// The hierarchy only stores indices into the list it was built from, so
+/// the same structure accelerates the elements of a scene and the triangles
+/// of a mesh. Callers are responsible for calling `Bvh::rebuild` when items
+/// are added or removed and `Bvh::refit` when items are edited in place.
+#[derive(Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// item indices, leaves reference contiguous ranges of this list
+    indices: Vec<usize>,
+    /// items without a finite bound (e.g. infinite planes), these are checked linearly
+    unbounded: Vec<usize>,
+}
+
+#[derive(Clone)]
+enum BvhNode {
+    Leaf {
+        bound: AABB,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bound: AABB,
+        /// the left child is always stored directly after its parent
+        right: usize,
+        /// axis the children were split on
+        axis: usize,
+    },
+}
+
+impl BvhNode {
+    fn bound(&self) -> AABB {
+        match self {
+            BvhNode::Leaf { bound, .. }     => *bound,
+            BvhNode::Interior { bound, .. } => *bound,
+        }
+    }
+}
+
+/// bound of an item, along with the centroid used to partition it
+struct BuildItem {
+    index: usize,
+    bound: AABB,
+    centroid: Vec3,
+}
+
+impl Bvh {
+    const MAX_LEAF_SIZE: usize = 4;
+    const BUCKETS: usize = 12;
+
+    pub fn new<T: Boundable>(items: &[T]) -> Bvh {
+        let mut bvh = Bvh::default();
+        bvh.rebuild(items);
+
+        bvh
+    }
+
+    /// rebuild the hierarchy from scratch, required after items are added or removed
+    pub fn rebuild<T: Boundable>(&mut self, items: &[T]) {
+        self.nodes.clear();
+        self.indices.clear();
+        self.unbounded.clear();
+
+        let mut build_items = vec![];
+
+        for (index, item) in items.iter().enumerate() {
+            let bound = item.bound();
+
+            if bound.is_finite() {
+                build_items.push(BuildItem { index, bound, centroid: bound.centroid() });
+            } else {
+                self.unbounded.push(index);
+            }
+        }
+
+        if !build_items.is_empty() {
+            self.build_node(&mut build_items, 0);
+        }
+
+        self.indices = build_items.iter().map(|item| item.index).collect();
+    }
+
+    /// recompute the bounds of every node without changing the structure of the
+    /// hierarchy. Cheaper than `Bvh::rebuild`, but only valid when the number
+    /// of items is unchanged. Traversal quality degrades if items move far.
+    pub fn refit<T: Boundable>(&mut self, items: &[T]) {
+        // children are always stored after their parents
+        for i in (0..self.nodes.len()).rev() {
+            let new_bound = match self.nodes[i] {
+                BvhNode::Leaf { start, count, .. } => {
+                    self.indices[start..start + count]
+                        .iter()
+                        .fold(AABB::empty(), |acc, index| AABB::surrounding(acc, items[*index].bound()))
+                }
+                BvhNode::Interior { right, .. } => {
+                    AABB::surrounding(self.nodes[i + 1].bound(), self.nodes[right].bound())
+                }
+            };
+
+            match &mut self.nodes[i] {
+                BvhNode::Leaf { bound, .. }     => *bound = new_bound,
+                BvhNode::Interior { bound, .. } => *bound = new_bound,
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.unbounded.is_empty()
+    }
+
+    /// bound of all the bounded items
+    pub fn bound(&self) -> AABB {
+        match self.nodes.first() {
+            Some(node) => node.bound(),
+            None       => AABB::empty(),
+        }
+    }
+
+    /// find the closest collision along the ray. `collide` is called with the
+    /// index of every item whose bound the ray might pass through
+    pub fn collide<F>(&self, ray: Ray, mut collide: F) -> Option<(usize, CollisionRecord)>
+    where
+        F: FnMut(usize) -> Option<CollisionRecord>
+    {
+        let mut closest: Option<(usize, CollisionRecord)> = None;
+        let mut c_t = f64::MAX;
+
+        let mut check = |index: usize, c_t: &mut f64, closest: &mut Option<(usize, CollisionRecord)>| {
+            if let Some(record) = collide(index) {
+                if MIN_INTERSECTION_T < record.t && record.t < *c_t {
+                    *c_t = record.t;
+                    *closest = Some((index, record));
+                }
+            }
+        };
+
+        for index in &self.unbounded {
+            check(*index, &mut c_t, &mut closest);
+        }
+
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let mut stack = vec![0usize];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            if !node.bound().hit(ray, MIN_INTERSECTION_T, c_t) {
+                continue;
+            }
+
+            match node {
+                BvhNode::Leaf { start, count, .. } => {
+                    for index in &self.indices[*start..*start + *count] {
+                        check(*index, &mut c_t, &mut closest);
+                    }
+                }
+                BvhNode::Interior { right, axis, .. } => {
+                    // visit the child closest to the ray's origin first, so the
+                    // other child is more likely to be culled
+                    if ray.direction[*axis] < 0.0 {
+                        stack.push(node_index + 1);
+                        stack.push(*right);
+                    } else {
+                        stack.push(*right);
+                        stack.push(node_index + 1);
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// recursively partition the items, returns the index of the created node.
+    /// `offset` is the position of the items in the final index list
+    fn build_node(&mut self, items: &mut [BuildItem], offset: usize) -> usize {
+        let bound = items
+            .iter()
+            .fold(AABB::empty(), |acc, item| AABB::surrounding(acc, item.bound));
+        let node_index = self.nodes.len();
+
+        if items.len() <= Bvh::MAX_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf { bound, start: offset, count: items.len() });
+            return node_index;
+        }
+
+        let centroid_bound = items.iter().fold(AABB::empty(), |acc, item| {
+            AABB::surrounding(acc, AABB::new(item.centroid, item.centroid))
+        });
+        let extent = centroid_bound.max() - centroid_bound.min();
+        let axis = if extent.x > extent.y && extent.x > extent.z { 0 }
+                   else if extent.y > extent.z { 1 }
+                   else { 2 };
+
+        // all centroids coincide, there is no meaningful way to split them
+        if extent[axis] <= 0.0 {
+            self.nodes.push(BvhNode::Leaf { bound, start: offset, count: items.len() });
+            return node_index;
+        }
+
+        let mid = match Bvh::sah_split(items, axis, centroid_bound, bound) {
+            Some(mid) => mid,
+            None => {
+                self.nodes.push(BvhNode::Leaf { bound, start: offset, count: items.len() });
+                return node_index;
+            }
+        };
+
+        // reserve the interior node, the children are filled in below
+        self.nodes.push(BvhNode::Interior { bound, right: 0, axis });
+
+        let (left, right) = items.split_at_mut(mid);
+        self.build_node(left, offset);
+        let right_index = self.build_node(right, offset + mid);
+
+        self.nodes[node_index] = BvhNode::Interior { bound, right: right_index, axis };
+
+        node_index
+    }
+
+    /// Partition the items along the axis using the surface area heuristic.
+    /// Returns the index of the first item in the right partition, or None
+    /// if a leaf is cheaper than any split.
+    fn sah_split(items: &mut [BuildItem], axis: usize, centroid_bound: AABB, bound: AABB) -> Option<usize> {
+        let min = centroid_bound.min()[axis];
+        let extent = centroid_bound.max()[axis] - min;
+
+        let bucket_of = |item: &BuildItem| {
+            let b = ((item.centroid[axis] - min) / extent * Bvh::BUCKETS as f64) as usize;
+            usize::min(b, Bvh::BUCKETS - 1)
+        };
+
+        let mut counts = [0usize; Bvh::BUCKETS];
+        let mut bounds = [AABB::empty(); Bvh::BUCKETS];
+
+        for item in items.iter() {
+            let b = bucket_of(item);
+            counts[b] += 1;
+            bounds[b] = AABB::surrounding(bounds[b], item.bound);
+        }
+
+        // cost of splitting after each bucket, relative to the cost of an intersection test
+        let mut best: Option<(usize, f64)> = None;
+
+        for split in 0..Bvh::BUCKETS - 1 {
+            let (mut left_bound, mut right_bound) = (AABB::empty(), AABB::empty());
+            let (mut left_count, mut right_count) = (0, 0);
+
+            for b in 0..=split {
+                left_bound = AABB::surrounding(left_bound, bounds[b]);
+                left_count += counts[b];
+            }
+            for b in split + 1..Bvh::BUCKETS {
+                right_bound = AABB::surrounding(right_bound, bounds[b]);
+                right_count += counts[b];
+            }
+
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+
+            let cost = 0.125 + (
+                left_count as f64 * left_bound.surface_area() +
+                right_count as f64 * right_bound.surface_area()
+            ) / f64::max(bound.surface_area(), f64::EPSILON);
+
+            match best {
+                Some((_, best_cost)) if best_cost <= cost => {}
+                _ => best = Some((split, cost)),
+            }
+        }
+
+        let (split, cost) = best?;
+
+        if items.len() <= Bvh::MAX_LEAF_SIZE * 4 && cost >= items.len() as f64 {
+            return None;
+        }
+
+        items.sort_by_key(|item| bucket_of(item) > split);
+
+        Some(items.iter().position(|item| bucket_of(item) > split).unwrap_or(items.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::Color;
+    use crate::material::{Lambertian, MaterialType};
+    use crate::ray::Ray;
+    use crate::scene::{Element, Scene};
+    use crate::shape::{Plane, ShapeType, Sphere};
+    use crate::utils::{random_range, random_unit_vector, seed_random};
+    use crate::vec3::Vec3;
+
+    fn random_point(size: f64) -> Vec3 {
+        Vec3::new(random_range(-size, size), random_range(-size, size), random_range(-size, size))
+    }
+
+    fn element(shape: ShapeType) -> Element {
+        Element::new(MaterialType::Lambertian(Lambertian::new(Color::white().into())), shape)
+    }
+
+    /// random spheres above a floor plane, which has no finite bound
+    fn random_scene(count: usize) -> Scene {
+        let mut elements: Vec<Element> = (0..count)
+            .map(|_| element(ShapeType::Sphere(Sphere::new(random_point(10.0), random_range(0.1, 1.5)))))
+            .collect();
+        elements.push(element(ShapeType::Plane(Plane::new(Vec3::new(0.0, -8.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).unwrap())));
+
+        Scene::new(elements)
+    }
+
+    /// checks that the bvh finds the same element at the same distance as
+    /// checking every element
+    fn assert_matches_linear(scene: &Scene, expected: &Scene, rays: usize) {
+        for _ in 0..rays {
+            let ray = Ray::new(random_point(12.0), random_unit_vector());
+
+            let found = scene.collide(ray).map(|(e, r)| (e.id, r.t));
+            let linear = expected.collide_linear(ray, |_| true).map(|(e, r)| (e.id, r.t));
+
+            assert_eq!(found, linear);
+        }
+    }
+
+    #[test]
+    fn matches_linear_search() {
+        seed_random(11);
+        let scene = random_scene(200);
+
+        assert_matches_linear(&scene, &scene, 2000);
+    }
+
+    #[test]
+    fn hits_unbounded_elements() {
+        seed_random(12);
+        let scene = random_scene(50);
+
+        let down = Ray::new(Vec3::new(100.0, 0.0, 100.0), Vec3::new(0.0, -1.0, 0.0));
+        let (element, record) = scene.collide(down).unwrap();
+
+        assert!(matches!(element.shape, ShapeType::Plane(_)));
+        assert!((record.t - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn refit_and_rebuild_after_edits() {
+        seed_random(13);
+        let mut refit = random_scene(100);
+        let ids: Vec<_> = refit.objects.iter().map(|e| e.id).collect();
+
+        for id in ids.iter().step_by(3) {
+            let moved = ShapeType::Sphere(Sphere::new(random_point(10.0), random_range(0.1, 1.5)));
+            refit.get_element_mut(*id).set_shape(moved);
+        }
+
+        let mut rebuilt = refit.clone();
+        refit.refit();
+        rebuilt.rebuild();
+        let fresh = Scene::new(refit.objects.clone());
+
+        assert_matches_linear(&refit, &fresh, 1000);
+        assert_matches_linear(&rebuilt, &fresh, 1000);
+        assert_matches_linear(&fresh, &fresh, 1000);
+    }
+}
@@ -1,6 +1,13 @@
+use std::f64::consts::PI;
+
 use crate::vec3::Vec3;
 use crate::ray::Ray;
+use crate::utils::random_float;
+
+use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct CameraConfig {
     /// aspect ratio (horizontal / vertical)
     pub aspect: f64,
@@ -14,6 +21,81 @@ pub struct CameraConfig {
     pub focus_dist: f64,
     /// vertical field of view in degrees
     pub vertical_fov_degrees: f64,
+    /// radius of the lens, objects away from the focus distance are
+    /// blurred more as it grows. Zero for a pinhole camera
+    pub aperture: f64,
+    /// shape of the lens' opening, which out of focus highlights take on
+    pub bokeh: BokehShape,
+    pub projection: Projection,
+    /// rays are cast at random times between the shutter opening and
+    /// closing, blurring shapes that move in between
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+/// How points on the image are mapped to rays
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum Projection {
+    /// pinhole camera, with the field of view `vertical_fov_degrees`
+    #[default]
+    Perspective,
+    /// parallel rays, `viewport_height` is the height of the view in world units
+    Orthographic {
+        viewport_height: f64,
+    },
+    /// equidistant fisheye, the angle from the view direction grows linearly
+    /// with the distance from the image's center. `fov_degrees` is the field
+    /// of view across the image's vertical axis
+    Fisheye {
+        fov_degrees: f64,
+    },
+    /// full 360 degree panorama, the image's horizontal axis maps to
+    /// longitude and the vertical axis to latitude
+    Equirectangular,
+}
+
+/// Shape of the aperture
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum BokehShape {
+    #[default]
+    Circular,
+    /// regular polygon formed by `blades` aperture blades
+    Polygonal {
+        blades: u32,
+        rotation_degrees: f64,
+    },
+}
+
+impl BokehShape {
+    /// uniformly sample a point on the unit sized aperture
+    fn sample(&self) -> (f64, f64) {
+        match *self {
+            BokehShape::Polygonal { blades, rotation_degrees } if blades >= 3 => {
+                // pick one of the (equally sized) triangles between the center
+                // and the polygon's edges, then a point inside of it
+                let wedge = 2.0 * PI / blades as f64;
+                let blade = u32::min((random_float() * blades as f64) as u32, blades - 1);
+                let theta0 = rotation_degrees.to_radians() + blade as f64 * wedge;
+                let theta1 = theta0 + wedge;
+
+                let r1 = random_float().sqrt();
+                let r2 = random_float();
+                let (a, b) = (r1 * (1.0 - r2), r1 * r2);
+
+                (
+                    a * theta0.cos() + b * theta1.cos(),
+                    a * theta0.sin() + b * theta1.sin(),
+                )
+            }
+            // fewer than 3 blades can't enclose an opening, use a circle
+            _ => {
+                let r = random_float().sqrt();
+                let theta = 2.0 * PI * random_float();
+
+                (r * theta.cos(), r * theta.sin())
+            }
+        }
+    }
 }
 
 impl Default for CameraConfig {
@@ -26,6 +108,11 @@ impl Default for CameraConfig {
             world_up: Vec3::new(0.0, 1.0, 0.0),
             focus_dist: 10.0,
             vertical_fov_degrees: 25.0,
+            aperture: 0.0,
+            bokeh: BokehShape::Circular,
+            projection: Projection::Perspective,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 }
@@ -37,6 +124,17 @@ pub struct Camera {
     horizontal: Vec3,
     vertical: Vec3,
     lower_left: Vec3,
+    /// horizontal and vertical unit vectors, spanning the lens
+    u: Vec3,
+    v: Vec3,
+    /// unit vector pointing away from the view direction
+    w: Vec3,
+    focus_dist: f64,
+    lens_radius: f64,
+    bokeh: BokehShape,
+    projection: Projection,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -68,6 +166,15 @@ impl Camera {
             horizontal,
             vertical,
             lower_left,
+            u,
+            v,
+            w: target,
+            focus_dist: cfg.focus_dist,
+            lens_radius: cfg.aperture,
+            bokeh: cfg.bokeh,
+            projection: cfg.projection,
+            shutter_open: cfg.shutter_open,
+            shutter_close: cfg.shutter_close,
         }
     }
 
@@ -78,14 +185,78 @@ impl Camera {
     pub fn aspect(&self) -> f64 { self.aspect }
     pub fn origin(&self) -> Vec3 {self.origin }
 
+    /// ray through the point of the image at the offsets, which range from
+    /// 0 to 1 starting in the lower left corner
     pub fn create_ray(&self, h_offset: f64, v_offset: f64) -> Ray {
-        Ray::new(
-            self.origin, 
-            self.lower_left
-            + self.horizontal * h_offset
-            + self.vertical * v_offset
-            - self.origin
-        )
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => (
+                self.origin,
+                self.lower_left
+                + self.horizontal * h_offset
+                + self.vertical * v_offset
+                - self.origin
+            ),
+            Projection::Orthographic { viewport_height } => {
+                let viewport_width = viewport_height * self.aspect;
+                let origin = self.origin
+                    + self.u * ((h_offset - 0.5) * viewport_width)
+                    + self.v * ((v_offset - 0.5) * viewport_height);
+
+                (origin, -self.w)
+            }
+            Projection::Fisheye { fov_degrees } => {
+                let x = (h_offset - 0.5) * self.aspect;
+                let y = v_offset - 0.5;
+                let r = (x * x + y * y).sqrt();
+
+                // angle from the view direction
+                let theta = f64::min(r * fov_degrees.to_radians(), PI);
+                let phi = y.atan2(x);
+
+                let direction = self.u * (theta.sin() * phi.cos())
+                    + self.v * (theta.sin() * phi.sin())
+                    - self.w * theta.cos();
+
+                (self.origin, direction)
+            }
+            Projection::Equirectangular => {
+                let longitude = (h_offset - 0.5) * 2.0 * PI;
+                let latitude = (v_offset - 0.5) * PI;
+
+                let direction = self.u * (latitude.cos() * longitude.sin())
+                    + self.v * latitude.sin()
+                    - self.w * (latitude.cos() * longitude.cos());
+
+                (self.origin, direction)
+            }
+        };
+
+        let time = self.shutter_open + (self.shutter_close - self.shutter_open) * random_float();
+
+        self.through_lens(origin, direction, time)
+    }
+
+    /// Move the ray's origin to a random point on the lens, while still passing
+    /// through the same point at the focus distance. Perspective and orthographic
+    /// cameras focus on a plane, the others on a sphere around the camera
+    fn through_lens(&self, origin: Vec3, direction: Vec3, time: f64) -> Ray {
+        if self.lens_radius <= 0.0 {
+            return Ray::at_time(origin, direction, time);
+        }
+
+        let direction = direction.normalize();
+        let focus_t = match self.projection {
+            Projection::Perspective | Projection::Orthographic { .. } => {
+                self.focus_dist / Vec3::dot(direction, -self.w)
+            }
+            _ => self.focus_dist,
+        };
+        let focus = origin + direction * focus_t;
+
+        let (x, y) = self.bokeh.sample();
+        let origin = origin + self.u * (x * self.lens_radius) + self.v * (y * self.lens_radius);
+
+        Ray::at_time(origin, focus - origin, time)
     }
 }
 
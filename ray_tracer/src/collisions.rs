@@ -1,5 +1,9 @@
 use crate::{ray::Ray, shape::UV, vec3::Vec3, ElementId};
 
+/// collisions closer than this are ignored, so that rays leaving a surface
+/// don't immediately collide with it again
+pub const MIN_INTERSECTION_T: f64 = 0.001;
+
 #[derive(Debug, PartialEq)]
 pub enum Face {
     Outer,
@@ -0,0 +1,516 @@
+//! Constructive solid geometry: shapes combined by union, intersection and
+//! difference.
+//!
+//! Solids report every span of a ray's line that lies inside of them, and
+//! the spans of the two children are merged according to the operation.
+//! Closed shapes (spheres, boxes, capped cylinders and cones, capsules, tori,
+//! instances and other CSG shapes) are solids, and so is a plane, which
+//! bounds the half-space behind it. Other shapes enclose no volume, so they
+//! don't contribute to CSG shapes.
+
+use std::sync::Arc;
+
+use crate::aabb::{AABB, Boundable};
+use crate::collisions::{CollisionRecord, Collidable, collision_face, MIN_INTERSECTION_T};
+use crate::instance::Instance;
+use crate::motion::Moving;
+use crate::ray::Ray;
+use crate::shape::{Box, Plane, ShapeType, Sphere, SurfaceNormal, TextureMap, UV};
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// Point where a ray's line crosses the surface of a solid
+#[derive(Copy, Clone)]
+pub struct Crossing {
+    /// may be negative, or infinite for unbounded solids
+    pub t: f64,
+    /// pointing out of the solid
+    pub normal: Vec3,
+    pub uv: UV,
+}
+
+/// Span of a ray's line inside of a solid
+#[derive(Copy, Clone)]
+pub struct Interval {
+    pub enter: Crossing,
+    pub exit: Crossing,
+}
+
+/// Shapes with an inside
+pub trait Solid {
+    /// disjoint spans of the ray's line inside of the solid, in order,
+    /// including the spans behind the ray's origin
+    fn intervals(&self, ray: Ray) -> Vec<Interval>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CsgOperation {
+    /// inside of either shape
+    Union,
+    /// inside of both shapes
+    Intersection,
+    /// inside of the first shape, but not the second
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(&self, inside_left: bool, inside_right: bool) -> bool {
+        match self {
+            CsgOperation::Union        => inside_left || inside_right,
+            CsgOperation::Intersection => inside_left && inside_right,
+            CsgOperation::Difference   => inside_left && !inside_right,
+        }
+    }
+}
+
+/// Two shapes combined by an operation. The children are shared between
+/// clones, like those of an `Instance`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Csg {
+    operation: CsgOperation,
+    left: Arc<ShapeType>,
+    right: Arc<ShapeType>,
+}
+
+impl Csg {
+    pub fn new<L, R>(operation: CsgOperation, left: L, right: R) -> Csg
+    where
+        L: Into<Arc<ShapeType>>,
+        R: Into<Arc<ShapeType>>,
+    {
+        Csg { operation, left: left.into(), right: right.into() }
+    }
+
+    pub fn union<L: Into<Arc<ShapeType>>, R: Into<Arc<ShapeType>>>(left: L, right: R) -> Csg {
+        Csg::new(CsgOperation::Union, left, right)
+    }
+
+    pub fn intersection<L: Into<Arc<ShapeType>>, R: Into<Arc<ShapeType>>>(left: L, right: R) -> Csg {
+        Csg::new(CsgOperation::Intersection, left, right)
+    }
+
+    pub fn difference<L: Into<Arc<ShapeType>>, R: Into<Arc<ShapeType>>>(left: L, right: R) -> Csg {
+        Csg::new(CsgOperation::Difference, left, right)
+    }
+
+    pub fn operation(&self) -> CsgOperation { self.operation }
+}
+
+impl Solid for Csg {
+    fn intervals(&self, ray: Ray) -> Vec<Interval> {
+        let left = self.left.intervals(ray);
+        let right = match (self.operation, left.is_empty()) {
+            // nothing to intersect with, or subtract from
+            (CsgOperation::Intersection | CsgOperation::Difference, true) => return vec![],
+            _ => self.right.intervals(ray),
+        };
+
+        // every crossing of either child, marked with the child it belongs to
+        let mut events = left
+            .iter()
+            .flat_map(|i| [(i.enter, true, true), (i.exit, true, false)])
+            .chain(right.iter().flat_map(|i| [(i.enter, false, true), (i.exit, false, false)]))
+            .collect::<Vec<(Crossing, bool, bool)>>();
+        events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+        let (mut inside_left, mut inside_right) = (false, false);
+        let mut inside = false;
+        let mut enter = None;
+        let mut intervals = vec![];
+
+        for (crossing, is_left, entering) in events {
+            match is_left {
+                true  => inside_left = entering,
+                false => inside_right = entering,
+            }
+
+            if self.operation.contains(inside_left, inside_right) == inside {
+                continue;
+            }
+            inside = !inside;
+
+            // the inside of the subtracted shape is the outside of the result
+            let crossing = match (self.operation, is_left) {
+                (CsgOperation::Difference, false) => Crossing { normal: -crossing.normal, ..crossing },
+                _ => crossing,
+            };
+
+            match enter.take() {
+                None        => enter = Some(crossing),
+                Some(enter) => intervals.push(Interval { enter, exit: crossing }),
+            }
+        }
+
+        intervals
+    }
+}
+
+impl Collidable for Csg {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let crossing = self
+            .intervals(ray)
+            .into_iter()
+            .flat_map(|i| [i.enter, i.exit])
+            .find(|c| c.t > MIN_INTERSECTION_T && c.t.is_finite())?;
+
+        Some(CollisionRecord {
+            point: ray.position_at(crossing.t),
+            s_normal: crossing.normal,
+            t: crossing.t,
+            uv: crossing.uv,
+            // entering the solid hits its outside, leaving it hits the inside
+            face: collision_face(ray.direction, crossing.normal),
+        })
+    }
+}
+
+impl Boundable for Csg {
+    fn bound(&self) -> AABB {
+        let (left, right) = (self.left.bound(), self.right.bound());
+
+        match self.operation {
+            CsgOperation::Union        => AABB::surrounding(left, right),
+            CsgOperation::Intersection => overlap(left, right).unwrap_or(left),
+            CsgOperation::Difference   => left,
+        }
+    }
+}
+
+/// the box shared by two boxes, None if they don't overlap
+fn overlap(a: AABB, b: AABB) -> Option<AABB> {
+    let min = Vec3::new(
+        f64::max(a.min().x, b.min().x),
+        f64::max(a.min().y, b.min().y),
+        f64::max(a.min().z, b.min().z),
+    );
+    let max = Vec3::new(
+        f64::min(a.max().x, b.max().x),
+        f64::min(a.max().y, b.max().y),
+        f64::min(a.max().z, b.max().z),
+    );
+
+    if min.x > max.x || min.y > max.y || min.z > max.z {
+        return None;
+    }
+
+    Some(AABB::new(min, max))
+}
+
+impl From<Csg> for ShapeType {
+    fn from(csg: Csg) -> Self {
+        ShapeType::Csg(csg)
+    }
+}
+
+impl ShapeType {
+    /// whether the shape encloses a volume, and can be combined by `Csg`
+    pub fn is_solid(&self) -> bool {
+        match self {
+            ShapeType::Sphere(_) | ShapeType::Box(_) | ShapeType::Plane(_) |
+            ShapeType::Capsule(_) | ShapeType::Torus(_) | ShapeType::Csg(_) => true,
+            ShapeType::Cylinder(c) => c.is_capped(),
+            ShapeType::Cone(c)     => c.is_capped(),
+            ShapeType::Instance(i) => i.shape().is_solid(),
+            ShapeType::Moving(m)   => m.shape().is_solid(),
+            _ => false,
+        }
+    }
+}
+
+impl Solid for ShapeType {
+    fn intervals(&self, ray: Ray) -> Vec<Interval> {
+        if !self.is_solid() {
+            return vec![];
+        }
+
+        match self {
+            ShapeType::Sphere(s)   => s.intervals(ray),
+            ShapeType::Box(s)      => s.intervals(ray),
+            ShapeType::Plane(s)    => s.intervals(ray),
+            ShapeType::Cylinder(s) => pair_crossings(s, ray, s.crossings(ray)),
+            ShapeType::Cone(s)     => pair_crossings(s, ray, s.crossings(ray)),
+            ShapeType::Capsule(s)  => pair_crossings(s, ray, s.crossings(ray)),
+            ShapeType::Torus(s)    => pair_crossings(s, ray, s.crossings(ray)),
+            ShapeType::Instance(s) => s.intervals(ray),
+            ShapeType::Moving(s)   => s.intervals(ray),
+            ShapeType::Csg(s)      => s.intervals(ray),
+            _ => vec![],
+        }
+    }
+}
+
+/// roots of a shape's polynomial closer together than this are the same root,
+/// found more than once
+const SAME_ROOT: f64 = 1e-6;
+
+/// Intervals of a closed surface: along a line, crossings alternate between
+/// entering and leaving
+fn pair_crossings<S: SurfaceNormal + TextureMap>(shape: &S, ray: Ray, mut times: Vec<f64>) -> Vec<Interval> {
+    times.sort_by(|a, b| a.total_cmp(b));
+
+    let crossing = |t: f64| {
+        let point = ray.position_at(t);
+        Crossing { t, normal: shape.surface_normal(point), uv: shape.map(point) }
+    };
+
+    // the solvers find a root as many times as its multiplicity. A root found
+    // an even number of times is where the line touches the surface without
+    // crossing it (e.g. tangent to a torus), so it isn't a crossing
+    let mut crossings = vec![];
+    let mut start = 0;
+    while start < times.len() {
+        let mut end = start + 1;
+        while end < times.len() && times[end] - times[end - 1] < SAME_ROOT {
+            end += 1;
+        }
+
+        if (end - start) % 2 == 1 {
+            crossings.push(times[start..end].iter().sum::<f64>() / (end - start) as f64);
+        }
+        start = end;
+    }
+
+    crossings
+        .chunks_exact(2)
+        .map(|pair| Interval { enter: crossing(pair[0]), exit: crossing(pair[1]) })
+        .collect()
+}
+
+impl Solid for Sphere {
+    fn intervals(&self, ray: Ray) -> Vec<Interval> {
+        let crossing = |t: f64| {
+            let normal = self.surface_normal(ray.position_at(t));
+            Crossing { t, normal, uv: self.map(normal) }
+        };
+
+        match self.intersections(ray) {
+            Some((enter, exit)) => vec![Interval { enter: crossing(enter), exit: crossing(exit) }],
+            None => vec![],
+        }
+    }
+}
+
+impl Solid for Box {
+    /// slab method, keeping track of the sides the ray enters and leaves through
+    fn intervals(&self, ray: Ray) -> Vec<Interval> {
+        let bound = self.bound();
+        let (min, max) = (bound.min(), bound.max());
+
+        let mut enter = (f64::NEG_INFINITY, 0);
+        let mut exit = (f64::INFINITY, 0);
+
+        for i in 0..3 {
+            if ray.direction[i] == 0.0 {
+                if ray.origin[i] < min[i] || max[i] < ray.origin[i] {
+                    return vec![];
+                }
+                continue;
+            }
+
+            let t0 = (min[i] - ray.origin[i]) / ray.direction[i];
+            let t1 = (max[i] - ray.origin[i]) / ray.direction[i];
+
+            if f64::min(t0, t1) > enter.0 { enter = (f64::min(t0, t1), i); }
+            if f64::max(t0, t1) < exit.0  { exit = (f64::max(t0, t1), i); }
+        }
+
+        if enter.0 > exit.0 {
+            return vec![];
+        }
+
+        let crossing = |(t, axis): (f64, usize), sign: f64| {
+            let mut normal = [0.0; 3];
+            normal[axis] = sign * ray.direction[axis].signum();
+
+            // position on the side, along the two other axes
+            let point = ray.position_at(t);
+            let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+            let uv = UV::new(
+                (point[a] - min[a]) / (max[a] - min[a]),
+                (point[b] - min[b]) / (max[b] - min[b]),
+            );
+
+            Crossing { t, normal: Vec3::new(normal[0], normal[1], normal[2]), uv }
+        };
+
+        vec![Interval { enter: crossing(enter, -1.0), exit: crossing(exit, 1.0) }]
+    }
+}
+
+impl Solid for Plane {
+    /// the half-space behind the plane
+    fn intervals(&self, ray: Ray) -> Vec<Interval> {
+        let normal = self.surface_normal(self.point());
+        let denominator = Vec3::dot(normal, ray.direction);
+        let crossing = |t: f64| Crossing { t, normal, uv: self.map(ray.position_at(t)) };
+        let unbounded = |t: f64| Crossing { t, normal, uv: UV::new(0.0, 0.0) };
+
+        if denominator.abs() < 1e-12 {
+            // parallel, either always behind the plane or never
+            return match Vec3::dot(normal, ray.origin - self.point()) < 0.0 {
+                true  => vec![Interval { enter: unbounded(f64::NEG_INFINITY), exit: unbounded(f64::INFINITY) }],
+                false => vec![],
+            };
+        }
+
+        let t = Vec3::dot(normal, self.point() - ray.origin) / denominator;
+
+        match denominator < 0.0 {
+            true  => vec![Interval { enter: crossing(t), exit: unbounded(f64::INFINITY) }],
+            false => vec![Interval { enter: unbounded(f64::NEG_INFINITY), exit: crossing(t) }],
+        }
+    }
+}
+
+impl Solid for Instance {
+    fn intervals(&self, ray: Ray) -> Vec<Interval> {
+        let transform = self.transform();
+        let local_ray = Ray::at_time(
+            transform.inverse_point(ray.origin),
+            transform.inverse_vector(ray.direction),
+            ray.time,
+        );
+
+        let crossing = |c: Crossing| {
+            // distances aren't preserved by scaling, so t is measured again
+            let t = match c.t.is_finite() {
+                true  => Vec3::dot(transform.point(local_ray.position_at(c.t)) - ray.origin, ray.direction),
+                false => c.t,
+            };
+
+            Crossing { t, normal: transform.normal(c.normal), uv: c.uv }
+        };
+
+        self.shape()
+            .intervals(local_ray)
+            .into_iter()
+            .map(|i| Interval { enter: crossing(i.enter), exit: crossing(i.exit) })
+            .collect()
+    }
+}
+
+impl Solid for Moving {
+    fn intervals(&self, ray: Ray) -> Vec<Interval> {
+        let offset = self.offset_at(ray.time);
+
+        self.shape().intervals(Ray::at_time(ray.origin - offset, ray.direction, ray.time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::Face;
+    use crate::primitives::Torus;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    /// unit spheres around the origin and (1, 0, 0)
+    fn spheres() -> (ShapeType, ShapeType) {
+        (
+            ShapeType::Sphere(Sphere::new(Vec3::zeros(), 1.0)),
+            ShapeType::Sphere(Sphere::new(Vec3::new(1.0, 0.0, 0.0), 1.0)),
+        )
+    }
+
+    /// along x, through the centers of both spheres
+    fn ray_from(x: f64) -> Ray {
+        Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    /// times and normals of where the intervals are entered and left
+    fn spans(csg: &Csg, ray: Ray) -> Vec<(f64, f64, Vec3, Vec3)> {
+        csg.intervals(ray)
+            .into_iter()
+            .map(|i| (i.enter.t, i.exit.t, i.enter.normal, i.exit.normal))
+            .collect()
+    }
+
+    #[test]
+    fn union_spans_both_spheres() {
+        let (a, b) = spheres();
+        let csg = Csg::union(a, b);
+
+        let spans = spans(&csg, ray_from(-5.0));
+        assert_eq!(spans.len(), 1);
+        let (enter, exit, enter_normal, exit_normal) = spans[0];
+        assert!((enter - 4.0).abs() < 1e-9 && (exit - 7.0).abs() < 1e-9);
+        assert!(close(enter_normal, Vec3::new(-1.0, 0.0, 0.0)));
+        assert!(close(exit_normal, Vec3::new(1.0, 0.0, 0.0)));
+
+        let record = csg.collide(ray_from(-5.0)).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-9);
+        assert!(record.face == Face::Outer);
+    }
+
+    #[test]
+    fn intersection_spans_the_overlap() {
+        let (a, b) = spheres();
+        let csg = Csg::intersection(a, b);
+
+        let spans = spans(&csg, ray_from(-5.0));
+        assert_eq!(spans.len(), 1);
+        let (enter, exit, enter_normal, exit_normal) = spans[0];
+        // enters through the second sphere, leaves through the first
+        assert!((enter - 5.0).abs() < 1e-9 && (exit - 6.0).abs() < 1e-9);
+        assert!(close(enter_normal, Vec3::new(-1.0, 0.0, 0.0)));
+        assert!(close(exit_normal, Vec3::new(1.0, 0.0, 0.0)));
+
+        let record = csg.collide(ray_from(-5.0)).unwrap();
+        assert!((record.t - 5.0).abs() < 1e-9);
+        assert!(record.face == Face::Outer);
+
+        // the spheres don't overlap off of the x axis
+        let miss = Ray::new(Vec3::new(-5.0, 0.95, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(csg.collide(miss).is_none());
+    }
+
+    #[test]
+    fn difference_flips_the_normals_of_the_subtracted_shape() {
+        let (a, b) = spheres();
+        let csg = Csg::difference(a, b);
+
+        let spans = spans(&csg, ray_from(-5.0));
+        assert_eq!(spans.len(), 1);
+        let (enter, exit, enter_normal, exit_normal) = spans[0];
+        assert!((enter - 4.0).abs() < 1e-9 && (exit - 5.0).abs() < 1e-9);
+        assert!(close(enter_normal, Vec3::new(-1.0, 0.0, 0.0)));
+        // the second sphere's surface, facing out of the result
+        assert!(close(exit_normal, Vec3::new(1.0, 0.0, 0.0)));
+
+        // from inside of the result, the hole's surface is hit from the inside
+        let record = csg.collide(ray_from(-0.5)).unwrap();
+        assert!((record.t - 0.5).abs() < 1e-9);
+        assert!(close(record.s_normal, Vec3::new(1.0, 0.0, 0.0)));
+        assert!(record.face == Face::Inner);
+
+        // the other way around, the first sphere's surface is hit from outside
+        let (a, b) = spheres();
+        let record = Csg::difference(b, a).collide(ray_from(-5.0)).unwrap();
+        assert!((record.t - 6.0).abs() < 1e-9);
+        assert!(close(record.s_normal, Vec3::new(-1.0, 0.0, 0.0)));
+        assert!(record.face == Face::Outer);
+    }
+
+    #[test]
+    fn tangent_lines_dont_enter_a_torus() {
+        let torus = ShapeType::Torus(Torus::new(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0), 2.0, 0.5));
+
+        // grazes the top of the tube on both sides of the hole
+        let tangent = Ray::new(Vec3::new(-5.0, 0.0, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(torus.intervals(tangent).is_empty());
+
+        let through = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let times = torus
+            .intervals(through)
+            .into_iter()
+            .map(|i| (i.enter.t, i.exit.t))
+            .collect::<Vec<(f64, f64)>>();
+        assert_eq!(times.len(), 2);
+        assert!((times[0].0 - 2.5).abs() < 1e-6 && (times[0].1 - 3.5).abs() < 1e-6);
+        assert!((times[1].0 - 6.5).abs() < 1e-6 && (times[1].1 - 7.5).abs() < 1e-6);
+    }
+}
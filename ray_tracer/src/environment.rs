@@ -0,0 +1,300 @@
+//! Light arriving from infinitely far away, seen by rays that leave the scene.
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::image::Color;
+use crate::image_texture::{decode, ImageSource, TextureError};
+use crate::sky::Sky;
+use crate::utils::random_float;
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Background {
+    Color(Color),
+    /// blend between two colors, based on the height of the direction
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+    /// equirectangular environment map
+    Map(EnvironmentMap),
+    /// daylight sky and sun
+    Sky(Sky),
+}
+
+impl Default for Background {
+    fn default() -> Self { Background::Color(Color::white()) }
+}
+
+impl From<Color> for Background {
+    fn from(color: Color) -> Self { Background::Color(color) }
+}
+
+/// Direction sampled towards the background
+pub struct EnvironmentSample {
+    pub direction: Vec3,
+    pub radiance: Color,
+    /// density of the direction, with respect to solid angle
+    pub pdf: f64,
+}
+
+impl Background {
+    /// light arriving from the (unit) direction
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.y + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+            Background::Map(map) => map.radiance(direction),
+            Background::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// whether the background can be sampled directly, uniform backgrounds are
+    /// found just as well by following the paths scattered by materials
+    pub fn is_samplable(&self) -> bool {
+        matches!(self, Background::Map(_) | Background::Sky(_))
+    }
+
+    pub fn sample(&self) -> Option<EnvironmentSample> {
+        match self {
+            Background::Map(map) => map.sample(),
+            Background::Sky(sky) => sky.sample(),
+            _ => None,
+        }
+    }
+
+    /// density of `sample` producing the direction
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        match self {
+            Background::Map(map) => map.pdf(direction),
+            Background::Sky(sky) => sky.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Piecewise constant distribution over a list of weights
+struct Distribution {
+    /// cdf[i] is the sum of the weights before i, normalized
+    cdf: Vec<f64>,
+    total: f64,
+}
+
+impl Distribution {
+    fn new(weights: &[f64]) -> Distribution {
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        let mut total = 0.0;
+
+        cdf.push(0.0);
+        for weight in weights {
+            total += weight;
+            cdf.push(total);
+        }
+
+        if total > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= total);
+        }
+
+        Distribution { cdf, total }
+    }
+
+    /// pick an index with probability proportional to its weight
+    fn sample(&self, u: f64) -> usize {
+        // the last entry whose cdf is at most u, skipping zero weights
+        let i = self.cdf.partition_point(|c| *c <= u);
+        usize::min(i.max(1), self.cdf.len() - 1) - 1
+    }
+
+    /// probability of `sample` picking the index
+    fn probability(&self, i: usize) -> f64 {
+        self.cdf[i + 1] - self.cdf[i]
+    }
+}
+
+/// Environment map lookup and importance sampling data
+struct EnvironmentData {
+    width: usize,
+    height: usize,
+    /// starting with the top row
+    colors: Vec<Color>,
+    rows: Distribution,
+    columns: Vec<Distribution>,
+}
+
+/// Equirectangular image surrounding the scene. The center of the image is
+/// seen when looking towards -z, before `rotation_degrees` (around +y) is
+/// applied. Directions are sampled proportionally to the map's brightness.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "DeserializeEnvironmentMap")]
+pub struct EnvironmentMap {
+    source: ImageSource,
+    rotation_degrees: f64,
+    intensity: f64,
+    #[serde(skip)]
+    data: Arc<EnvironmentData>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeserializeEnvironmentMap {
+    source: ImageSource,
+    #[serde(default)]
+    rotation_degrees: f64,
+    #[serde(default = "default_intensity")]
+    intensity: f64,
+}
+
+fn default_intensity() -> f64 { 1.0 }
+
+impl TryFrom<DeserializeEnvironmentMap> for EnvironmentMap {
+    type Error = TextureError;
+
+    fn try_from(serialized: DeserializeEnvironmentMap) -> Result<EnvironmentMap, TextureError> {
+        EnvironmentMap::new(serialized.source, serialized.rotation_degrees, serialized.intensity)
+    }
+}
+
+impl EnvironmentMap {
+    pub fn new(source: ImageSource, rotation_degrees: f64, intensity: f64) -> Result<EnvironmentMap, TextureError> {
+        let texels = match &source {
+            ImageSource::Path(path) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|error| TextureError::Io { path: path.clone(), error })?;
+                decode(&bytes)?
+            }
+            ImageSource::Data(bytes) => decode(bytes)?,
+        };
+
+        let (width, height) = (texels.width, texels.height);
+
+        // weight pixels by their solid angle, which shrinks towards the poles
+        let columns = texels.colors
+            .chunks_exact(width)
+            .enumerate()
+            .map(|(y, row)| {
+                let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+                let weights = row.iter().map(|c| c.luminance().max(0.0) * sin_theta).collect::<Vec<f64>>();
+
+                Distribution::new(&weights)
+            })
+            .collect::<Vec<Distribution>>();
+        let rows = Distribution::new(&columns.iter().map(|c| c.total).collect::<Vec<f64>>());
+
+        Ok(EnvironmentMap {
+            source,
+            rotation_degrees,
+            intensity,
+            data: Arc::new(EnvironmentData { width, height, colors: texels.colors, rows, columns }),
+        })
+    }
+
+    /// load a Radiance HDR, PNG or PPM image
+    pub fn load<P: Into<std::path::PathBuf>>(path: P) -> Result<EnvironmentMap, TextureError> {
+        EnvironmentMap::new(ImageSource::Path(path.into()), 0.0, 1.0)
+    }
+
+    /// rotate a direction around +y
+    fn rotate(&self, direction: Vec3, degrees: f64) -> Vec3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+
+        Vec3::new(
+            cos * direction.x + sin * direction.z,
+            direction.y,
+            -sin * direction.x + cos * direction.z,
+        )
+    }
+
+    /// coordinates in the image, in [0, 1] starting from the top left
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = self.rotate(direction, -self.rotation_degrees);
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        let phi = d.x.atan2(-d.z);
+
+        (phi / (2.0 * PI) + 0.5, theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = v * PI;
+
+        let d = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+        self.rotate(d, self.rotation_degrees)
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> (usize, usize) {
+        let data = &self.data;
+        let x = usize::min((u * data.width as f64) as usize, data.width - 1);
+        let y = usize::min((v * data.height as f64) as usize, data.height - 1);
+
+        (x, y)
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel(u, v);
+
+        self.data.colors[y * self.data.width + x] * self.intensity
+    }
+
+    pub fn sample(&self) -> Option<EnvironmentSample> {
+        let data = &self.data;
+
+        if data.rows.total <= 0.0 {
+            return None;
+        }
+
+        let y = data.rows.sample(random_float());
+        let x = data.columns[y].sample(random_float());
+
+        // uniform within the pixel
+        let u = (x as f64 + random_float()) / data.width as f64;
+        let v = (y as f64 + random_float()) / data.height as f64;
+
+        let direction = self.uv_to_direction(u, v);
+        let pdf = self.pdf_of_pixel(x, y, v);
+
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(EnvironmentSample {
+            direction,
+            radiance: data.colors[y * data.width + x] * self.intensity,
+            pdf,
+        })
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        if self.data.rows.total <= 0.0 {
+            return 0.0;
+        }
+
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel(u, v);
+
+        self.pdf_of_pixel(x, y, v)
+    }
+
+    /// solid angle density of directions in the pixel, `v` is the vertical
+    /// image coordinate of the direction
+    fn pdf_of_pixel(&self, x: usize, y: usize, v: f64) -> f64 {
+        let data = &self.data;
+        let sin_theta = (v * PI).sin();
+
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // density over the image, converted to density over the sphere
+        let pdf_image = data.rows.probability(y) * data.columns[y].probability(x)
+            * (data.width * data.height) as f64;
+
+        pdf_image / (2.0 * PI * PI * sin_theta)
+    }
+}
@@ -0,0 +1,491 @@
+//! Export of linear radiance, for grading and compositing renders elsewhere.
+//! Radiance files can be read as well, e.g. to light scenes with environment maps.
+//!
+//! Both formats are written without any post processing:
+//! - Radiance `.hdr`: RGBE pixels with run-length encoded scanlines
+//! - OpenEXR `.exr`: uncompressed scanlines of 32-bit float R, G and B channels
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::image::{Color, HdrImage, ImageError};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HdrFormat {
+    Radiance,
+    Exr,
+}
+
+impl HdrFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<HdrFormat> {
+        match path.as_ref().extension()?.to_str()?.to_lowercase().as_str() {
+            "hdr" => Some(HdrFormat::Radiance),
+            "exr" => Some(HdrFormat::Exr),
+            _     => None,
+        }
+    }
+}
+
+impl HdrImage {
+    /// colors of the pixels in a row, rows are numbered from the top
+    fn top_down_row(&self, y: u32) -> impl Iterator<Item = Color> + '_ {
+        let row = self.height() - 1 - y;
+
+        (0..self.width()).map(move |col| self.get_color(row, col))
+    }
+
+    /// write the image as a Radiance HDR (RGBE) file
+    pub fn write_radiance_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+
+        write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height(), self.width())?;
+
+        for y in 0..self.height() {
+            let rgbe = self.top_down_row(y).map(to_rgbe).collect::<Vec<[u8; 4]>>();
+
+            // run-length encoding is only defined for these widths
+            if !(8..0x8000).contains(&self.width()) {
+                for pixel in &rgbe {
+                    writer.write_all(pixel)?;
+                }
+                continue;
+            }
+
+            writer.write_all(&[2, 2, (self.width() >> 8) as u8, (self.width() & 0xff) as u8])?;
+
+            // every channel is encoded separately
+            for channel in 0..4 {
+                let values = rgbe.iter().map(|pixel| pixel[channel]).collect::<Vec<u8>>();
+                write_rle(&mut writer, &values)?;
+            }
+        }
+
+        writer.flush()
+    }
+
+    /// write the image as an uncompressed, single part, scanline OpenEXR file
+    pub fn write_exr_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        let (width, height) = (self.width() as i32, self.height() as i32);
+
+        // magic number and version 2, single part scanline
+        writer.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+
+        // channels are sorted by name, as required by the format
+        let mut channels = vec![];
+        for name in ["B", "G", "R"] {
+            channels.extend(name.as_bytes());
+            channels.push(0);
+            channels.extend(2i32.to_le_bytes()); // 32-bit float
+            channels.extend([0, 0, 0, 0]); // pLinear and reserved
+            channels.extend(1i32.to_le_bytes()); // x sampling
+            channels.extend(1i32.to_le_bytes()); // y sampling
+        }
+        channels.push(0);
+
+        let window = [0, 0, width - 1, height - 1]
+            .iter()
+            .flat_map(|v: &i32| v.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        let mut header = vec![];
+        write_exr_attribute(&mut header, "channels", "chlist", &channels);
+        write_exr_attribute(&mut header, "compression", "compression", &[0]);
+        write_exr_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_exr_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_exr_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        write_exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        write_exr_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+        writer.write_all(&header)?;
+
+        // offset table, one block per scanline
+        let table_start = 8 + header.len() as u64;
+        let table_size = 8 * height as u64;
+        let block_size = 8 + 3 * 4 * width as u64;
+
+        for y in 0..height as u64 {
+            writer.write_all(&(table_start + table_size + y * block_size).to_le_bytes())?;
+        }
+
+        for y in 0..self.height() {
+            writer.write_all(&(y as i32).to_le_bytes())?;
+            writer.write_all(&(3 * 4 * width).to_le_bytes())?;
+
+            let row = self.top_down_row(y).collect::<Vec<Color>>();
+            for channel in [|c: &Color| c.blue, |c: &Color| c.green, |c: &Color| c.red] {
+                for color in &row {
+                    writer.write_all(&(channel(color) as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        writer.flush()
+    }
+
+    /// Read a Radiance HDR file. Only the standard orientation (`-Y height +X width`)
+    /// is supported
+    pub fn read_radiance(bytes: &[u8]) -> Result<HdrImage, ImageError> {
+        let error = |message: &str| ImageError::Decoding { message: message.to_string() };
+
+        if !bytes.starts_with(b"#?") {
+            return Err(error("not a Radiance HDR file"));
+        }
+
+        // the header ends with an empty line, followed by the resolution
+        let header_end = bytes
+            .windows(2)
+            .position(|w| w == b"\n\n")
+            .ok_or_else(|| error("missing end of header"))? + 2;
+        let resolution_end = bytes[header_end..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| error("missing resolution"))? + header_end;
+
+        let header = String::from_utf8_lossy(&bytes[..header_end]);
+        if header.lines().any(|line| line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe") {
+            return Err(error("only RGBE pixels are supported"));
+        }
+
+        let resolution = String::from_utf8_lossy(&bytes[header_end..resolution_end]);
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["-Y", height, "+X", width] => (
+                height.parse::<u32>().map_err(|_| error("invalid height"))?,
+                width.parse::<u32>().map_err(|_| error("invalid width"))?,
+            ),
+            _ => return Err(error("unsupported orientation")),
+        };
+
+        let mut image = HdrImage::new(height, width);
+        let mut data = bytes[resolution_end + 1..].iter().copied();
+        let mut next = || data.next().ok_or_else(|| error("unexpected end of file"));
+
+        for y in 0..height {
+            let mut rgbe = vec![[0u8; 4]; width as usize];
+            let first = [next()?, next()?, next()?, next()?];
+
+            if first[0] == 2 && first[1] == 2 && first[2] < 128 {
+                if ((first[2] as u32) << 8 | first[3] as u32) != width {
+                    return Err(error("scanline width mismatch"));
+                }
+
+                for channel in 0..4 {
+                    let mut x = 0;
+
+                    while x < width as usize {
+                        let count = next()? as usize;
+
+                        if count > 128 {
+                            let value = next()?;
+                            let run = rgbe
+                                .get_mut(x..x + count - 128)
+                                .ok_or_else(|| error("run exceeds scanline"))?;
+
+                            for pixel in run {
+                                pixel[channel] = value;
+                            }
+                            x += count - 128;
+                        } else {
+                            for _ in 0..count {
+                                let value = next()?;
+                                rgbe.get_mut(x).ok_or_else(|| error("run exceeds scanline"))?[channel] = value;
+                                x += 1;
+                            }
+                        }
+                    }
+                }
+            } else {
+                // flat scanline
+                rgbe[0] = first;
+                for pixel in rgbe.iter_mut().skip(1) {
+                    *pixel = [next()?, next()?, next()?, next()?];
+                }
+            }
+
+            for (x, pixel) in rgbe.iter().enumerate() {
+                image.set_color(height - 1 - y, x as u32, from_rgbe(*pixel));
+            }
+        }
+
+        Ok(image)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W, format: HdrFormat) -> Result<(), ImageError> {
+        match format {
+            HdrFormat::Radiance => self.write_radiance_to(writer)?,
+            HdrFormat::Exr      => self.write_exr_to(writer)?,
+        }
+
+        Ok(())
+    }
+
+    /// save the image, in the format given by the file's extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        let path = path.as_ref();
+        let format = HdrFormat::from_path(path)
+            .ok_or_else(|| ImageError::UnknownFormat { path: path.to_path_buf() })?;
+
+        self.save_as(path, format)
+    }
+
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, format: HdrFormat) -> Result<(), ImageError> {
+        let path = path.as_ref();
+        let mut file = File::create(path)
+            .map_err(|error| ImageError::Io { path: Some(path.to_path_buf()), error })?;
+
+        self.write_to(&mut file, format).map_err(|error| match error {
+            ImageError::Io { path: None, error } => ImageError::Io { path: Some(path.to_path_buf()), error },
+            error => error,
+        })
+    }
+}
+
+/// shared exponent encoding of a color
+fn to_rgbe(color: Color) -> [u8; 4] {
+    let (red, green, blue) = (color.red.max(0.0), color.green.max(0.0), color.blue.max(0.0));
+    let max = f64::max(red, f64::max(green, blue));
+
+    if max < 1e-32 || !max.is_finite() {
+        return [0, 0, 0, 0];
+    }
+
+    // max = mantissa * 2^exponent, with mantissa in [0.5, 1)
+    let exponent = i32::min(max.log2().floor() as i32 + 1, 127);
+    let scale = 256.0 / f64::powi(2.0, exponent);
+
+    [
+        f64::min(red * scale, 255.0) as u8,
+        f64::min(green * scale, 255.0) as u8,
+        f64::min(blue * scale, 255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::black();
+    }
+
+    let scale = f64::powi(2.0, rgbe[3] as i32 - 136);
+
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+/// Radiance run-length encoding of one channel of a scanline. Runs are written
+/// as (128 + length, value), anything else as (length, values...)
+fn write_rle<W: Write>(writer: &mut W, values: &[u8]) -> std::io::Result<()> {
+    const MIN_RUN: usize = 4;
+
+    let mut i = 0;
+
+    while i < values.len() {
+        // find the next run long enough to be worth encoding
+        let mut run_start = i;
+        let mut run_length = 0;
+
+        while run_start < values.len() {
+            run_length = values[run_start..]
+                .iter()
+                .take(127)
+                .take_while(|v| **v == values[run_start])
+                .count();
+
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        // the values before the run
+        while i < run_start {
+            let count = usize::min(128, run_start - i);
+            writer.write_all(&[count as u8])?;
+            writer.write_all(&values[i..i + count])?;
+            i += count;
+        }
+
+        if run_length >= MIN_RUN {
+            writer.write_all(&[128 + run_length as u8, values[run_start]])?;
+            i = run_start + run_length;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// image with long runs of one color, short runs, and noisy stretches
+    fn test_image(width: u32, height: u32) -> HdrImage {
+        let mut image = HdrImage::new(height, width);
+
+        for row in 0..height {
+            for col in 0..width {
+                let color = match col % 40 {
+                    0..=19  => Color::new(0.25, 0.5, 0.75),
+                    20..=22 => Color::new(2.0, 0.0, 40.0),
+                    23..=29 => Color::black(),
+                    _       => Color::new(col as f64 * 0.37, row as f64 * 1.3 + 0.01, (col * row) as f64 * 0.011),
+                };
+                image.set_color(row, col, color);
+            }
+        }
+
+        image
+    }
+
+    fn assert_round_trip(width: u32, height: u32) -> Vec<u8> {
+        let image = test_image(width, height);
+        let mut bytes = vec![];
+        image.write_radiance_to(&mut bytes).unwrap();
+
+        let read = HdrImage::read_radiance(&bytes).unwrap();
+        assert_eq!((read.width(), read.height()), (width, height));
+
+        for row in 0..height {
+            for col in 0..width {
+                // decoding restores the encoded color, up to the precision of RGBE
+                let (original, decoded) = (image.get_color(row, col), read.get_color(row, col));
+                assert_eq!(to_rgbe(original), to_rgbe(decoded), "pixel ({}, {})", row, col);
+
+                let max = f64::max(original.red, f64::max(original.green, original.blue));
+                for (a, b) in [(original.red, decoded.red), (original.green, decoded.green), (original.blue, decoded.blue)] {
+                    assert!((a - b).abs() <= max / 128.0, "pixel ({}, {}): {} != {}", row, col, a, b);
+                }
+            }
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn run_length_encoded_round_trip() {
+        let (width, height) = (300, 4);
+        let bytes = assert_round_trip(width, height);
+
+        // the runs make the encoded image smaller than the flat pixels
+        assert!(bytes.len() < (4 * width * height) as usize);
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 4 +X 300\n".len();
+        assert_eq!(bytes[header..header + 4], [2, 2, 1, 44]);
+    }
+
+    #[test]
+    fn flat_round_trip() {
+        let (width, height) = (5, 3);
+        let bytes = assert_round_trip(width, height);
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 3 +X 5\n".len();
+        assert_eq!(bytes.len(), header + (4 * width * height) as usize);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let mut bytes = vec![];
+        test_image(50, 2).write_radiance_to(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 10);
+
+        assert!(HdrImage::read_radiance(&bytes).is_err());
+    }
+
+    /// (name, type, value) of the header's attributes, and where the header ends
+    fn exr_attributes(bytes: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+        let mut attributes = vec![];
+        let mut i = 8;
+
+        let string = |i: &mut usize| {
+            let end = *i + bytes[*i..].iter().position(|b| *b == 0).unwrap();
+            let s = String::from_utf8(bytes[*i..end].to_vec()).unwrap();
+            *i = end + 1;
+            s
+        };
+
+        while bytes[i] != 0 {
+            let name = string(&mut i);
+            let kind = string(&mut i);
+            let size = i32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+            attributes.push((name, kind, bytes[i + 4..i + 4 + size].to_vec()));
+            i += 4 + size;
+        }
+
+        (attributes, i + 1)
+    }
+
+    fn read_u64(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    fn read_i32(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn exr_layout() {
+        let (width, height) = (7, 3);
+        let image = test_image(width, height);
+        let mut bytes = vec![];
+        image.write_exr_to(&mut bytes).unwrap();
+
+        // magic number, then version 2 without any flags
+        assert_eq!(bytes[..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let (attributes, header_end) = exr_attributes(&bytes);
+        let names: Vec<(&str, &str)> = attributes.iter().map(|(n, k, _)| (n.as_str(), k.as_str())).collect();
+        assert_eq!(names, [
+            ("channels", "chlist"),
+            ("compression", "compression"),
+            ("dataWindow", "box2i"),
+            ("displayWindow", "box2i"),
+            ("lineOrder", "lineOrder"),
+            ("pixelAspectRatio", "float"),
+            ("screenWindowCenter", "v2f"),
+            ("screenWindowWidth", "float"),
+        ]);
+
+        // B, G and R as 32-bit floats, sorted by name
+        let channels = &attributes[0].2;
+        assert_eq!(channels.len(), 3 * (2 + 16) + 1);
+        for (i, name) in [b'B', b'G', b'R'].iter().enumerate() {
+            assert_eq!(channels[i * 18..i * 18 + 2], [*name, 0]);
+            assert_eq!(read_i32(channels, i * 18 + 2), 2);
+        }
+
+        let window: Vec<i32> = (0..4).map(|i| read_i32(&attributes[2].2, 4 * i)).collect();
+        assert_eq!(window, [0, 0, width as i32 - 1, height as i32 - 1]);
+        assert_eq!(attributes[1].2, [0]);
+
+        // the first scanline directly follows the offset table
+        let block_size = 8 + 3 * 4 * width as usize;
+        let first = header_end + 8 * height as usize;
+        for y in 0..height as usize {
+            let offset = read_u64(&bytes, header_end + 8 * y) as usize;
+            assert_eq!(offset, first + y * block_size);
+            assert_eq!(read_i32(&bytes, offset), y as i32);
+            assert_eq!(read_i32(&bytes, offset + 4), 3 * 4 * width as i32);
+        }
+        assert_eq!(bytes.len(), first + height as usize * block_size);
+
+        // the blue channel of the top row comes first
+        let top = image.top_down_row(0).collect::<Vec<Color>>();
+        for (x, color) in top.iter().enumerate() {
+            let blue = f32::from_le_bytes(bytes[first + 8 + 4 * x..first + 12 + 4 * x].try_into().unwrap());
+            assert_eq!(blue, color.blue as f32);
+        }
+    }
+}
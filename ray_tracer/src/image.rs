@@ -1,4 +1,7 @@
 use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
@@ -40,19 +43,196 @@ impl Image {
    }
 }
 
+/// Image storing the (linear, unbounded) radiance of each pixel, before
+/// it is post processed into an `Image`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HdrImage {
+    height: u32,
+    width: u32,
+    /// buffer[i * width + j] => row(i) col(j), rows are ordered like in `Image`
+    buffer: Vec<Color>,
+}
+
+impl HdrImage {
+    pub fn new(rows: u32, cols: u32) -> HdrImage {
+        HdrImage {
+            height: rows,
+            width: cols,
+            buffer: vec![Color::black(); (rows * cols) as usize],
+        }
+    }
+
+    pub fn set_color(&mut self, row: u32, col: u32, color: Color) {
+        self.buffer[(row * self.width + col) as usize] = color;
+    }
+
+    pub fn get_color(&self, row: u32, col: u32) -> Color {
+        self.buffer[(row * self.width + col) as usize]
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+}
+
+impl Image {
+    /// rgb bytes of every pixel, starting with the top row
+    fn rgb_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity((self.width * self.height * 3) as usize);
+
+        for i in (0..self.height).rev() {
+            for j in 0..self.width {
+                let color = self.get_color(i, j);
+                bytes.extend([color.red, color.green, color.blue]);
+            }
+        }
+
+        bytes
+    }
+
+    /// write the image as a binary (P6) PPM
+    pub fn write_p6_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.rgb_bytes())?;
+
+        writer.flush()
+    }
+
+    pub fn write_png_to<W: Write>(&self, writer: &mut W) -> Result<(), ImageError> {
+        let mut encoder = png::Encoder::new(BufWriter::new(writer), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut png_writer = encoder.write_header()?;
+        png_writer.write_image_data(&self.rgb_bytes())?;
+        png_writer.finish()?;
+
+        Ok(())
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W, format: ImageFormat) -> Result<(), ImageError> {
+        match format {
+            ImageFormat::Ppm => self.write_ppm_to(writer)?,
+            ImageFormat::P6  => self.write_p6_to(writer)?,
+            ImageFormat::Png => self.write_png_to(writer)?,
+        }
+
+        Ok(())
+    }
+
+    /// encode the image in memory, e.g. to be downloaded
+    pub fn encode(&self, format: ImageFormat) -> Result<Vec<u8>, ImageError> {
+        let mut bytes = vec![];
+        self.write_to(&mut bytes, format)?;
+
+        Ok(bytes)
+    }
+
+    /// save the image, in the format given by the file's extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path)
+            .ok_or_else(|| ImageError::UnknownFormat { path: path.to_path_buf() })?;
+
+        self.save_as(path, format)
+    }
+
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), ImageError> {
+        let path = path.as_ref();
+        let mut file = File::create(path)
+            .map_err(|error| ImageError::Io { path: Some(path.to_path_buf()), error })?;
+
+        self.write_to(&mut file, format).map_err(|error| match error {
+            ImageError::Io { path: None, error } => ImageError::Io { path: Some(path.to_path_buf()), error },
+            error => error,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+    /// ASCII PPM (P3)
+    Ppm,
+    /// binary PPM
+    P6,
+    Png,
+}
+
+impl ImageFormat {
+    /// `.ppm` files are written as ASCII PPMs
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        match path.as_ref().extension()?.to_str()?.to_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            _     => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    /// `path` is None when writing to something other than a file
+    Io { path: Option<PathBuf>, error: std::io::Error },
+    UnknownFormat { path: PathBuf },
+    Encoding { message: String },
+    Decoding { message: String },
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::Io { path: Some(path), error } => write!(f, "{}: {}", path.display(), error),
+            ImageError::Io { path: None, error } => write!(f, "{}", error),
+            ImageError::UnknownFormat { path } => {
+                write!(f, "{}: unknown image format", path.display())
+            }
+            ImageError::Encoding { message } => write!(f, "failed to encode image: {}", message),
+            ImageError::Decoding { message } => write!(f, "failed to decode image: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<std::io::Error> for ImageError {
+    fn from(error: std::io::Error) -> Self {
+        ImageError::Io { path: None, error }
+    }
+}
+
+impl From<png::EncodingError> for ImageError {
+    fn from(error: png::EncodingError) -> Self {
+        match error {
+            png::EncodingError::IoError(error) => ImageError::Io { path: None, error },
+            error => ImageError::Encoding { message: error.to_string() },
+        }
+    }
+}
+
 pub trait WritePPM {
     fn write_as_ppm(&self) -> ();
+    /// write the image as an ASCII (P3) PPM
+    fn write_ppm_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()>;
 }
 
 impl WritePPM for Image {
     fn write_as_ppm(&self) -> () {
-        println!("P3\n{} {}\n255", self.width, self.height);
+        self.write_ppm_to(&mut std::io::stdout().lock()).expect("failed to write to stdout");
+    }
+
+    fn write_ppm_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+
+        writeln!(writer, "P3\n{} {}\n255", self.width, self.height)?;
 
         for i in (0..self.height).rev() {
             for j in 0..self.width {
-                println!("{}", self.get_color(i, j));
+                writeln!(writer, "{}", self.get_color(i, j))?;
             }
         }
+
+        writer.flush()
     }
 }
 
@@ -109,6 +289,15 @@ impl Color {
         } 
     }
 
+    /// perceived brightness of a linear color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.red == 0.0 && self.green == 0.0 && self.blue == 0.0
+    }
+
     pub const fn white() -> Color { Color::new(1.0, 1.0, 1.0) }
     pub const fn black() -> Color { Color::new(0.0, 0.0, 0.0) }
 }
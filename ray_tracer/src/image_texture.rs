@@ -0,0 +1,435 @@
+//! Textures sampled from PNG, PPM and Radiance HDR images.
+//!
+//! PNG and PPM images are assumed to be sRGB encoded, and are converted to
+//! linear colors when loaded. The texture keeps the encoded file, so that it can be
+//! serialized either by path or with the image data embedded.
+
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::image::{Color, HdrImage};
+use crate::shape::UV;
+use crate::texture::{Texture, TextureType};
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// How texels are combined into the texture's value
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TextureFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/// How UVs outside of [0, 1] are mapped onto the image
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TextureAddress {
+    /// repeat the image
+    #[default]
+    Wrap,
+    /// extend the edges of the image
+    Clamp,
+    /// repeat the image, flipping every other repetition
+    Mirror,
+}
+
+/// Where the image was loaded from
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ImageSource {
+    /// path to the image, relative paths are resolved from the working directory
+    Path(PathBuf),
+    /// contents of a PNG, PPM or Radiance HDR file
+    Data(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io { path: PathBuf, error: std::io::Error },
+    Decode { message: String },
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Io { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+            TextureError::Decode { message } => write!(f, "failed to decode image: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// decoded, linear, texels, starting with the top row
+pub(crate) struct Texels {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) colors: Vec<Color>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "DeserializeImageTexture")]
+pub struct ImageTexture {
+    source: ImageSource,
+    filter: TextureFilter,
+    address: TextureAddress,
+    #[serde(skip)]
+    texels: Arc<Texels>,
+}
+
+#[derive(Deserialize)]
+struct DeserializeImageTexture {
+    source: ImageSource,
+    #[serde(default)]
+    filter: TextureFilter,
+    #[serde(default)]
+    address: TextureAddress,
+}
+
+impl TryFrom<DeserializeImageTexture> for ImageTexture {
+    type Error = TextureError;
+
+    fn try_from(serialized: DeserializeImageTexture) -> Result<ImageTexture, TextureError> {
+        Ok(ImageTexture::new(serialized.source)?.with_sampling(serialized.filter, serialized.address))
+    }
+}
+
+impl ImageTexture {
+    pub fn new(source: ImageSource) -> Result<ImageTexture, TextureError> {
+        let texels = match &source {
+            ImageSource::Path(path) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|error| TextureError::Io { path: path.clone(), error })?;
+                decode(&bytes)?
+            }
+            ImageSource::Data(bytes) => decode(bytes)?,
+        };
+
+        Ok(ImageTexture {
+            source,
+            filter: TextureFilter::default(),
+            address: TextureAddress::default(),
+            texels: Arc::new(texels),
+        })
+    }
+
+    /// load a PNG, PPM or Radiance HDR file
+    pub fn load<P: Into<PathBuf>>(path: P) -> Result<ImageTexture, TextureError> {
+        ImageTexture::new(ImageSource::Path(path.into()))
+    }
+
+    /// decode the contents of an image file, e.g. uploaded in the browser
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<ImageTexture, TextureError> {
+        ImageTexture::new(ImageSource::Data(bytes))
+    }
+
+    pub fn with_sampling(mut self, filter: TextureFilter, address: TextureAddress) -> ImageTexture {
+        self.filter = filter;
+        self.address = address;
+        self
+    }
+
+    pub fn width(&self) -> usize { self.texels.width }
+    pub fn height(&self) -> usize { self.texels.height }
+
+    /// color of the texel at (x, y), after addressing. y = 0 is the top row
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = address(x, self.texels.width, self.address);
+        let y = address(y, self.texels.height, self.address);
+
+        self.texels.colors[y * self.texels.width + x]
+    }
+}
+
+/// map a texel coordinate into [0, size)
+fn address(i: i64, size: usize, mode: TextureAddress) -> usize {
+    let size = size as i64;
+
+    let i = match mode {
+        TextureAddress::Wrap  => i.rem_euclid(size),
+        TextureAddress::Clamp => i.clamp(0, size - 1),
+        TextureAddress::Mirror => {
+            let i = i.rem_euclid(2 * size);
+            if i < size { i } else { 2 * size - 1 - i }
+        }
+    };
+
+    i as usize
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, uv: UV, _point: Vec3) -> Color {
+        // v grows upwards, while images are stored top to bottom
+        let x = uv.u() * self.texels.width as f64;
+        let y = (1.0 - uv.v()) * self.texels.height as f64;
+
+        if !x.is_finite() || !y.is_finite() {
+            return Color::black();
+        }
+
+        match self.filter {
+            TextureFilter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            TextureFilter::Bilinear => {
+                // texel centers are at half integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                self.texel(x0, y0) * ((1.0 - fx) * (1.0 - fy))
+                    + self.texel(x0 + 1, y0) * (fx * (1.0 - fy))
+                    + self.texel(x0, y0 + 1) * ((1.0 - fx) * fy)
+                    + self.texel(x0 + 1, y0 + 1) * (fx * fy)
+            }
+        }
+    }
+}
+
+impl From<ImageTexture> for TextureType {
+    fn from(texture: ImageTexture) -> Self {
+        TextureType::ImageTexture(texture)
+    }
+}
+
+/// sRGB encoded channel in [0, 1] to linear
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 }
+    else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<Texels, TextureError> {
+    let texels = if bytes.starts_with(b"\x89PNG") {
+        decode_png(bytes)?
+    } else if bytes.starts_with(b"P3") || bytes.starts_with(b"P6") {
+        decode_ppm(bytes)?
+    } else if bytes.starts_with(b"#?") {
+        decode_radiance(bytes)?
+    } else {
+        return Err(TextureError::Decode { message: "expected a PNG, PPM or Radiance HDR image".to_string() });
+    };
+
+    if texels.width == 0 || texels.height == 0 {
+        return Err(TextureError::Decode { message: "the image is empty".to_string() });
+    }
+
+    Ok(texels)
+}
+
+/// Radiance HDR images are already linear
+fn decode_radiance(bytes: &[u8]) -> Result<Texels, TextureError> {
+    let image = HdrImage::read_radiance(bytes)
+        .map_err(|e| TextureError::Decode { message: e.to_string() })?;
+
+    let (width, height) = (image.width(), image.height());
+    let colors = (0..height)
+        .rev()
+        .flat_map(|row| (0..width).map(move |col| (row, col)))
+        .map(|(row, col)| image.get_color(row, col))
+        .collect();
+
+    Ok(Texels { width: width as usize, height: height as usize, colors })
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Texels, TextureError> {
+    let error = |e: png::DecodingError| TextureError::Decode { message: e.to_string() };
+
+    let mut decoder = png::Decoder::new(bytes);
+    // expand palettes and low bit depths, strip 16 bit channels to 8 bits
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info().map_err(error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(error)?;
+
+    let channels = info.color_type.samples();
+    let colors = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|texel| {
+            let channel = |i: usize| srgb_to_linear(texel[i] as f64 / 255.0);
+
+            match info.color_type {
+                // grayscale, with or without alpha
+                png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                    Color::new(channel(0), channel(0), channel(0))
+                }
+                _ => Color::new(channel(0), channel(1), channel(2)),
+            }
+        })
+        .collect();
+
+    Ok(Texels { width: info.width as usize, height: info.height as usize, colors })
+}
+
+/// decode an ASCII (P3) or binary (P6) PPM
+fn decode_ppm(bytes: &[u8]) -> Result<Texels, TextureError> {
+    let error = |message: &str| TextureError::Decode { message: message.to_string() };
+
+    // the header is 4 whitespace separated tokens, comments start with '#'
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while tokens.len() < 4 && i < bytes.len() {
+        if bytes[i] == b'#' {
+            while i < bytes.len() && bytes[i] != b'\n' { i += 1; }
+        } else if bytes[i].is_ascii_whitespace() {
+            i += 1;
+        } else {
+            let start = i;
+            while i < bytes.len() && !bytes[i].is_ascii_whitespace() { i += 1; }
+            tokens.push(String::from_utf8_lossy(&bytes[start..i]).to_string());
+        }
+    }
+
+    let number = |token: Option<&String>| -> Result<usize, TextureError> {
+        token.and_then(|t| t.parse().ok()).ok_or_else(|| error("invalid PPM header"))
+    };
+    let width = number(tokens.get(1))?;
+    let height = number(tokens.get(2))?;
+    let max_value = number(tokens.get(3))?;
+
+    if max_value == 0 || max_value > 65535 {
+        return Err(error("invalid PPM maximum value"));
+    }
+
+    // the size comes from the file, so it may be zero or overflow
+    let count = match width.checked_mul(height).and_then(|n| n.checked_mul(3)) {
+        Some(0)     => return Err(error("the PPM is empty")),
+        Some(count) => count,
+        None        => return Err(error("the PPM is too large")),
+    };
+
+    let values: Vec<usize> = match tokens[0].as_str() {
+        "P3" => String::from_utf8_lossy(&bytes[i..])
+            .split_whitespace()
+            .take(count)
+            .map(|value| value.parse().map_err(|_| error("invalid PPM pixel value")))
+            .collect::<Result<_, _>>()?,
+        _ => {
+            // a single whitespace character separates the header from the pixels
+            let data = bytes.get(i + 1..).unwrap_or(&[]);
+
+            if max_value < 256 {
+                data.iter().take(count).map(|v| *v as usize).collect()
+            } else {
+                data.chunks_exact(2).take(count).map(|v| (v[0] as usize) << 8 | v[1] as usize).collect()
+            }
+        }
+    };
+
+    if values.len() < count {
+        return Err(error("the PPM has fewer pixels than its size"));
+    }
+
+    let colors = values
+        .chunks_exact(3)
+        .map(|texel| {
+            let channel = |i: usize| srgb_to_linear(f64::min(texel[i] as f64 / max_value as f64, 1.0));
+            Color::new(channel(0), channel(1), channel(2))
+        })
+        .collect();
+
+    Ok(Texels { width, height, colors })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// one red texel left of a blue one
+    fn red_blue(filter: TextureFilter, address: TextureAddress) -> ImageTexture {
+        ImageTexture::from_bytes(b"P3 2 1 255 255 0 0 0 0 255".to_vec())
+            .unwrap()
+            .with_sampling(filter, address)
+    }
+
+    fn red(texture: &ImageTexture, u: f64) -> f64 {
+        texture.value(UV::new(u, 0.5), Vec3::zeros()).red
+    }
+
+    fn decode_error(bytes: &[u8]) -> String {
+        match decode(bytes) {
+            Err(TextureError::Decode { message }) => message,
+            _ => panic!("expected a decode error"),
+        }
+    }
+
+    #[test]
+    fn decodes_ascii_and_binary_ppm() {
+        let ascii = decode(b"P3\n# comment\n1 2\n255\n255 128 0\n0 0 255\n").unwrap();
+        assert_eq!((ascii.width, ascii.height), (1, 2));
+        assert_eq!(ascii.colors[0].red, 1.0);
+        assert!((ascii.colors[0].green - 0.2158).abs() < 1e-4);
+        assert_eq!(ascii.colors[1].blue, 1.0);
+
+        let binary = decode(b"P6 2 1 255\n\xff\x80\x00\x00\x00\xff").unwrap();
+        assert_eq!((binary.width, binary.height), (2, 1));
+        assert_eq!(binary.colors[0].red, ascii.colors[0].red);
+        assert_eq!(binary.colors[0].green, ascii.colors[0].green);
+        assert_eq!(binary.colors[1].blue, 1.0);
+
+        let wide = decode(b"P6 1 1 65535\n\xff\xff\x00\x00\x80\x00").unwrap();
+        assert_eq!(wide.colors[0].red, 1.0);
+        assert_eq!(wide.colors[0].green, 0.0);
+    }
+
+    #[test]
+    fn rejects_bad_ppm() {
+        assert_eq!(decode_error(b"P3 0 4 255\n"), "the PPM is empty");
+        assert_eq!(decode_error(b"P6 4 0 255\n"), "the PPM is empty");
+        assert_eq!(decode_error(b"P6 4294967296 4294967296 255\n"), "the PPM is too large");
+        assert_eq!(decode_error(b"P3 2 2 255\n1 2 3\n"), "the PPM has fewer pixels than its size");
+        assert_eq!(decode_error(b"P3 2 2 0\n"), "invalid PPM maximum value");
+        assert_eq!(decode_error(b"P3 2 x 255\n"), "invalid PPM header");
+        assert!(decode(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn nearest_filter() {
+        let texture = red_blue(TextureFilter::Nearest, TextureAddress::Clamp);
+
+        assert_eq!(red(&texture, 0.25), 1.0);
+        assert_eq!(red(&texture, 0.49), 1.0);
+        assert_eq!(red(&texture, 0.51), 0.0);
+        assert_eq!(red(&texture, 0.75), 0.0);
+    }
+
+    #[test]
+    fn bilinear_filter() {
+        let texture = red_blue(TextureFilter::Bilinear, TextureAddress::Clamp);
+
+        // exact at texel centers, blended between them
+        assert_eq!(red(&texture, 0.25), 1.0);
+        assert_eq!(red(&texture, 0.75), 0.0);
+        assert!((red(&texture, 0.5) - 0.5).abs() < 1e-12);
+        assert!((red(&texture, 0.375) - 0.75).abs() < 1e-12);
+        // clamped edges don't blend with the other side
+        assert_eq!(red(&texture, 0.0), 1.0);
+        assert_eq!(red(&texture, 1.0), 0.0);
+
+        let wrapped = red_blue(TextureFilter::Bilinear, TextureAddress::Wrap);
+        assert!((red(&wrapped, 0.0) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn addressing() {
+        assert_eq!(address(-1, 4, TextureAddress::Wrap), 3);
+        assert_eq!(address(9, 4, TextureAddress::Wrap), 1);
+        assert_eq!(address(-1, 4, TextureAddress::Clamp), 0);
+        assert_eq!(address(9, 4, TextureAddress::Clamp), 3);
+        assert_eq!(address(-1, 4, TextureAddress::Mirror), 0);
+        assert_eq!(address(4, 4, TextureAddress::Mirror), 3);
+        assert_eq!(address(9, 4, TextureAddress::Mirror), 1);
+
+        let wrap = red_blue(TextureFilter::Nearest, TextureAddress::Wrap);
+        let clamp = red_blue(TextureFilter::Nearest, TextureAddress::Clamp);
+        let mirror = red_blue(TextureFilter::Nearest, TextureAddress::Mirror);
+
+        assert_eq!(red(&wrap, 1.25), 1.0);
+        assert_eq!(red(&wrap, -0.25), 0.0);
+        assert_eq!(red(&clamp, 1.25), 0.0);
+        assert_eq!(red(&clamp, -0.75), 1.0);
+        assert_eq!(red(&mirror, 1.25), 0.0);
+        assert_eq!(red(&mirror, -0.25), 1.0);
+    }
+}
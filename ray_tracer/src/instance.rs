@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use crate::aabb::{AABB, Boundable};
+use crate::collisions::{CollisionRecord, Collidable};
+use crate::ray::Ray;
+use crate::shape::ShapeType;
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// Shape placed in the world by a transform. Rays are transformed into the
+/// shape's own space, and the collision back into the world, so the same
+/// shape (e.g. a mesh) can be shared between many instances.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Instance {
+    shape: Arc<ShapeType>,
+    #[serde(default)]
+    transform: Transform,
+}
+
+impl Instance {
+    pub fn new<S: Into<Arc<ShapeType>>>(shape: S, transform: Transform) -> Instance {
+        Instance { shape: shape.into(), transform }
+    }
+
+    pub fn shape(&self) -> &Arc<ShapeType> { &self.shape }
+    pub fn transform(&self) -> &Transform { &self.transform }
+}
+
+impl Collidable for Instance {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let local_ray = Ray::at_time(
+            self.transform.inverse_point(ray.origin),
+            self.transform.inverse_vector(ray.direction),
+            ray.time,
+        );
+        let record = self.shape.collide(local_ray)?;
+
+        // distances aren't preserved by scaling, so t is measured again
+        let point = self.transform.point(record.point);
+
+        Some(CollisionRecord {
+            point,
+            s_normal: self.transform.normal(record.s_normal),
+            t: Vec3::dot(point - ray.origin, ray.direction),
+            uv: record.uv,
+            // affine transforms don't change which side of the surface is hit
+            face: record.face,
+        })
+    }
+}
+
+impl Boundable for Instance {
+    fn bound(&self) -> AABB {
+        self.transform.bound(self.shape.bound())
+    }
+}
+
+impl From<Instance> for ShapeType {
+    fn from(instance: Instance) -> Self {
+        ShapeType::Instance(instance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Sphere;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    fn unit_sphere(transform: Transform) -> Instance {
+        Instance::new(ShapeType::Sphere(Sphere::new(Vec3::zeros(), 1.0)), transform)
+    }
+
+    #[test]
+    fn scaled_sphere_world_t() {
+        let instance = unit_sphere(Transform::new(Vec3::new(0.0, 0.0, 1.0), Vec3::zeros(), Vec3::ones() * 2.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+
+        let record = instance.collide(ray).unwrap();
+        assert!((record.t - 9.0).abs() < 1e-9);
+        assert!(close(record.point, Vec3::new(0.0, 0.0, -1.0)));
+        assert!(close(record.point, ray.position_at(record.t)));
+        assert!(close(record.s_normal, Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn unevenly_scaled_sphere() {
+        let instance = unit_sphere(Transform::scaling(Vec3::new(4.0, 1.0, 1.0)));
+
+        let along = instance.collide(Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))).unwrap();
+        assert!((along.t - 6.0).abs() < 1e-9);
+
+        let across = instance.collide(Ray::new(Vec3::new(2.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        let z = (1.0f64 - 0.25).sqrt();
+        assert!((across.t - (10.0 - z)).abs() < 1e-9);
+
+        // the normal of the ellipsoid x²/16 + y² + z² = 1
+        let expected = Vec3::new(2.0 / 16.0, 0.0, -z).normalize();
+        assert!(close(across.s_normal, expected));
+
+        let miss = Ray::new(Vec3::new(4.5, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(instance.collide(miss).is_none());
+    }
+
+    #[test]
+    fn bound_is_transformed() {
+        let instance = unit_sphere(Transform::new(Vec3::new(5.0, 0.0, 0.0), Vec3::zeros(), Vec3::new(2.0, 1.0, 3.0)));
+        let bound = instance.bound();
+
+        assert!(close(bound.min(), Vec3::new(3.0, -1.0, -3.0)));
+        assert!(close(bound.max(), Vec3::new(7.0, 1.0, 3.0)));
+    }
+}
@@ -4,21 +4,54 @@ pub use renderer::*;
 pub use scene::*;
 pub use camera::*;
 pub use shape::*;
+pub use primitives::*;
 pub use material::*;
+pub use microfacet::{ComplexIor, Conductor, RoughDielectric};
 pub use texture::*;
+pub use light::{LightType, PointLight, SpotLight, DirectionalLight};
+pub use image_texture::*;
+pub use environment::{Background, EnvironmentMap};
+pub use sky::Sky;
+pub use post_process::*;
+pub use hdr::HdrFormat;
+pub use mesh::Mesh;
+pub use instance::Instance;
+pub use transform::Transform;
+pub use motion::{Curve, Keyframe, Moving, Track};
+pub use animation::{Animation, AnimationError, ImageSequence, frame_path};
+pub use csg::{Csg, CsgOperation};
+pub use volume::{ConstantMedium, HeterogeneousMedium, DensityField, NoiseField, VoxelGrid, Medium};
 pub use pool::WorkerPool;
 
 pub mod vec3;
 pub mod ray;
 pub mod utils;
 pub mod image;
+pub mod post_process;
+pub mod hdr;
 pub mod renderer;
 pub mod scene;
+pub mod scene_file;
 pub mod camera;
 pub mod shape;
+pub mod primitives;
+pub mod mesh;
+pub mod instance;
+pub mod transform;
+pub mod motion;
+pub mod animation;
+pub mod csg;
+pub mod volume;
+pub mod obj;
 pub mod aabb;
+pub mod bvh;
 pub mod collisions;
 pub mod material;
+pub mod microfacet;
+pub mod light;
 pub mod texture;
+pub mod image_texture;
+pub mod environment;
+pub mod sky;
 pub mod perlin;
 pub mod pool;
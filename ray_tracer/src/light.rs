@@ -0,0 +1,451 @@
+use std::f64::consts::PI;
+
+use crate::collisions::{Collidable, MIN_INTERSECTION_T};
+use crate::image::Color;
+use crate::instance::Instance;
+use crate::ray::Ray;
+use crate::shape::{ShapeType, Sphere, RectangleXY, RectangleXZ, RectangleYZ, Triangle, Quad, Disk, SurfaceNormal, TextureMap, UV};
+use crate::transform::Transform;
+use crate::utils::{random_float, Onb};
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// Point sampled on the surface of a shape, as seen from a reference point
+pub struct SurfaceSample {
+    pub point: Vec3,
+    /// surface normal at the sampled point
+    pub normal: Vec3,
+    pub uv: UV,
+    /// probability density of the direction towards the point, with respect
+    /// to solid angle at the reference point
+    pub pdf: f64,
+}
+
+/// Shapes that can be sampled directly, which allows emissive elements
+/// to be used for explicit light sampling
+pub trait Samplable {
+    /// sample a point on the shape, as seen from `origin`
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample>;
+
+    /// density of `sample` producing the direction from `origin`, zero if
+    /// a ray in the direction doesn't hit the shape
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64;
+}
+
+impl ShapeType {
+    /// whether the shape implements `Samplable`
+    pub fn is_samplable(&self) -> bool {
+        match self {
+            ShapeType::Instance(s) => s.shape().is_samplable(),
+            _ => matches!(
+                self,
+                ShapeType::Sphere(_) | ShapeType::RectangleXY(_) | ShapeType::RectangleXZ(_) |
+                ShapeType::RectangleYZ(_) | ShapeType::Triangle(_) | ShapeType::Quad(_) |
+                ShapeType::Disk(_)
+            ),
+        }
+    }
+}
+
+impl Samplable for ShapeType {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        match self {
+            ShapeType::Sphere(s)      => s.sample(origin),
+            ShapeType::RectangleXY(s) => s.sample(origin),
+            ShapeType::RectangleXZ(s) => s.sample(origin),
+            ShapeType::RectangleYZ(s) => s.sample(origin),
+            ShapeType::Triangle(s)    => s.sample(origin),
+            ShapeType::Quad(s)        => s.sample(origin),
+            ShapeType::Disk(s)        => s.sample(origin),
+            ShapeType::Instance(s)    => s.sample(origin),
+            _ => None,
+        }
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        match self {
+            ShapeType::Sphere(s)      => s.pdf(origin, direction),
+            ShapeType::RectangleXY(s) => s.pdf(origin, direction),
+            ShapeType::RectangleXZ(s) => s.pdf(origin, direction),
+            ShapeType::RectangleYZ(s) => s.pdf(origin, direction),
+            ShapeType::Triangle(s)    => s.pdf(origin, direction),
+            ShapeType::Quad(s)        => s.pdf(origin, direction),
+            ShapeType::Disk(s)        => s.pdf(origin, direction),
+            ShapeType::Instance(s)    => s.pdf(origin, direction),
+            _ => 0.0,
+        }
+    }
+}
+
+/// convert a point sampled uniformly over the area of a surface to a solid angle sample
+fn area_sample(origin: Vec3, point: Vec3, normal: Vec3, uv: UV, area: f64) -> Option<SurfaceSample> {
+    let to_point = point - origin;
+    let distance_squared = to_point.length_squared();
+
+    if distance_squared == 0.0 {
+        return None;
+    }
+
+    let cos = Vec3::dot(normal, to_point).abs() / distance_squared.sqrt();
+
+    // the surface is seen edge on
+    if cos < 1e-8 {
+        return None;
+    }
+
+    Some(SurfaceSample { point, normal, uv, pdf: distance_squared / (cos * area) })
+}
+
+/// solid angle density of the direction, for shapes sampled uniformly by area
+fn area_pdf<C: Collidable>(shape: &C, origin: Vec3, direction: Vec3, area: f64) -> f64 {
+    match shape.collide(Ray::new(origin, direction)) {
+        Some(record) if record.t > MIN_INTERSECTION_T => {
+            area_sample(origin, record.point, record.s_normal, record.uv, area).map_or(0.0, |sample| sample.pdf)
+        }
+        _ => 0.0,
+    }
+}
+
+impl Samplable for RectangleXY {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        let (x0, x1, y0, y1, k) = self.extent();
+        let point = Vec3::new(x0 + random_float() * (x1 - x0), y0 + random_float() * (y1 - y0), k);
+
+        area_sample(origin, point, self.surface_normal(point), self.map(point), (x1 - x0) * (y1 - y0))
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let (x0, x1, y0, y1, _) = self.extent();
+
+        area_pdf(self, origin, direction, (x1 - x0) * (y1 - y0))
+    }
+}
+
+impl Samplable for RectangleXZ {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        let (x0, x1, z0, z1, k) = self.extent();
+        let point = Vec3::new(x0 + random_float() * (x1 - x0), k, z0 + random_float() * (z1 - z0));
+
+        area_sample(origin, point, self.surface_normal(point), self.map(point), (x1 - x0) * (z1 - z0))
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let (x0, x1, z0, z1, _) = self.extent();
+
+        area_pdf(self, origin, direction, (x1 - x0) * (z1 - z0))
+    }
+}
+
+impl Samplable for RectangleYZ {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        let (y0, y1, z0, z1, k) = self.extent();
+        let point = Vec3::new(k, y0 + random_float() * (y1 - y0), z0 + random_float() * (z1 - z0));
+
+        area_sample(origin, point, self.surface_normal(point), self.map(point), (y1 - y0) * (z1 - z0))
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let (y0, y1, z0, z1, _) = self.extent();
+
+        area_pdf(self, origin, direction, (y1 - y0) * (z1 - z0))
+    }
+}
+
+impl Samplable for Triangle {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        let [a, b, c] = self.vertices();
+
+        // uniform barycentric coordinates
+        let r1 = random_float().sqrt();
+        let r2 = random_float();
+        let point = a * (1.0 - r1) + b * (r1 * (1.0 - r2)) + c * (r1 * r2);
+
+        area_sample(origin, point, self.geometric_normal(), self.map(point), self.area())
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_pdf(self, origin, direction, self.area())
+    }
+}
+
+impl Samplable for Quad {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        let (u, v) = self.edges();
+        let point = self.corner() + u * random_float() + v * random_float();
+
+        area_sample(origin, point, self.surface_normal(point), self.map(point), self.area())
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_pdf(self, origin, direction, self.area())
+    }
+}
+
+impl Samplable for Disk {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        // uniform by area
+        let r = self.radius() * random_float().sqrt();
+        let phi = 2.0 * PI * random_float();
+        let point = self.center() + self.basis().local(r * phi.cos(), r * phi.sin(), 0.0);
+
+        area_sample(origin, point, self.surface_normal(point), self.map(point), self.area())
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        area_pdf(self, origin, direction, self.area())
+    }
+}
+
+impl Sphere {
+    /// cosine of the half-angle of the cone the sphere subtends from outside of it
+    fn cos_theta_max(&self, origin: Vec3) -> Option<f64> {
+        let distance_squared = (self.center() - origin).length_squared();
+        let radius_squared = self.radius() * self.radius();
+
+        if distance_squared <= radius_squared {
+            return None;
+        }
+
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+}
+
+impl Samplable for Sphere {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            // inside of the sphere, every point is visible
+            None => {
+                let normal = crate::utils::random_unit_vector();
+                let area = 4.0 * PI * self.radius() * self.radius();
+
+                return area_sample(origin, self.center() + normal * self.radius(), normal, self.map(normal), area);
+            }
+        };
+
+        // sample the cone of directions that hit the sphere uniformly
+        let cos_theta = 1.0 - random_float() * (1.0 - cos_theta_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * random_float();
+
+        let onb = Onb::from_w(self.center() - origin);
+        let direction = onb.local(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+
+        let t = match self.intersections(Ray::new(origin, direction)) {
+            Some((t, _)) => t,
+            // the direction grazes the sphere
+            None => Vec3::dot(self.center() - origin, direction),
+        };
+        let point = origin + direction * t;
+        let normal = self.surface_normal(point);
+
+        Some(SurfaceSample {
+            point,
+            normal,
+            uv: self.map(normal),
+            pdf: 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+        })
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => match self.intersections(Ray::new(origin, direction)) {
+                Some((_, t)) if t > MIN_INTERSECTION_T => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+                _ => 0.0,
+            },
+            None => area_pdf(self, origin, direction, 4.0 * PI * self.radius() * self.radius()),
+        }
+    }
+}
+
+/// Solid angle density, seen from `origin`, of a point sampled with `pdf`
+/// from `local_origin` in the instanced shape's space. Scaling doesn't
+/// preserve solid angles, so the density is converted through area density.
+fn instance_pdf(transform: &Transform, origin: Vec3, local_origin: Vec3, point: Vec3, normal: Vec3, pdf: f64) -> Option<f64> {
+    let cos_and_distance_squared = |origin: Vec3, point: Vec3, normal: Vec3| {
+        let to_point = point - origin;
+        let distance_squared = to_point.length_squared();
+
+        (Vec3::dot(normal, to_point).abs() / distance_squared.sqrt(), distance_squared)
+    };
+
+    let (local_cos, local_distance_squared) = cos_and_distance_squared(local_origin, point, normal);
+    let (cos, distance_squared) = cos_and_distance_squared(origin, transform.point(point), transform.normal(normal));
+
+    // the surface is seen edge on
+    if local_cos < 1e-8 || cos < 1e-8 {
+        return None;
+    }
+
+    let area_pdf = pdf * local_cos / local_distance_squared / transform.area_scale(normal);
+
+    Some(area_pdf * distance_squared / cos)
+}
+
+impl Samplable for Instance {
+    fn sample(&self, origin: Vec3) -> Option<SurfaceSample> {
+        let transform = self.transform();
+        let local_origin = transform.inverse_point(origin);
+        let sample = self.shape().sample(local_origin)?;
+
+        Some(SurfaceSample {
+            point: transform.point(sample.point),
+            normal: transform.normal(sample.normal),
+            uv: sample.uv,
+            pdf: instance_pdf(transform, origin, local_origin, sample.point, sample.normal, sample.pdf)?,
+        })
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let transform = self.transform();
+        let local_origin = transform.inverse_point(origin);
+        let local_direction = transform.inverse_vector(direction).normalize();
+
+        match self.shape().collide(Ray::new(local_origin, local_direction)) {
+            Some(record) if record.t > MIN_INTERSECTION_T => {
+                let pdf = self.shape().pdf(local_origin, local_direction);
+
+                instance_pdf(transform, origin, local_origin, record.point, record.s_normal, pdf).unwrap_or(0.0)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Lights without any geometry. Rays can't hit them, so they are only found
+/// by sampling them directly from every surface
+#[derive(Clone, Serialize, Deserialize)]
+pub enum LightType {
+    Point(PointLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight),
+}
+
+/// Light arriving at a point from a delta light
+pub struct LightSample {
+    /// unit direction towards the light
+    pub direction: Vec3,
+    /// distance to the light, infinite for directional lights
+    pub distance: f64,
+    /// irradiance at the point, perpendicular to `direction`
+    pub irradiance: Color,
+}
+
+impl LightType {
+    /// light arriving at `point`, None if the point isn't lit
+    pub fn sample(&self, point: Vec3) -> Option<LightSample> {
+        match self {
+            LightType::Point(l)       => l.sample(point),
+            LightType::Spot(l)        => l.sample(point),
+            LightType::Directional(l) => l.sample(point),
+        }
+    }
+}
+
+/// Emits light equally in every direction. `intensity` is the irradiance at
+/// a distance of 1, falling off with the squared distance
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PointLight {
+    position: Vec3,
+    color: Color,
+    intensity: f64,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Color, intensity: f64) -> PointLight {
+        PointLight { position, color, intensity }
+    }
+
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        toward_position(self.position, point, self.color * self.intensity)
+    }
+}
+
+/// Point light restricted to a cone around `direction`. The light fades out
+/// between `inner_angle_degrees` and `outer_angle_degrees` from the axis
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    color: Color,
+    intensity: f64,
+    inner_angle_degrees: f64,
+    outer_angle_degrees: f64,
+}
+
+impl SpotLight {
+    pub fn new(position: Vec3, direction: Vec3, color: Color, intensity: f64, inner_angle_degrees: f64, outer_angle_degrees: f64) -> SpotLight {
+        SpotLight { position, direction, color, intensity, inner_angle_degrees, outer_angle_degrees }
+    }
+
+    /// fraction of the light emitted towards the (unit) direction
+    fn falloff(&self, direction: Vec3) -> f64 {
+        let cos = Vec3::dot(direction, self.direction.normalize());
+        let cos_inner = self.inner_angle_degrees.to_radians().cos();
+        let cos_outer = self.outer_angle_degrees.to_radians().cos();
+
+        if cos >= cos_inner {
+            return 1.0;
+        }
+        if cos <= cos_outer {
+            return 0.0;
+        }
+
+        // smoothstep
+        let t = (cos - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let falloff = self.falloff((point - self.position).normalize());
+
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        toward_position(self.position, point, self.color * (self.intensity * falloff))
+    }
+}
+
+/// Light arriving from infinitely far away along `direction`, like sunlight.
+/// `intensity` is the irradiance on a surface facing the light
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DirectionalLight {
+    /// direction the light travels in
+    direction: Vec3,
+    color: Color,
+    intensity: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Color, intensity: f64) -> DirectionalLight {
+        DirectionalLight { direction, color, intensity }
+    }
+
+    fn sample(&self, _point: Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction.normalize(),
+            distance: f64::INFINITY,
+            irradiance: self.color * self.intensity,
+        })
+    }
+}
+
+/// light from a point at `position`, with `intensity` at a distance of 1
+fn toward_position(position: Vec3, point: Vec3, intensity: Color) -> Option<LightSample> {
+    let to_light = position - point;
+    let distance_squared = to_light.length_squared();
+
+    if distance_squared == 0.0 {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+
+    Some(LightSample {
+        direction: to_light / distance,
+        distance,
+        irradiance: intensity * (1.0 / distance_squared),
+    })
+}
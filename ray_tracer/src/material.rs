@@ -1,22 +1,48 @@
 use crate::collisions::{CollisionRecord, Face};
 use crate::image::Color;
+use crate::microfacet::{Conductor, RoughDielectric};
 use crate::ray::Ray;
+use crate::shape::UV;
 use crate::texture::{TextureType, Texture};
-use crate::utils::{reflect, random_unit_vector, sample_unit_sphere};
+use crate::utils::{reflect, random_cosine_direction, random_unit_vector, sample_unit_sphere, Onb};
 use crate::vec3::Vec3;
 
+use std::f64::consts::PI;
+
 use serde::{Deserialize, Serialize};
 
-pub struct CollisionResult {
-    pub reflected_ray: Ray,
-    pub color: Color,
-    pub emitted_light: Color
+/// Direction sampled from a material's BSDF
+pub struct BsdfSample {
+    /// direction the scattered light arrives from, pointing away from the surface
+    pub direction: Vec3,
+    /// BSDF * cos / pdf, the factor light arriving from `direction` is scaled by
+    pub weight: Color,
+    /// density of the direction with respect to solid angle, meaningless for
+    /// specular samples
+    pub pdf: f64,
+    /// the direction was picked from a discrete set (mirrors and glass) or a
+    /// distribution that `eval` and `pdf` don't describe, so it can't be
+    /// combined with light sampling
+    pub specular: bool,
 }
 
+/// Scattering at a surface, described by its BSDF.
+///
+/// Light travels opposite to the rays being traced: `ray` is the ray that hit
+/// the surface, and `direction` is the direction the scattered light arrives from.
 pub trait Material {
-    /// Takes in a ray and a collision record and performs 
-    /// collision resolution 
-    fn resolve(&self, ray: Ray, collision: CollisionRecord) -> CollisionResult;
+    /// sample a direction, None if the light is absorbed
+    fn sample(&self, _ray: Ray, _collision: &CollisionRecord) -> Option<BsdfSample> { None }
+
+    /// BSDF * cos, where cos is the cosine of the angle between `direction`
+    /// and the surface normal. Zero for specular materials
+    fn eval(&self, _ray: Ray, _collision: &CollisionRecord, _direction: Vec3) -> Color { Color::black() }
+
+    /// density of `sample` producing `direction`, zero for specular materials
+    fn pdf(&self, _ray: Ray, _collision: &CollisionRecord, _direction: Vec3) -> f64 { 0.0 }
+
+    /// light emitted by the surface
+    fn emitted(&self, _uv: UV, _point: Vec3, _face: &Face) -> Color { Color::black() }
 }
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -25,21 +51,98 @@ pub enum MaterialType {
     Dielectric(Dielectric),
     Metal(Metal),
     DiffuseLight(DiffuseLight),
+    Isotropic(Isotropic),
+    Conductor(Conductor),
+    RoughDielectric(RoughDielectric),
 }
 
 impl Material for MaterialType {
     // This allows use to resolve collision for non-homogenous data 
     // without having to use trait objects 
-    fn resolve(&self, ray: Ray, collision: CollisionRecord) -> CollisionResult {
+    fn sample(&self, ray: Ray, collision: &CollisionRecord) -> Option<BsdfSample> {
+        match self {
+            MaterialType::Dielectric(m)   => m.sample(ray, collision),
+            MaterialType::Lambertian(m)   => m.sample(ray, collision),
+            MaterialType::Metal(m)        => m.sample(ray, collision),
+            MaterialType::DiffuseLight(m) => m.sample(ray, collision),
+            MaterialType::Isotropic(m)    => m.sample(ray, collision),
+            MaterialType::Conductor(m)    => m.sample(ray, collision),
+            MaterialType::RoughDielectric(m) => m.sample(ray, collision),
+        }
+    }
+
+    fn eval(&self, ray: Ray, collision: &CollisionRecord, direction: Vec3) -> Color {
+        match self {
+            MaterialType::Dielectric(m)   => m.eval(ray, collision, direction),
+            MaterialType::Lambertian(m)   => m.eval(ray, collision, direction),
+            MaterialType::Metal(m)        => m.eval(ray, collision, direction),
+            MaterialType::DiffuseLight(m) => m.eval(ray, collision, direction),
+            MaterialType::Isotropic(m)    => m.eval(ray, collision, direction),
+            MaterialType::Conductor(m)    => m.eval(ray, collision, direction),
+            MaterialType::RoughDielectric(m) => m.eval(ray, collision, direction),
+        }
+    }
+
+    fn pdf(&self, ray: Ray, collision: &CollisionRecord, direction: Vec3) -> f64 {
+        match self {
+            MaterialType::Dielectric(m)   => m.pdf(ray, collision, direction),
+            MaterialType::Lambertian(m)   => m.pdf(ray, collision, direction),
+            MaterialType::Metal(m)        => m.pdf(ray, collision, direction),
+            MaterialType::DiffuseLight(m) => m.pdf(ray, collision, direction),
+            MaterialType::Isotropic(m)    => m.pdf(ray, collision, direction),
+            MaterialType::Conductor(m)    => m.pdf(ray, collision, direction),
+            MaterialType::RoughDielectric(m) => m.pdf(ray, collision, direction),
+        }
+    }
+
+    fn emitted(&self, uv: UV, point: Vec3, face: &Face) -> Color {
         match self {
-            MaterialType::Dielectric(m)   => m.resolve(ray, collision),
-            MaterialType::Lambertian(m)   => m.resolve(ray, collision),
-            MaterialType::Metal(m)        => m.resolve(ray, collision),
-            MaterialType::DiffuseLight(m) => m.resolve(ray, collision),
+            MaterialType::Dielectric(m)   => m.emitted(uv, point, face),
+            MaterialType::Lambertian(m)   => m.emitted(uv, point, face),
+            MaterialType::Metal(m)        => m.emitted(uv, point, face),
+            MaterialType::DiffuseLight(m) => m.emitted(uv, point, face),
+            MaterialType::Isotropic(m)    => m.emitted(uv, point, face),
+            MaterialType::Conductor(m)    => m.emitted(uv, point, face),
+            MaterialType::RoughDielectric(m) => m.emitted(uv, point, face),
         }
     }
 } 
 
+/// Material property that can be changed after the material is created,
+/// e.g. by an animation
+#[derive(Copy, Clone)]
+pub enum MaterialParameter {
+    /// replaces the texture of Lambertian, Metal and Isotropic materials,
+    /// and the hue of lights
+    Color(Color),
+    Fuzz(f64),
+    RefractiveIndex(f64),
+    Brightness(f64),
+    /// of microfacet materials
+    Roughness(f64),
+}
+
+impl MaterialType {
+    /// change one of the material's parameters, false if the material doesn't have it
+    pub fn set_parameter(&mut self, parameter: MaterialParameter) -> bool {
+        match (self, parameter) {
+            (MaterialType::Lambertian(m), MaterialParameter::Color(color))       => m.texture = color.into(),
+            (MaterialType::Metal(m), MaterialParameter::Color(color))            => m.texture = color.into(),
+            (MaterialType::Isotropic(m), MaterialParameter::Color(color))        => m.texture = color.into(),
+            (MaterialType::DiffuseLight(m), MaterialParameter::Color(color))     => m.hue = color,
+            (MaterialType::Metal(m), MaterialParameter::Fuzz(fuzz))              => m.fuzz = fuzz,
+            (MaterialType::Dielectric(m), MaterialParameter::RefractiveIndex(n)) => m.ref_index = n,
+            (MaterialType::DiffuseLight(m), MaterialParameter::Brightness(b))    => m.brightness = b,
+            (MaterialType::RoughDielectric(m), MaterialParameter::RefractiveIndex(n)) => m.ref_index = n,
+            (MaterialType::Conductor(m), MaterialParameter::Roughness(r))        => m.roughness = r,
+            (MaterialType::RoughDielectric(m), MaterialParameter::Roughness(r))  => m.roughness = r,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Lambertian {
     texture: TextureType 
@@ -55,18 +158,35 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn resolve(&self, _ray: Ray, collision: CollisionRecord) -> CollisionResult {
-        let mut bounce_dir = collision.normal() + random_unit_vector(); 
+    fn sample(&self, _ray: Ray, collision: &CollisionRecord) -> Option<BsdfSample> {
+        // cosine weighted directions cancel out the cosine term of the BSDF
+        let (x, y, z) = random_cosine_direction();
+        let direction = Onb::from_w(collision.normal()).local(x, y, z);
 
-        if bounce_dir.near_zero() {
-            bounce_dir = collision.normal();
+        if z <= 0.0 {
+            return None;
         }
 
-        CollisionResult {
-            reflected_ray: Ray::new(collision.point, bounce_dir),
-            color: self.texture.value(collision.uv, collision.point),
-            emitted_light: Color::new(0.0, 0.0, 0.0)
+        Some(BsdfSample {
+            direction,
+            weight: self.texture.value(collision.uv, collision.point),
+            pdf: z / PI,
+            specular: false,
+        })
+    }
+
+    fn eval(&self, _ray: Ray, collision: &CollisionRecord, direction: Vec3) -> Color {
+        let cos = Vec3::dot(collision.normal(), direction);
+
+        if cos <= 0.0 {
+            return Color::black();
         }
+
+        self.texture.value(collision.uv, collision.point) * (cos / PI)
+    }
+
+    fn pdf(&self, _ray: Ray, collision: &CollisionRecord, direction: Vec3) -> f64 {
+        f64::max(0.0, Vec3::dot(collision.normal(), direction)) / PI
     }
 }
 
@@ -90,7 +210,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn resolve(&self, ray: Ray, collision: CollisionRecord) -> CollisionResult {
+    fn sample(&self, ray: Ray, collision: &CollisionRecord) -> Option<BsdfSample> {
         let ref_ratio = match collision.face {
             Face::Outer => 1.0 / self.ref_index,
             Face::Inner => self.ref_index
@@ -104,14 +224,15 @@ impl Material for Dielectric {
 
         let reflect = must_reflect || veto_refract;
 
-        CollisionResult {
-            reflected_ray: match reflect { 
-                true  => ray.reflect(collision.normal(), collision.point),
-                false => ray.refract(collision.normal(), collision.point, ref_ratio)
+        Some(BsdfSample {
+            direction: match reflect { 
+                true  => ray.reflect(collision.normal(), collision.point).direction,
+                false => ray.refract(collision.normal(), collision.point, ref_ratio).direction
             },
-            color: Color::new(1.0, 1.0, 1.0),
-            emitted_light: Color::new(0.0, 0.0, 0.0)
-        }
+            weight: Color::white(),
+            pdf: 0.0,
+            specular: true,
+        })
     }
 }
 
@@ -132,34 +253,33 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn resolve(&self, ray: Ray, collision: CollisionRecord) -> CollisionResult {
+    fn sample(&self, ray: Ray, collision: &CollisionRecord) -> Option<BsdfSample> {
+        // ray cannot escape
+        if collision.face == Face::Inner {
+            return None;
+        }
+
         let ref_ray_dir = reflect(ray.direction, collision.normal());
-        let ref_ray = Ray::new(
-            collision.point,
-            /*
-            add fuzziness to reflection by perturbing the reflected
-            ray by selecting a target point inside a sphere of radius (fuzz)
-            centered at the non-perturbed rays target
-            */
-            ref_ray_dir + sample_unit_sphere() * self.fuzz
-        );
-
-        match collision.face {
-            Face::Outer => {
-                CollisionResult {
-                    color: self.texture.value(collision.uv, collision.point),
-                    reflected_ray: ref_ray,
-                    emitted_light: Color::new(0.0, 0.0, 0.0)
-                }
-    
-            }
-            // ray cannot escape
-            Face::Inner => CollisionResult {
-                color: Color::black(),
-                reflected_ray: ref_ray,
-                emitted_light: Color::new(0.0, 0.0, 0.0)
-            } 
+        /*
+        add fuzziness to reflection by perturbing the reflected
+        ray by selecting a target point inside a sphere of radius (fuzz)
+        centered at the non-perturbed rays target
+        */
+        let direction = (ref_ray_dir + sample_unit_sphere() * self.fuzz).normalize();
+
+        // perturbed below the surface
+        if Vec3::dot(direction, collision.normal()) <= 0.0 {
+            return None;
         }
+
+        // fuzzy reflections aren't described by eval and pdf, so they are
+        // treated like perfect reflections
+        Some(BsdfSample {
+            direction,
+            weight: self.texture.value(collision.uv, collision.point),
+            pdf: 0.0,
+            specular: true,
+        })
     }
 }
 
@@ -175,21 +295,43 @@ impl DiffuseLight {
     }
 }
 
+/// Emits light and absorbs all incoming light
 impl Material for DiffuseLight {
-    fn resolve(&self, ray: Ray, collision: CollisionRecord) -> CollisionResult {
-        /*
-        This is a temporary hack. Basically, currently we have "color" determined 
-        seperately from "emitted_light". Eventually these two things will
-        be combined by applying the Blinn-Phong reflection model 
+    fn emitted(&self, _uv: UV, _point: Vec3, _face: &Face) -> Color {
+        self.hue * self.brightness
+    }
+}
 
-        For now, to determine the light coming from light emitting surfaces,
-        we will simply zero out the impact of all incoming light on the surface
-        by making the attentuation "color"=Color(0, 0, 0).
-        */
-        CollisionResult {
-            reflected_ray: ray, 
-            color: Color::new(0.0, 0.0, 0.0),
-            emitted_light: self.hue * self.brightness,
-        }    
+/// Scatters light equally in every direction. Used inside of participating
+/// media (`ConstantMedium`), where there is no surface to scatter off of
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Isotropic {
+    texture: TextureType,
+}
+
+impl Isotropic {
+    pub fn new(texture: TextureType) -> Isotropic {
+        Isotropic { texture }
+    }
+}
+
+impl Material for Isotropic {
+    fn sample(&self, _ray: Ray, collision: &CollisionRecord) -> Option<BsdfSample> {
+        // the phase function is sampled exactly, so only the albedo remains
+        Some(BsdfSample {
+            direction: random_unit_vector(),
+            weight: self.texture.value(collision.uv, collision.point),
+            pdf: 1.0 / (4.0 * PI),
+            specular: false,
+        })
+    }
+
+    /// phase function, there is no cosine term in a medium
+    fn eval(&self, _ray: Ray, collision: &CollisionRecord, _direction: Vec3) -> Color {
+        self.texture.value(collision.uv, collision.point) * (1.0 / (4.0 * PI))
+    }
+
+    fn pdf(&self, _ray: Ray, _collision: &CollisionRecord, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
     }
 }
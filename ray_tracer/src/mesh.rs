@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use crate::aabb::{AABB, Boundable};
+use crate::bvh::Bvh;
+use crate::collisions::{CollisionRecord, Collidable};
+use crate::ray::Ray;
+use crate::shape::Triangle;
+
+use serde::{Deserialize, Serialize};
+
+/// Collection of triangles with its own bvh, so that a single element
+/// can be made of many triangles without slowing down the scene's bvh.
+/// The triangles and bvh are shared between clones.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "DeserializeMesh")]
+pub struct Mesh {
+    triangles: Arc<Vec<Triangle>>,
+    #[serde(skip)]
+    bvh: Arc<Bvh>,
+}
+
+#[derive(Deserialize)]
+struct DeserializeMesh {
+    triangles: Vec<Triangle>,
+}
+
+impl From<DeserializeMesh> for Mesh {
+    fn from(serialized: DeserializeMesh) -> Mesh {
+        Mesh::new(serialized.triangles)
+    }
+}
+
+impl Mesh {
+    pub fn new(triangles: Vec<Triangle>) -> Mesh {
+        let bvh = Bvh::new(&triangles);
+
+        Mesh {
+            triangles: Arc::new(triangles),
+            bvh: Arc::new(bvh),
+        }
+    }
+
+    pub fn triangles(&self) -> &[Triangle] { &self.triangles }
+}
+
+impl Collidable for Mesh {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        self.bvh
+            .collide(ray, |i| self.triangles[i].collide(ray))
+            .map(|(_, record)| record)
+    }
+}
+
+impl Boundable for Mesh {
+    fn bound(&self) -> AABB {
+        self.bvh.bound()
+    }
+}
@@ -0,0 +1,548 @@
+//! Rough conductors and dielectrics. Their surfaces are modelled as tiny
+//! perfect mirrors (microfacets), oriented according to the GGX
+//! (Trowbridge-Reitz) distribution, with Smith's masking-shadowing.
+//!
+//! Directions are sampled from the distribution of facets visible from the
+//! ray (Heitz, "Sampling the GGX Distribution of Visible Normals", 2018), and
+//! `eval` and `pdf` describe the sampled directions, so rough surfaces can be
+//! combined with light sampling.
+
+use crate::collisions::{CollisionRecord, Face};
+use crate::image::Color;
+use crate::material::{BsdfSample, Dielectric, Material};
+use crate::ray::Ray;
+use crate::utils::{random_float, reflect, Onb};
+use crate::vec3::Vec3;
+
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+/// below this alpha the distribution is too peaked to evaluate, and the
+/// surface is treated as perfectly smooth
+const MIN_ALPHA: f64 = 1e-3;
+
+/// GGX distribution of facet normals, in a basis where the surface normal is z
+#[derive(Copy, Clone)]
+struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    /// alpha = roughness², which makes roughness look roughly linear
+    fn new(roughness: f64) -> Ggx {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Ggx { alpha: roughness * roughness }
+    }
+
+    fn is_smooth(&self) -> bool { self.alpha < MIN_ALPHA }
+
+    /// density of facet normals
+    fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        let x = h.z * h.z * (a2 - 1.0) + 1.0;
+        a2 / (PI * x * x)
+    }
+
+    /// Smith's auxiliary function, the same on both sides of the surface
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        let tan2 = f64::max(0.0, 1.0 - cos2) / cos2;
+
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// fraction of the facets facing `h` visible from `w`
+    fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// fraction of the facets visible from both `wo` and `wi` (height correlated)
+    fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// facet normal visible from `wo`, which must be above the surface
+    fn sample_visible(&self, wo: Vec3) -> Vec3 {
+        // stretch the view direction so that the distribution is a hemisphere
+        let v = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+
+        let length2 = v.x * v.x + v.y * v.y;
+        let t1 = match length2 > 0.0 {
+            true  => Vec3::new(-v.y, v.x, 0.0) * (1.0 / length2.sqrt()),
+            false => Vec3::new(1.0, 0.0, 0.0),
+        };
+        let t2 = Vec3::cross(v, t1);
+
+        // uniform point on a disk, squeezed onto the part of the hemisphere
+        // visible from v
+        let r = random_float().sqrt();
+        let phi = 2.0 * PI * random_float();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let n = t1 * p1 + t2 * p2 + v * f64::max(0.0, 1.0 - p1 * p1 - p2 * p2).sqrt();
+
+        // unstretch
+        Vec3::new(self.alpha * n.x, self.alpha * n.y, f64::max(0.0, n.z)).normalize()
+    }
+
+    /// density of `sample_visible` producing `h`
+    fn pdf_visible(&self, wo: Vec3, h: Vec3) -> f64 {
+        self.g1(wo) * f64::max(0.0, Vec3::dot(wo, h)) * self.d(h) / wo.z
+    }
+}
+
+/// basis around the surface normal facing the ray
+fn shading_basis(collision: &CollisionRecord) -> Onb {
+    Onb::from_w(collision.normal())
+}
+
+fn to_local(basis: &Onb, w: Vec3) -> Vec3 {
+    Vec3::new(Vec3::dot(w, basis.u), Vec3::dot(w, basis.v), Vec3::dot(w, basis.w))
+}
+
+/// Reflectance of a conductor with complex refractive index eta + ik, for a
+/// single wavelength (relative to the medium the light arrives through)
+fn fresnel_conductor(cos: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos * cos;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rs + rp)
+}
+
+/// Reflectance of the interface between two dielectrics, `eta` is the
+/// refractive index on the far side relative to the side the light arrives
+/// through. One under total internal reflection
+fn fresnel_dielectric(cos: f64, eta: f64) -> f64 {
+    let sin2_t = (1.0 - cos * cos) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos - eta * cos_t) / (cos + eta * cos_t);
+    let rp = (eta * cos - cos_t) / (eta * cos + cos_t);
+
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Complex refractive index of a conductor, eta + ik, for red, green and blue
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum ComplexIor {
+    Gold,
+    Copper,
+    Aluminium,
+    Custom { eta: Color, k: Color },
+}
+
+impl ComplexIor {
+    /// (eta, k)
+    pub fn eta_k(&self) -> (Color, Color) {
+        // measured spectra integrated against the sRGB primaries
+        match self {
+            ComplexIor::Gold      => (Color::new(0.143119, 0.374957, 1.442479), Color::new(3.983160, 2.385721, 1.603215)),
+            ComplexIor::Copper    => (Color::new(0.200438, 0.924033, 1.102212), Color::new(3.912949, 2.452848, 2.142188)),
+            ComplexIor::Aluminium => (Color::new(1.657460, 0.880369, 0.521229), Color::new(9.223869, 6.269523, 4.837001)),
+            ComplexIor::Custom { eta, k } => (*eta, *k),
+        }
+    }
+}
+
+/// Rough metal. Roughness [0, 1] is how spread out the facet normals are, at
+/// 0 the surface is a perfect mirror. Unlike `Metal`, the color comes from
+/// the metal's refractive index, and changes towards grazing angles
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Conductor {
+    pub(crate) ior: ComplexIor,
+    pub(crate) roughness: f64,
+}
+
+impl Conductor {
+    pub fn new(ior: ComplexIor, roughness: f64) -> Conductor {
+        Conductor { ior, roughness }
+    }
+
+    pub fn gold(roughness: f64) -> Conductor { Conductor::new(ComplexIor::Gold, roughness) }
+    pub fn copper(roughness: f64) -> Conductor { Conductor::new(ComplexIor::Copper, roughness) }
+    pub fn aluminium(roughness: f64) -> Conductor { Conductor::new(ComplexIor::Aluminium, roughness) }
+
+    fn fresnel(&self, cos: f64) -> Color {
+        let (eta, k) = self.ior.eta_k();
+
+        Color::new(
+            fresnel_conductor(cos, eta.red, k.red),
+            fresnel_conductor(cos, eta.green, k.green),
+            fresnel_conductor(cos, eta.blue, k.blue),
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn sample(&self, ray: Ray, collision: &CollisionRecord) -> Option<BsdfSample> {
+        // ray cannot escape
+        if collision.face == Face::Inner {
+            return None;
+        }
+
+        let basis = shading_basis(collision);
+        let wo = to_local(&basis, -ray.direction);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let ggx = Ggx::new(self.roughness);
+        if ggx.is_smooth() {
+            return Some(BsdfSample {
+                direction: reflect(ray.direction, collision.normal()),
+                weight: self.fresnel(wo.z),
+                pdf: 0.0,
+                specular: true,
+            });
+        }
+
+        let h = ggx.sample_visible(wo);
+        let cos = Vec3::dot(wo, h);
+        let wi = h * (2.0 * cos) - wo;
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: basis.local(wi.x, wi.y, wi.z),
+            weight: self.fresnel(cos) * (ggx.g2(wo, wi) / ggx.g1(wo)),
+            pdf: ggx.g1(wo) * ggx.d(h) / (4.0 * wo.z),
+            specular: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, collision: &CollisionRecord, direction: Vec3) -> Color {
+        let ggx = Ggx::new(self.roughness);
+        if collision.face == Face::Inner || ggx.is_smooth() {
+            return Color::black();
+        }
+
+        let basis = shading_basis(collision);
+        let wo = to_local(&basis, -ray.direction);
+        let wi = to_local(&basis, direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::black();
+        }
+
+        let h = (wo + wi).normalize();
+
+        // D * G * F / (4 * cos_o * cos_i), times cos_i
+        self.fresnel(Vec3::dot(wo, h)) * (ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z))
+    }
+
+    fn pdf(&self, ray: Ray, collision: &CollisionRecord, direction: Vec3) -> f64 {
+        let ggx = Ggx::new(self.roughness);
+        if collision.face == Face::Inner || ggx.is_smooth() {
+            return 0.0;
+        }
+
+        let basis = shading_basis(collision);
+        let wo = to_local(&basis, -ray.direction);
+        let wi = to_local(&basis, direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let h = (wo + wi).normalize();
+        ggx.g1(wo) * ggx.d(h) / (4.0 * wo.z)
+    }
+}
+
+/// Frosted glass. Reflects or refracts off of facets with an exact Fresnel
+/// term, roughness [0, 1] as for `Conductor`. At 0 it behaves like `Dielectric`
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct RoughDielectric {
+    pub(crate) ref_index: f64,
+    pub(crate) roughness: f64,
+}
+
+/// How a pair of directions scatters off of a rough dielectric, in the
+/// surface's basis
+enum Scattering {
+    Reflection { h: Vec3 },
+    Transmission { h: Vec3 },
+}
+
+impl RoughDielectric {
+    pub fn new(ref_index: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric { ref_index, roughness }
+    }
+
+    /// refractive index on the far side of the surface relative to the ray's side
+    fn eta(&self, face: &Face) -> f64 {
+        match face {
+            Face::Outer => self.ref_index,
+            Face::Inner => 1.0 / self.ref_index,
+        }
+    }
+
+    /// the facet normal that scatters `wo` into `wi`, None if there isn't one
+    fn scattering(wo: Vec3, wi: Vec3, eta: f64) -> Option<Scattering> {
+        if wi.z > 0.0 {
+            return Some(Scattering::Reflection { h: (wo + wi).normalize() });
+        }
+
+        // generalized half vector, facing the ray's side
+        let h = wo + wi * eta;
+        if h.length_squared() == 0.0 {
+            return None;
+        }
+        let h = h.normalize();
+        let h = if h.z < 0.0 { -h } else { h };
+
+        // both directions on the facet's own sides
+        match Vec3::dot(wo, h) > 0.0 && Vec3::dot(wi, h) < 0.0 {
+            true  => Some(Scattering::Transmission { h }),
+            false => None,
+        }
+    }
+
+    /// (BSDF * cos, pdf) of scattering `wo` into `wi`
+    fn evaluate(&self, ggx: Ggx, wo: Vec3, wi: Vec3, eta: f64) -> (f64, f64) {
+        match RoughDielectric::scattering(wo, wi, eta) {
+            Some(Scattering::Reflection { h }) => {
+                let f = fresnel_dielectric(Vec3::dot(wo, h), eta);
+                let d = ggx.d(h);
+
+                (f * d * ggx.g2(wo, wi) / (4.0 * wo.z), f * ggx.g1(wo) * d / (4.0 * wo.z))
+            }
+            Some(Scattering::Transmission { h }) => {
+                let cos_o = Vec3::dot(wo, h);
+                let cos_i = Vec3::dot(wi, h);
+                let f = fresnel_dielectric(cos_o, eta);
+
+                // change of variables from facet normals to refracted directions
+                let denominator = cos_o + eta * cos_i;
+                let jacobian = eta * eta * cos_i.abs() / (denominator * denominator);
+
+                // radiance isn't scaled by eta², as for `Dielectric`
+                let value = (1.0 - f) * ggx.d(h) * ggx.g2(wo, wi) * cos_o * jacobian / wo.z;
+                let pdf = (1.0 - f) * ggx.pdf_visible(wo, h) * jacobian;
+
+                (value, pdf)
+            }
+            None => (0.0, 0.0),
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn sample(&self, ray: Ray, collision: &CollisionRecord) -> Option<BsdfSample> {
+        let ggx = Ggx::new(self.roughness);
+        if ggx.is_smooth() {
+            return Dielectric::new(self.ref_index).sample(ray, collision);
+        }
+
+        let basis = shading_basis(collision);
+        let wo = to_local(&basis, -ray.direction);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let eta = self.eta(&collision.face);
+        let h = ggx.sample_visible(wo);
+        let cos = Vec3::dot(wo, h);
+
+        let reflect = fresnel_dielectric(cos, eta) > random_float();
+        let wi = match reflect {
+            true  => h * (2.0 * cos) - wo,
+            false => {
+                let cos_t = (1.0 - (1.0 - cos * cos) / (eta * eta)).sqrt();
+                (-wo * (1.0 / eta) + h * (cos / eta - cos_t)).normalize()
+            }
+        };
+
+        // scattered to the wrong side of the surface by a steep facet
+        if reflect != (wi.z > 0.0) {
+            return None;
+        }
+
+        let (_, pdf) = self.evaluate(ggx, wo, wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: basis.local(wi.x, wi.y, wi.z),
+            weight: Color::white() * (ggx.g2(wo, wi) / ggx.g1(wo)),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, collision: &CollisionRecord, direction: Vec3) -> Color {
+        let ggx = Ggx::new(self.roughness);
+        if ggx.is_smooth() {
+            return Color::black();
+        }
+
+        let basis = shading_basis(collision);
+        let wo = to_local(&basis, -ray.direction);
+        if wo.z <= 0.0 {
+            return Color::black();
+        }
+
+        let (value, _) = self.evaluate(ggx, wo, to_local(&basis, direction), self.eta(&collision.face));
+        Color::white() * value
+    }
+
+    fn pdf(&self, ray: Ray, collision: &CollisionRecord, direction: Vec3) -> f64 {
+        let ggx = Ggx::new(self.roughness);
+        if ggx.is_smooth() {
+            return 0.0;
+        }
+
+        let basis = shading_basis(collision);
+        let wo = to_local(&basis, -ray.direction);
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        let (_, pdf) = self.evaluate(ggx, wo, to_local(&basis, direction), self.eta(&collision.face));
+        pdf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::UV;
+    use crate::utils::{random_unit_vector, seed_random};
+
+    /// surface at the origin facing +z, hit from the given side
+    fn collision(face: Face) -> CollisionRecord {
+        let s_normal = match face {
+            Face::Outer => Vec3::new(0.0, 0.0, 1.0),
+            Face::Inner => Vec3::new(0.0, 0.0, -1.0),
+        };
+
+        CollisionRecord { point: Vec3::zeros(), s_normal, t: 1.0, uv: UV::new(0.0, 0.0), face }
+    }
+
+    /// arriving from above the surface at roughly 40 degrees
+    fn incoming() -> Ray {
+        Ray::new(Vec3::new(0.6, 0.3, 1.0), Vec3::new(-0.6, -0.3, -1.0))
+    }
+
+    /// checks that the sampled weights match eval / pdf, and the sampled pdfs match pdf
+    fn check_samples<M: Material>(material: &M, face: Face) {
+        let (ray, collision) = (incoming(), collision(face));
+        let mut sampled = 0;
+
+        for _ in 0..2000 {
+            let Some(sample) = material.sample(ray, &collision) else { continue };
+            sampled += 1;
+
+            let eval = material.eval(ray, &collision, sample.direction);
+            let pdf = material.pdf(ray, &collision, sample.direction);
+
+            assert!(!sample.specular);
+            assert!((sample.pdf - pdf).abs() <= 1e-6 * pdf.max(1.0));
+            for (weight, value) in [
+                (sample.weight.red, eval.red),
+                (sample.weight.green, eval.green),
+                (sample.weight.blue, eval.blue),
+            ] {
+                assert!((weight - value / pdf).abs() <= 1e-6 * weight.max(1.0));
+            }
+        }
+
+        assert!(sampled > 1000);
+    }
+
+    /// integral of the pdf over the sphere of directions, by uniform sampling
+    fn pdf_integral<M: Material>(material: &M, face: Face) -> f64 {
+        let (ray, collision) = (incoming(), collision(face));
+        let samples = 200000;
+
+        let sum: f64 = (0..samples)
+            .map(|_| material.pdf(ray, &collision, random_unit_vector()))
+            .sum();
+
+        sum / samples as f64 * 4.0 * std::f64::consts::PI
+    }
+
+    #[test]
+    fn conductor_normal_incidence() {
+        for (eta, k) in [(0.2, 3.9), (1.66, 9.22), (1.5, 0.0)] {
+            let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+            assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-12);
+        }
+
+        // mirrors at grazing angles
+        assert!((fresnel_conductor(1e-6, 0.2, 3.9) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn dielectric_fresnel() {
+        let n = 1.5f64;
+        assert!((fresnel_dielectric(1.0, n) - ((n - 1.0) / (n + 1.0)).powi(2)).abs() < 1e-12);
+        assert!((fresnel_dielectric(1.0, 1.0 / n) - ((n - 1.0) / (n + 1.0)).powi(2)).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.5, 1.0), 0.0);
+
+        // total internal reflection past the critical angle, sin = 1 / 1.5
+        assert_eq!(fresnel_dielectric(0.7, 1.0 / n), 1.0);
+        assert_eq!(fresnel_dielectric(0.0, 1.0 / n), 1.0);
+        assert!(fresnel_dielectric(0.8, 1.0 / n) < 1.0);
+    }
+
+    #[test]
+    fn conductor_samples_match_eval() {
+        seed_random(1);
+        check_samples(&Conductor::gold(0.3), Face::Outer);
+        check_samples(&Conductor::aluminium(0.8), Face::Outer);
+    }
+
+    #[test]
+    fn rough_dielectric_samples_match_eval() {
+        seed_random(2);
+        check_samples(&RoughDielectric::new(1.5, 0.3), Face::Outer);
+        check_samples(&RoughDielectric::new(1.5, 0.6), Face::Inner);
+    }
+
+    #[test]
+    fn pdfs_integrate_to_at_most_one() {
+        seed_random(3);
+
+        for integral in [
+            pdf_integral(&Conductor::copper(0.5), Face::Outer),
+            pdf_integral(&RoughDielectric::new(1.5, 0.5), Face::Outer),
+            pdf_integral(&RoughDielectric::new(1.5, 0.5), Face::Inner),
+        ] {
+            assert!(0.5 < integral && integral <= 1.03, "pdf integrates to {}", integral);
+        }
+    }
+
+    #[test]
+    fn smooth_conductor_is_a_mirror() {
+        let sample = Conductor::gold(0.0).sample(incoming(), &collision(Face::Outer)).unwrap();
+
+        assert!(sample.specular);
+        assert!((sample.direction - Vec3::new(-0.6, -0.3, 1.0).normalize()).length() < 1e-12);
+        assert_eq!(Conductor::gold(0.0).pdf(incoming(), &collision(Face::Outer), sample.direction), 0.0);
+    }
+}
@@ -0,0 +1,338 @@
+//! Values keyframed over time, and shapes that move while the camera's
+//! shutter is open.
+
+use std::sync::Arc;
+
+use crate::aabb::{AABB, Boundable};
+use crate::collisions::{CollisionRecord, Collidable};
+use crate::image::Color;
+use crate::ray::Ray;
+use crate::shape::ShapeType;
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// Values that can be interpolated between keyframes
+pub trait Lerp: Copy {
+    /// `a` when `t` is 0, `b` when `t` is 1
+    fn lerp(a: Self, b: Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(a: f64, b: f64, t: f64) -> f64 { a + (b - a) * t }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 { a + (b - a) * t }
+}
+
+impl Lerp for Color {
+    fn lerp(a: Color, b: Color, t: f64) -> Color { a * (1.0 - t) + b * t }
+}
+
+impl Lerp for Transform {
+    /// each of the translation, rotation and scale separately
+    fn lerp(a: Transform, b: Transform, t: f64) -> Transform {
+        Transform::new(
+            Vec3::lerp(a.translation_vector(), b.translation_vector(), t),
+            Vec3::lerp(a.rotation_degrees(), b.rotation_degrees(), t),
+            Vec3::lerp(a.scale(), b.scale(), t),
+        )
+    }
+}
+
+/// How a value changes between a keyframe and the next one
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Curve {
+    /// at a constant rate
+    #[default]
+    Linear,
+    /// holds the keyframe's value until the next keyframe
+    Step,
+    /// starts slowly and speeds up
+    EaseIn,
+    /// slows down towards the next keyframe
+    EaseOut,
+    /// starts and ends slowly (smoothstep)
+    EaseInOut,
+}
+
+impl Curve {
+    /// progress towards the next keyframe, `t` is the fraction of the time
+    /// between the keyframes that has passed
+    pub fn apply(&self, t: f64) -> f64 {
+        match self {
+            Curve::Linear    => t,
+            Curve::Step      => 0.0,
+            Curve::EaseIn    => t * t,
+            Curve::EaseOut   => t * (2.0 - t),
+            Curve::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+    /// interpolation towards the next keyframe
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(time: f64, value: T) -> Keyframe<T> {
+        Keyframe { time, value, curve: Curve::Linear }
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Keyframe<T> {
+        self.curve = curve;
+        self
+    }
+}
+
+/// Value changing over time, interpolated between keyframes according to
+/// their curves. Before the first keyframe and after the last one the value
+/// is held.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<Keyframe<T>>", into = "Vec<Keyframe<T>>")]
+#[serde(bound(serialize = "T: Serialize + Clone", deserialize = "T: Deserialize<'de>"))]
+pub struct Track<T> {
+    /// sorted by time
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T> From<Vec<Keyframe<T>>> for Track<T> {
+    fn from(keyframes: Vec<Keyframe<T>>) -> Track<T> {
+        Track::new(keyframes)
+    }
+}
+
+impl<T> From<Track<T>> for Vec<Keyframe<T>> {
+    fn from(track: Track<T>) -> Vec<Keyframe<T>> {
+        track.keyframes
+    }
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self { Track { keyframes: vec![] } }
+}
+
+impl<T> Track<T> {
+    pub fn new(mut keyframes: Vec<Keyframe<T>>) -> Track<T> {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Track { keyframes }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] { &self.keyframes }
+    pub fn is_empty(&self) -> bool { self.keyframes.is_empty() }
+}
+
+impl<T: Lerp> Track<T> {
+    /// value at `time`, None if there are no keyframes
+    pub fn value_at(&self, time: f64) -> Option<T> {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+
+        match (self.keyframes.get(next.wrapping_sub(1)), self.keyframes.get(next)) {
+            (Some(a), Some(b)) => {
+                let t = a.curve.apply((time - a.time) / (b.time - a.time));
+                Some(T::lerp(a.value, b.value, t))
+            }
+            (Some(a), None)    => Some(a.value),
+            (None, Some(b))    => Some(b.value),
+            (None, None)       => None,
+        }
+    }
+}
+
+/// Shape moved by a keyframed translation, e.g. a falling ball. Rays collide
+/// with the shape where it is at the ray's time.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Moving {
+    shape: Arc<ShapeType>,
+    translation: Track<Vec3>,
+}
+
+impl Moving {
+    pub fn new<S: Into<Arc<ShapeType>>>(shape: S, translation: Track<Vec3>) -> Moving {
+        Moving { shape: shape.into(), translation }
+    }
+
+    /// moves the shape by `displacement` at a constant speed, from `start_time`
+    /// to `end_time`
+    pub fn linear<S>(shape: S, displacement: Vec3, start_time: f64, end_time: f64) -> Moving
+    where
+        S: Into<Arc<ShapeType>>
+    {
+        Moving::new(shape, Track::new(vec![
+            Keyframe::new(start_time, Vec3::zeros()),
+            Keyframe::new(end_time, displacement),
+        ]))
+    }
+
+    pub fn shape(&self) -> &Arc<ShapeType> { &self.shape }
+    pub fn translation(&self) -> &Track<Vec3> { &self.translation }
+
+    /// how far the shape has moved at `time`
+    pub fn offset_at(&self, time: f64) -> Vec3 {
+        self.translation.value_at(time).unwrap_or(Vec3::zeros())
+    }
+}
+
+impl Collidable for Moving {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let offset = self.offset_at(ray.time);
+        let record = self.shape.collide(Ray::at_time(ray.origin - offset, ray.direction, ray.time))?;
+
+        Some(CollisionRecord { point: record.point + offset, ..record })
+    }
+}
+
+impl Boundable for Moving {
+    /// the shape's bound swept along its path. The path is straight between
+    /// keyframes, so the bounds at the keyframes contain it
+    fn bound(&self) -> AABB {
+        let bound = self.shape.bound();
+        if !bound.is_finite() || self.translation.is_empty() {
+            return bound;
+        }
+
+        self.translation
+            .keyframes()
+            .iter()
+            .map(|k| AABB::new(bound.min() + k.value, bound.max() + k.value))
+            .fold(AABB::empty(), AABB::surrounding)
+    }
+}
+
+impl From<Moving> for ShapeType {
+    fn from(moving: Moving) -> Self {
+        ShapeType::Moving(moving)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Sphere;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    fn track(keyframes: &[(f64, f64)]) -> Track<f64> {
+        Track::new(keyframes.iter().map(|&(time, value)| Keyframe::new(time, value)).collect())
+    }
+
+    #[test]
+    fn holds_value_outside_keyframes() {
+        let track = track(&[(1.0, 2.0), (3.0, 6.0)]);
+
+        assert_eq!(track.value_at(-5.0), Some(2.0));
+        assert_eq!(track.value_at(1.0), Some(2.0));
+        assert_eq!(track.value_at(2.0), Some(4.0));
+        assert_eq!(track.value_at(3.0), Some(6.0));
+        assert_eq!(track.value_at(10.0), Some(6.0));
+        assert_eq!(Track::<f64>::default().value_at(0.0), None);
+    }
+
+    #[test]
+    fn keyframes_are_sorted() {
+        let track = track(&[(3.0, 6.0), (1.0, 2.0)]);
+
+        assert_eq!(track.value_at(2.0), Some(4.0));
+    }
+
+    #[test]
+    fn step_and_ease_in_out() {
+        let step = Track::new(vec![
+            Keyframe::new(0.0, 1.0).with_curve(Curve::Step),
+            Keyframe::new(1.0, 5.0),
+        ]);
+        assert_eq!(step.value_at(0.0), Some(1.0));
+        assert_eq!(step.value_at(0.99), Some(1.0));
+        assert_eq!(step.value_at(1.0), Some(5.0));
+
+        let ease = Track::new(vec![
+            Keyframe::new(0.0, 0.0).with_curve(Curve::EaseInOut),
+            Keyframe::new(2.0, 1.0),
+        ]);
+        assert_eq!(ease.value_at(1.0), Some(0.5));
+        // slower than linear near both ends
+        assert!(ease.value_at(0.2).unwrap() < 0.1);
+        assert!(ease.value_at(1.8).unwrap() > 0.9);
+    }
+
+    #[test]
+    fn duplicate_times_jump() {
+        let track = track(&[(0.0, 0.0), (1.0, 1.0), (1.0, 5.0), (2.0, 5.0)]);
+
+        assert_eq!(track.value_at(0.5), Some(0.5));
+        assert_eq!(track.value_at(1.0), Some(5.0));
+        assert_eq!(track.value_at(1.5), Some(5.0));
+    }
+
+    #[test]
+    fn bound_covers_every_keyframe() {
+        let moving = Moving::new(
+            ShapeType::Sphere(Sphere::new(Vec3::zeros(), 1.0)),
+            Track::new(vec![
+                Keyframe::new(0.0, Vec3::zeros()),
+                Keyframe::new(1.0, Vec3::new(10.0, 0.0, 0.0)),
+                Keyframe::new(2.0, Vec3::new(10.0, 5.0, -3.0)),
+            ]),
+        );
+
+        let bound = moving.bound();
+        assert!(close(bound.min(), Vec3::new(-1.0, -1.0, -4.0)));
+        assert!(close(bound.max(), Vec3::new(11.0, 6.0, 1.0)));
+    }
+
+    #[test]
+    fn collides_where_shape_is_at_ray_time() {
+        let moving = Moving::linear(
+            ShapeType::Sphere(Sphere::new(Vec3::zeros(), 1.0)),
+            Vec3::new(10.0, 0.0, 0.0),
+            0.0,
+            1.0,
+        );
+        let origin = Vec3::new(5.0, 0.0, -5.0);
+        let direction = Vec3::new(0.0, 0.0, 1.0);
+
+        assert!(moving.collide(Ray::at_time(origin, direction, 0.0)).is_none());
+
+        let record = moving.collide(Ray::at_time(origin, direction, 0.5)).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-9);
+        assert!(close(record.point, Vec3::new(5.0, 0.0, -1.0)));
+
+        let record = moving.collide(Ray::at_time(Vec3::new(0.0, 0.0, -5.0), direction, 0.0)).unwrap();
+        assert!(close(record.point, Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn curves_start_and_end_at_keyframes() {
+        for curve in [Curve::Linear, Curve::EaseIn, Curve::EaseOut, Curve::EaseInOut] {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert_eq!(curve.apply(1.0), 1.0);
+        }
+
+        assert!(Curve::EaseIn.apply(0.5) < 0.5);
+        assert!(Curve::EaseOut.apply(0.5) > 0.5);
+    }
+
+    #[test]
+    fn lerps_colors_and_transforms() {
+        let color = Color::lerp(Color::black(), Color::new(1.0, 0.5, 0.0), 0.5);
+        assert_eq!((color.red, color.green, color.blue), (0.5, 0.25, 0.0));
+
+        let a = Transform::identity();
+        let b = Transform::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 90.0, 0.0), Vec3::new(3.0, 1.0, 1.0));
+        let halfway = Transform::lerp(a, b, 0.5);
+
+        assert!(close(halfway.translation_vector(), Vec3::new(1.0, 0.0, 0.0)));
+        assert!(close(halfway.rotation_degrees(), Vec3::new(0.0, 45.0, 0.0)));
+        assert!(close(halfway.scale(), Vec3::new(2.0, 1.0, 1.0)));
+    }
+}
@@ -0,0 +1,461 @@
+//! Wavefront OBJ (and MTL) importer.
+//!
+//! Faces are triangulated and grouped by material, producing one `Mesh`
+//! element per material used in the file. MTL materials are approximated
+//! with the materials the ray tracer supports:
+//! - emissive (`Ke`) materials become `DiffuseLight`
+//! - transparent (`d` < 1, `Tr` > 0 or a refractive `illum`) materials become `Dielectric`
+//! - specular (`Ks` brighter than `Kd`, or `illum 3`) materials become `Metal`
+//! - everything else becomes `Lambertian`
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::image::Color;
+use crate::material::{MaterialType, Lambertian, Metal, Dielectric, DiffuseLight};
+use crate::mesh::Mesh;
+use crate::scene::Element;
+use crate::shape::{ShapeType, Triangle, UV};
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io { path: PathBuf, error: std::io::Error },
+    Parse { file: String, line: usize, message: String },
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+            ObjError::Parse { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Load an OBJ file, along with any MTL files it references (resolved relative to the OBJ file)
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Element>, ObjError> {
+    let path = path.as_ref();
+    let source = read(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    for library in material_libraries(&source) {
+        let mtl_path = directory.join(&library);
+        let mtl_source = read(&mtl_path)?;
+
+        materials.extend(parse_mtl(&mtl_source, &library)?);
+    }
+
+    parse_obj(&source, &path.display().to_string(), &materials)
+}
+
+/// Parse OBJ and MTL sources that are already in memory (e.g. uploaded in the browser)
+pub fn load_obj_from_str(obj: &str, mtl: Option<&str>) -> Result<Vec<Element>, ObjError> {
+    let materials = match mtl {
+        Some(mtl) => parse_mtl(mtl, "<mtl>")?,
+        None      => HashMap::new(),
+    };
+
+    parse_obj(obj, "<obj>", &materials)
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|error| ObjError::Io { path: path.to_path_buf(), error })
+}
+
+/// names of the MTL files referenced by `mtllib` statements
+fn material_libraries(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("mtllib "))
+        .flat_map(|names| names.split_whitespace().map(String::from))
+        .collect()
+}
+
+fn default_material() -> MaterialType {
+    MaterialType::Lambertian(Lambertian::new(Color::new(0.8, 0.8, 0.8).into()))
+}
+
+fn parse_floats<const N: usize>(args: &[&str], file: &str, line: usize) -> Result<[f64; N], ObjError> {
+    let mut values = [0f64; N];
+
+    if args.len() < N {
+        return Err(ObjError::Parse {
+            file: file.to_string(),
+            line,
+            message: format!("expected {} numbers, found {}", N, args.len()),
+        });
+    }
+
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse().map_err(|_| ObjError::Parse {
+            file: file.to_string(),
+            line,
+            message: format!("invalid number '{}'", arg),
+        })?;
+    }
+
+    Ok(values)
+}
+
+/// resolve a (1-based, possibly negative) OBJ index into a list of `count` items
+fn resolve_index(index: &str, count: usize, file: &str, line: usize) -> Result<usize, ObjError> {
+    let error = |message: String| ObjError::Parse { file: file.to_string(), line, message };
+
+    let i: i64 = index.parse().map_err(|_| error(format!("invalid index '{}'", index)))?;
+    let resolved = if i < 0 { count as i64 + i } else { i - 1 };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(error(format!("index {} out of range", i)));
+    }
+
+    Ok(resolved as usize)
+}
+
+/// a vertex of a face: position, texture coordinate and normal indices
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+fn parse_obj(source: &str, file: &str, materials: &HashMap<String, MaterialType>) -> Result<Vec<Element>, ObjError> {
+    let mut positions: Vec<Vec3> = vec![];
+    let mut uvs: Vec<UV> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+
+    // triangles grouped by material name, in order of first use
+    let mut groups: Vec<(Option<String>, Vec<Triangle>)> = vec![(None, vec![])];
+    let mut current = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let args = tokens.collect::<Vec<&str>>();
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(&args, file, line_number)?;
+                positions.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                // the v coordinate is optional
+                let u = parse_floats::<1>(&args, file, line_number)?[0];
+                let v = match args.get(1) {
+                    Some(_) => parse_floats::<2>(&args, file, line_number)?[1],
+                    None    => 0.0,
+                };
+                uvs.push(UV::new(u, v));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(&args, file, line_number)?;
+                normals.push(Vec3::new(x, y, z));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ObjError::Parse {
+                        file: file.to_string(),
+                        line: line_number,
+                        message: "faces need at least 3 vertices".to_string(),
+                    });
+                }
+
+                let mut face: Vec<FaceVertex> = vec![];
+
+                for arg in &args {
+                    let mut indices = arg.split('/');
+                    let position = resolve_index(indices.next().unwrap_or(""), positions.len(), file, line_number)?;
+                    let uv = match indices.next() {
+                        Some(index) if !index.is_empty() => Some(resolve_index(index, uvs.len(), file, line_number)?),
+                        _ => None,
+                    };
+                    let normal = match indices.next() {
+                        Some(index) if !index.is_empty() => Some(resolve_index(index, normals.len(), file, line_number)?),
+                        _ => None,
+                    };
+
+                    face.push((position, uv, normal));
+                }
+
+                // triangulate the (assumed convex) polygon as a fan
+                for j in 1..face.len() - 1 {
+                    let corners = [face[0], face[j], face[j + 1]];
+
+                    let vertices = corners.map(|(p, _, _)| positions[p]);
+                    let triangle_uvs = match corners.map(|(_, t, _)| t) {
+                        [Some(a), Some(b), Some(c)] => Some([uvs[a], uvs[b], uvs[c]]),
+                        _ => None,
+                    };
+                    let triangle_normals = match corners.map(|(_, _, n)| n) {
+                        [Some(a), Some(b), Some(c)] => Some([normals[a], normals[b], normals[c]]),
+                        _ => None,
+                    };
+
+                    groups[current].1.push(Triangle::with_attributes(vertices, triangle_normals, triangle_uvs));
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+
+                current = match groups.iter().position(|(group, _)| group.as_deref() == Some(name.as_str())) {
+                    Some(index) => index,
+                    None => {
+                        groups.push((Some(name), vec![]));
+                        groups.len() - 1
+                    }
+                };
+            }
+            // grouping, smoothing groups, lines and points don't affect rendering
+            _ => {}
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .filter(|(_, triangles)| !triangles.is_empty())
+        .map(|(name, triangles)| {
+            let material = name
+                .and_then(|name| materials.get(&name).cloned())
+                .unwrap_or_else(default_material);
+
+            Element::new(material, ShapeType::Mesh(Mesh::new(triangles)))
+        })
+        .collect())
+}
+
+/// the subset of MTL properties used to choose a material
+struct MtlProperties {
+    diffuse: Color,
+    specular: Color,
+    emissive: Color,
+    shininess: f64,
+    dissolve: f64,
+    ref_index: f64,
+    illum: u32,
+}
+
+impl Default for MtlProperties {
+    fn default() -> Self {
+        MtlProperties {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::black(),
+            emissive: Color::black(),
+            shininess: 0.0,
+            dissolve: 1.0,
+            ref_index: 1.5,
+            illum: 2,
+        }
+    }
+}
+
+fn max_channel(color: Color) -> f64 {
+    f64::max(color.red, f64::max(color.green, color.blue))
+}
+
+impl From<MtlProperties> for MaterialType {
+    fn from(mtl: MtlProperties) -> MaterialType {
+        let emission = max_channel(mtl.emissive);
+        let transparent = mtl.dissolve < 1.0 || matches!(mtl.illum, 4 | 6 | 7 | 9);
+        let specular = mtl.illum == 3 || max_channel(mtl.specular) > max_channel(mtl.diffuse);
+
+        if emission > 0.0 {
+            MaterialType::DiffuseLight(DiffuseLight::new(mtl.emissive * (1.0 / emission), emission))
+        } else if transparent {
+            MaterialType::Dielectric(Dielectric::new(mtl.ref_index))
+        } else if specular {
+            // map the phong exponent to an approximate roughness
+            let fuzz = f64::min(1.0, (2.0 / (mtl.shininess + 2.0)).sqrt());
+            MaterialType::Metal(Metal::new(mtl.specular.into(), fuzz))
+        } else {
+            MaterialType::Lambertian(Lambertian::new(mtl.diffuse.into()))
+        }
+    }
+}
+
+fn parse_mtl(source: &str, file: &str) -> Result<HashMap<String, MaterialType>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlProperties)> = None;
+
+    let color = |args: &[&str], line: usize| -> Result<Color, ObjError> {
+        let [r, g, b] = parse_floats(args, file, line)?;
+        Ok(Color::new(r, g, b))
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let args = tokens.collect::<Vec<&str>>();
+
+        if keyword == "newmtl" {
+            if let Some((name, properties)) = current.take() {
+                materials.insert(name, properties.into());
+            }
+            current = Some((args.join(" "), MtlProperties::default()));
+            continue;
+        }
+
+        let properties = match current.as_mut() {
+            Some((_, properties)) => properties,
+            None => return Err(ObjError::Parse {
+                file: file.to_string(),
+                line: line_number,
+                message: format!("'{}' before any 'newmtl'", keyword),
+            }),
+        };
+
+        match keyword {
+            "Kd" => properties.diffuse = color(&args, line_number)?,
+            "Ks" => properties.specular = color(&args, line_number)?,
+            "Ke" => properties.emissive = color(&args, line_number)?,
+            "Ns" => properties.shininess = parse_floats::<1>(&args, file, line_number)?[0],
+            "Ni" => properties.ref_index = parse_floats::<1>(&args, file, line_number)?[0],
+            "d"  => properties.dissolve = parse_floats::<1>(&args, file, line_number)?[0],
+            "Tr" => properties.dissolve = 1.0 - parse_floats::<1>(&args, file, line_number)?[0],
+            "illum" => properties.illum = parse_floats::<1>(&args, file, line_number)?[0] as u32,
+            // texture maps and other properties aren't supported
+            _ => {}
+        }
+    }
+
+    if let Some((name, properties)) = current.take() {
+        materials.insert(name, properties.into());
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::Collidable;
+    use crate::ray::Ray;
+
+    fn triangles(element: &Element) -> &[Triangle] {
+        match &element.shape {
+            ShapeType::Mesh(mesh) => mesh.triangles(),
+            _ => panic!("expected a mesh"),
+        }
+    }
+
+    /// the first triangle, hit from above at (0.25, 0.25)
+    fn hit(element: &Element) -> crate::collisions::CollisionRecord {
+        let ray = Ray::new(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        triangles(element)[0].collide(ray).unwrap()
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let elements = load_obj_from_str(&format!("{}v 5 5 5\nf -4 -3 -2\n", TRIANGLE), None).unwrap();
+
+        assert_eq!(elements.len(), 1);
+        let [a, b, c] = triangles(&elements[0])[0].vertices();
+        assert!(close(a, Vec3::zeros()) && close(b, Vec3::new(1.0, 0.0, 0.0)) && close(c, Vec3::new(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn vertex_texture_and_normal_indices() {
+        let obj = format!("{}vt 0 0\nvt 1 0\nvt 0 1\nvn 0 0.6 0.8\nf 1/1/1 2/2/1 3/3/1\n", TRIANGLE);
+        let record = hit(&load_obj_from_str(&obj, None).unwrap()[0]);
+
+        // the vertex normal and texture coordinates are interpolated
+        assert!(close(record.s_normal, Vec3::new(0.0, 0.6, 0.8)));
+        assert!((record.uv.u() - 0.25).abs() < 1e-9 && (record.uv.v() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn vertex_and_normal_indices() {
+        let obj = format!("{}vn 0 0.6 0.8\nf 1//1 2//1 3//1\n", TRIANGLE);
+        let record = hit(&load_obj_from_str(&obj, None).unwrap()[0]);
+
+        assert!(close(record.s_normal, Vec3::new(0.0, 0.6, 0.8)));
+    }
+
+    #[test]
+    fn polygons_are_triangulated_as_fans() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv -1 0.5 0\nf 1 2 3 4\nf 1 2 3 4 5\n";
+        let elements = load_obj_from_str(obj, None).unwrap();
+        let triangles = triangles(&elements[0]);
+
+        assert_eq!(triangles.len(), 5);
+        // every triangle of a fan shares the first vertex
+        let [a, b, c] = triangles[1].vertices();
+        assert!(close(a, Vec3::zeros()) && close(b, Vec3::new(1.0, 1.0, 0.0)) && close(c, Vec3::new(0.0, 1.0, 0.0)));
+        assert!(triangles.iter().all(|t| close(t.vertices()[0], Vec3::zeros())));
+    }
+
+    #[test]
+    fn mtl_materials_are_approximated() {
+        let mtl = "\
+newmtl lamp
+Ke 4 2 0
+newmtl glass
+d 0.5
+Ni 1.3
+newmtl chrome
+Kd 0.1 0.1 0.1
+Ks 0.9 0.9 0.9
+Ns 200
+newmtl clay
+Kd 0.7 0.3 0.2
+";
+        let obj = format!(
+            "{}usemtl lamp\nf 1 2 3\nusemtl glass\nf 1 2 3\nusemtl chrome\nf 1 2 3\nusemtl clay\nf 1 2 3\nusemtl missing\nf 1 2 3\n",
+            TRIANGLE,
+        );
+        let elements = load_obj_from_str(&obj, Some(mtl)).unwrap();
+
+        assert_eq!(elements.len(), 5);
+        assert!(matches!(elements[0].material, MaterialType::DiffuseLight(_)));
+        assert!(matches!(elements[1].material, MaterialType::Dielectric(_)));
+        assert!(matches!(elements[2].material, MaterialType::Metal(_)));
+        assert!(matches!(elements[3].material, MaterialType::Lambertian(_)));
+        // unknown materials fall back to the default
+        assert!(matches!(elements[4].material, MaterialType::Lambertian(_)));
+    }
+
+    fn parse_error(obj: &str, mtl: Option<&str>) -> (String, usize, String) {
+        match load_obj_from_str(obj, mtl) {
+            Err(ObjError::Parse { file, line, message }) => (file, line, message),
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_line() {
+        let (file, line, message) = parse_error("v 0 0 0\nv 1 x 0\n", None);
+        assert_eq!((file.as_str(), line), ("<obj>", 2));
+        assert_eq!(message, "invalid number 'x'");
+
+        let (_, line, message) = parse_error("v 0 0\n", None);
+        assert_eq!((line, message.as_str()), (1, "expected 3 numbers, found 2"));
+
+        let (_, line, message) = parse_error(&format!("{}f 1 2\n", TRIANGLE), None);
+        assert_eq!((line, message.as_str()), (4, "faces need at least 3 vertices"));
+
+        let (_, line, message) = parse_error(&format!("{}f 1 2 4\n", TRIANGLE), None);
+        assert_eq!((line, message.as_str()), (4, "index 4 out of range"));
+
+        let (_, line, message) = parse_error(&format!("{}f 1 2 -4\n", TRIANGLE), None);
+        assert_eq!((line, message.as_str()), (4, "index -4 out of range"));
+
+        let (file, line, message) = parse_error(TRIANGLE, Some("# colors\nKd 1 1 1\n"));
+        assert_eq!((file.as_str(), line, message.as_str()), ("<mtl>", 2, "'Kd' before any 'newmtl'"));
+    }
+}
@@ -1,5 +1,8 @@
 use crate::vec3::Vec3;
-use crate::utils::{random_float, u32_random_range, t_lerp, random_range, perlin_t_lerp};
+use crate::utils::{random_float, perlin_t_lerp};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 pub struct Perlin {
     x: Box<[u32; Perlin::POINT_COUNT as usize]>,
@@ -11,20 +14,31 @@ pub struct Perlin {
 impl Perlin {
     const POINT_COUNT: u32 = 256;
     pub fn new() -> Perlin {
+        Perlin::generate(&mut random_float)
+    }
+
+    /// the same noise for the same seed
+    pub fn seeded(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        Perlin::generate(&mut || rng.gen())
+    }
+
+    fn generate<R: FnMut() -> f64>(random: &mut R) -> Perlin {
         let mut vectors = Box::new([Vec3::zeros(); Perlin::POINT_COUNT as usize]);
         for i in 0..vectors.len() as usize {
             vectors[i] = Vec3::new(
-                random_range(-1.0, 1.0),
-                random_range(-1.0, 1.0),
-                random_range(-1.0, 1.0),
+                random() * 2.0 - 1.0,
+                random() * 2.0 - 1.0,
+                random() * 2.0 - 1.0,
             ).normalize();
         }
 
         Perlin {
             vectors,
-            x: Perlin::generate_perm(),
-            y: Perlin::generate_perm(),
-            z: Perlin::generate_perm(),
+            x: Perlin::generate_perm(random),
+            y: Perlin::generate_perm(random),
+            z: Perlin::generate_perm(random),
         }
     }
 
@@ -57,14 +71,14 @@ impl Perlin {
         perlin_t_lerp(cube, u, v, w)
     }
 
-    fn generate_perm() -> Box<[u32; Perlin::POINT_COUNT as usize]> {
+    fn generate_perm<R: FnMut() -> f64>(random: &mut R) -> Box<[u32; Perlin::POINT_COUNT as usize]> {
         let mut p = Box::new([0u32; Perlin::POINT_COUNT as usize]);
 
         for i in 0..Perlin::POINT_COUNT as usize {
             p[i] = i as u32;
         }
 
-        Perlin::permute(&mut p, Perlin::POINT_COUNT);
+        Perlin::permute(&mut p, Perlin::POINT_COUNT, random);
 
         p
     }
@@ -87,9 +101,9 @@ impl Perlin {
     /*
     Randomly permute the elements in the array p 
     */
-    fn permute(p: &mut Box<[u32; Perlin::POINT_COUNT as usize]>, n: u32) {
+    fn permute<R: FnMut() -> f64>(p: &mut Box<[u32; Perlin::POINT_COUNT as usize]>, n: u32, random: &mut R) {
         for i in (0..n as usize).rev() {
-            let target = u32_random_range(0, i as u32);
+            let target = (random() * i as f64) as u32;
             let temp = p[i]; 
             p[i] = p[target as usize];
             p[target as usize] = temp;
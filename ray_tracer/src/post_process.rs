@@ -0,0 +1,104 @@
+//! Conversion of rendered (linear, unbounded) radiance to displayable colors.
+//!
+//! Colors are scaled by the exposure, compressed into [0, 1] by a tone
+//! mapping operator and finally encoded with a transfer function. The
+//! default (clamp, gamma 2) matches the output of earlier versions.
+
+use crate::image::{Color, HdrImage, Image, ColorU8};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostProcess {
+    /// in stops, every stop doubles the brightness
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub transfer: Transfer,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        PostProcess {
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            transfer: Transfer::Gamma(2.0),
+        }
+    }
+}
+
+/// Maps radiance to [0, 1]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum ToneMap {
+    /// anything brighter than 1 is clipped
+    #[default]
+    Clamp,
+    /// x / (1 + x), compresses highlights while keeping dark colors unchanged
+    Reinhard,
+    /// filmic curve, approximating the ACES reference rendering transform
+    Aces,
+}
+
+/// Encoding of the tone mapped colors
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Transfer {
+    /// raise to the power of 1 / gamma
+    Gamma(f64),
+    /// the sRGB transfer function
+    Srgb,
+    Linear,
+}
+
+impl Default for Transfer {
+    fn default() -> Self { Transfer::Gamma(2.0) }
+}
+
+impl ToneMap {
+    pub fn apply(&self, x: f64) -> f64 {
+        let x = f64::max(x, 0.0);
+
+        let mapped = match self {
+            ToneMap::Clamp    => x,
+            ToneMap::Reinhard => x / (1.0 + x),
+            // Krzysztof Narkowicz's fit of the ACES curve
+            ToneMap::Aces     => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        };
+
+        f64::min(mapped, 1.0)
+    }
+}
+
+impl Transfer {
+    pub fn apply(&self, x: f64) -> f64 {
+        match *self {
+            Transfer::Gamma(gamma) => x.powf(1.0 / gamma),
+            Transfer::Srgb => {
+                if x <= 0.003_130_8 { 12.92 * x }
+                else { 1.055 * x.powf(1.0 / 2.4) - 0.055 }
+            }
+            Transfer::Linear => x,
+        }
+    }
+}
+
+impl PostProcess {
+    /// displayable color, with channels in [0, 1]
+    pub fn apply(&self, color: Color) -> Color {
+        let scale = f64::powf(2.0, self.exposure);
+        let channel = |x: f64| self.transfer.apply(self.tone_map.apply(x * scale));
+
+        Color::new(channel(color.red), channel(color.green), channel(color.blue))
+    }
+
+    pub fn to_image(&self, hdr: &HdrImage) -> Image {
+        let mut image = Image::new(hdr.height(), hdr.width());
+
+        for row in 0..hdr.height() {
+            for col in 0..hdr.width() {
+                image.set_color(row, col, ColorU8::from(self.apply(hdr.get_color(row, col))));
+            }
+        }
+
+        image
+    }
+}
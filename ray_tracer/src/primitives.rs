@@ -0,0 +1,679 @@
+//! Analytic shapes that are symmetric around an axis: cylinders, cones,
+//! capsules and tori.
+//!
+//! Collisions are computed in a local frame, with the axis along z, and the
+//! UV wraps around the axis (u) and along it (v).
+
+use std::f64::consts::PI;
+
+use crate::aabb::{AABB, Boundable};
+use crate::collisions::{CollisionRecord, Collidable, collision_face, MIN_INTERSECTION_T};
+use crate::ray::Ray;
+use crate::shape::{SurfaceNormal, TextureMap, UV};
+use crate::utils::Onb;
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// coefficients closer to zero than this are treated as zero by the root finders
+const EPSILON: f64 = 1e-9;
+
+/// Frame with its origin on an axis, and z along the axis
+struct AxisFrame {
+    origin: Vec3,
+    onb: Onb,
+}
+
+impl AxisFrame {
+    fn new(origin: Vec3, axis: Vec3) -> AxisFrame {
+        AxisFrame { origin, onb: Onb::from_w(axis) }
+    }
+
+    fn point_to_local(&self, point: Vec3) -> Vec3 {
+        self.vector_to_local(point - self.origin)
+    }
+
+    fn vector_to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(Vec3::dot(v, self.onb.u), Vec3::dot(v, self.onb.v), Vec3::dot(v, self.onb.w))
+    }
+
+    fn vector_to_world(&self, v: Vec3) -> Vec3 {
+        self.onb.local(v.x, v.y, v.z)
+    }
+
+    /// ray in local coordinates, the basis is orthonormal so distances are kept
+    fn ray_to_local(&self, ray: Ray) -> (Vec3, Vec3) {
+        (self.point_to_local(ray.origin), self.vector_to_local(ray.direction))
+    }
+}
+
+/// angle around the z axis, in [0, 1)
+fn azimuth(local: Vec3) -> f64 {
+    (local.y.atan2(local.x) + PI) / (2.0 * PI)
+}
+
+/// box containing a disk of `radius` around `center`, facing along `axis`
+fn disk_bound(center: Vec3, axis: Vec3, radius: f64) -> AABB {
+    let w = axis.normalize();
+    let extent = Vec3::new(
+        radius * (1.0 - w.x * w.x).max(0.0).sqrt(),
+        radius * (1.0 - w.y * w.y).max(0.0).sqrt(),
+        radius * (1.0 - w.z * w.z).max(0.0).sqrt(),
+    ) + Vec3::ones() * 0.0001;
+
+    AABB::new(center - extent, center + extent)
+}
+
+/// the closest valid collision time
+fn closest(candidates: impl Iterator<Item = f64>) -> Option<f64> {
+    candidates
+        .filter(|t| *t > MIN_INTERSECTION_T && t.is_finite())
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// collision with a shape at time `t`, given its normal and UV at a point
+fn record<S: SurfaceNormal + TextureMap>(shape: &S, ray: Ray, t: f64) -> CollisionRecord {
+    let point = ray.position_at(t);
+    let s_normal = shape.surface_normal(point);
+
+    CollisionRecord {
+        point,
+        s_normal,
+        t,
+        uv: shape.map(point),
+        face: collision_face(ray.direction, s_normal),
+    }
+}
+
+/// Cylinder of `radius` around the segment from `base` to `top`, optionally
+/// closed at both ends
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Cylinder {
+    base: Vec3,
+    top: Vec3,
+    radius: f64,
+    #[serde(default = "default_capped")]
+    capped: bool,
+}
+
+fn default_capped() -> bool { true }
+
+impl Cylinder {
+    pub fn new(base: Vec3, top: Vec3, radius: f64, capped: bool) -> Cylinder {
+        Cylinder { base, top, radius, capped }
+    }
+
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.base, self.top - self.base)
+    }
+
+    fn height(&self) -> f64 {
+        (self.top - self.base).length()
+    }
+
+    /// times of every collision along the ray's line, including behind its origin
+    pub(crate) fn crossings(&self, ray: Ray) -> Vec<f64> {
+        let (o, d) = self.frame().ray_to_local(ray);
+        let height = self.height();
+
+        // x^2 + y^2 = r^2
+        let side = solve_quadratic(
+            o.x * o.x + o.y * o.y - self.radius * self.radius,
+            2.0 * (o.x * d.x + o.y * d.y),
+            d.x * d.x + d.y * d.y,
+        );
+        let side = side.into_iter().filter(|t| (0.0..=height).contains(&(o.z + t * d.z)));
+
+        let caps = [0.0, height]
+            .into_iter()
+            .filter(|_| self.capped && d.z.abs() > EPSILON)
+            .map(|z| (z - o.z) / d.z)
+            .filter(|t| {
+                let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+                x * x + y * y <= self.radius * self.radius
+            });
+
+        side.chain(caps).collect()
+    }
+
+    pub fn is_capped(&self) -> bool { self.capped }
+}
+
+impl Collidable for Cylinder {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let t = closest(self.crossings(ray).into_iter())?;
+
+        Some(record(self, ray, t))
+    }
+}
+
+impl Boundable for Cylinder {
+    fn bound(&self) -> AABB {
+        let axis = self.top - self.base;
+
+        AABB::surrounding(disk_bound(self.base, axis, self.radius), disk_bound(self.top, axis, self.radius))
+    }
+}
+
+impl SurfaceNormal for Cylinder {
+    fn surface_normal(&self, point: Vec3) -> Vec3 {
+        let frame = self.frame();
+        let p = frame.point_to_local(point);
+        let height = self.height();
+        let radial = (p.x * p.x + p.y * p.y).sqrt();
+
+        // points on the caps are inside of the side's radius
+        let normal = if self.capped && p.z <= 1e-6 && radial < self.radius * (1.0 - 1e-6) {
+            Vec3::new(0.0, 0.0, -1.0)
+        } else if self.capped && p.z >= height - 1e-6 && radial < self.radius * (1.0 - 1e-6) {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(p.x, p.y, 0.0)
+        };
+
+        frame.vector_to_world(normal).normalize()
+    }
+}
+
+impl TextureMap for Cylinder {
+    fn map(&self, point: Vec3) -> UV {
+        let p = self.frame().point_to_local(point);
+
+        UV::new(azimuth(p), (p.z / self.height()).clamp(0.0, 1.0))
+    }
+}
+
+/// Cone with a base of `radius` at `base`, narrowing to a point at `apex`,
+/// optionally closed at the base
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Cone {
+    base: Vec3,
+    apex: Vec3,
+    radius: f64,
+    #[serde(default = "default_capped")]
+    capped: bool,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, apex: Vec3, radius: f64, capped: bool) -> Cone {
+        Cone { base, apex, radius, capped }
+    }
+
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.base, self.apex - self.base)
+    }
+
+    fn height(&self) -> f64 {
+        (self.apex - self.base).length()
+    }
+
+    /// times of every collision along the ray's line, including behind its origin
+    pub(crate) fn crossings(&self, ray: Ray) -> Vec<f64> {
+        let (o, d) = self.frame().ray_to_local(ray);
+        let height = self.height();
+        // radius shrinks by k for every unit along the axis
+        let k = self.radius / height;
+        let k2 = k * k;
+
+        // x^2 + y^2 = k^2 (h - z)^2
+        let side = solve_quadratic(
+            o.x * o.x + o.y * o.y - k2 * (height - o.z) * (height - o.z),
+            2.0 * (o.x * d.x + o.y * d.y + k2 * (height - o.z) * d.z),
+            d.x * d.x + d.y * d.y - k2 * d.z * d.z,
+        );
+        let side = side.into_iter().filter(|t| (0.0..=height).contains(&(o.z + t * d.z)));
+
+        let cap = Some(-o.z / d.z)
+            .filter(|_| self.capped && d.z.abs() > EPSILON)
+            .filter(|t| {
+                let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+                x * x + y * y <= self.radius * self.radius
+            });
+
+        side.chain(cap).collect()
+    }
+
+    pub fn is_capped(&self) -> bool { self.capped }
+}
+
+impl Collidable for Cone {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let t = closest(self.crossings(ray).into_iter())?;
+
+        Some(record(self, ray, t))
+    }
+}
+
+impl Boundable for Cone {
+    fn bound(&self) -> AABB {
+        let padding = Vec3::ones() * 0.0001;
+
+        AABB::surrounding(
+            disk_bound(self.base, self.apex - self.base, self.radius),
+            AABB::new(self.apex - padding, self.apex + padding),
+        )
+    }
+}
+
+impl SurfaceNormal for Cone {
+    fn surface_normal(&self, point: Vec3) -> Vec3 {
+        let frame = self.frame();
+        let p = frame.point_to_local(point);
+        let radial = (p.x * p.x + p.y * p.y).sqrt();
+        let k = self.radius / self.height();
+
+        let normal = if self.capped && p.z <= 1e-6 && radial < self.radius * (1.0 - 1e-6) {
+            Vec3::new(0.0, 0.0, -1.0)
+        } else if radial < EPSILON {
+            // the apex
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(p.x, p.y, k * radial)
+        };
+
+        frame.vector_to_world(normal).normalize()
+    }
+}
+
+impl TextureMap for Cone {
+    fn map(&self, point: Vec3) -> UV {
+        let p = self.frame().point_to_local(point);
+
+        UV::new(azimuth(p), (p.z / self.height()).clamp(0.0, 1.0))
+    }
+}
+
+/// Points within `radius` of the segment from `base` to `top`: a cylinder
+/// with hemispheres at both ends
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Capsule {
+    base: Vec3,
+    top: Vec3,
+    radius: f64,
+}
+
+impl Capsule {
+    pub fn new(base: Vec3, top: Vec3, radius: f64) -> Capsule {
+        Capsule { base, top, radius }
+    }
+
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.base, self.top - self.base)
+    }
+
+    fn height(&self) -> f64 {
+        (self.top - self.base).length()
+    }
+
+    /// times of every collision along the ray's line, including behind its origin
+    pub(crate) fn crossings(&self, ray: Ray) -> Vec<f64> {
+        let (o, d) = self.frame().ray_to_local(ray);
+        let height = self.height();
+        let r2 = self.radius * self.radius;
+
+        let side = solve_quadratic(
+            o.x * o.x + o.y * o.y - r2,
+            2.0 * (o.x * d.x + o.y * d.y),
+            d.x * d.x + d.y * d.y,
+        );
+        let side = side.into_iter().filter(|t| (0.0..=height).contains(&(o.z + t * d.z)));
+
+        // hemispheres, each only beyond its end of the segment
+        let ends = [(0.0, -1.0), (height, 1.0)].into_iter().flat_map(|(z, side)| {
+            let c = o - Vec3::new(0.0, 0.0, z);
+
+            solve_quadratic(c.length_squared() - r2, 2.0 * Vec3::dot(c, d), d.length_squared())
+                .into_iter()
+                .filter(move |t| (o.z + t * d.z - z) * side >= 0.0)
+        });
+
+        side.chain(ends).collect()
+    }
+}
+
+impl Collidable for Capsule {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let t = closest(self.crossings(ray).into_iter())?;
+
+        Some(record(self, ray, t))
+    }
+}
+
+impl Boundable for Capsule {
+    fn bound(&self) -> AABB {
+        let extent = Vec3::ones() * self.radius;
+
+        AABB::surrounding(
+            AABB::new(self.base - extent, self.base + extent),
+            AABB::new(self.top - extent, self.top + extent),
+        )
+    }
+}
+
+impl SurfaceNormal for Capsule {
+    fn surface_normal(&self, point: Vec3) -> Vec3 {
+        let frame = self.frame();
+        let p = frame.point_to_local(point);
+        // away from the closest point of the segment
+        let z = p.z.clamp(0.0, self.height());
+
+        frame.vector_to_world(p - Vec3::new(0.0, 0.0, z)).normalize()
+    }
+}
+
+impl TextureMap for Capsule {
+    fn map(&self, point: Vec3) -> UV {
+        let p = self.frame().point_to_local(point);
+        let length = self.height() + 2.0 * self.radius;
+
+        UV::new(azimuth(p), ((p.z + self.radius) / length).clamp(0.0, 1.0))
+    }
+}
+
+/// Ring around `axis` through `center`. `major_radius` is the distance from
+/// the center to the middle of the tube, and `minor_radius` is the radius of
+/// the tube. The UV wraps around the axis (u) and around the tube (v).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Torus {
+    center: Vec3,
+    axis: Vec3,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64) -> Torus {
+        Torus { center, axis, major_radius, minor_radius }
+    }
+
+    fn frame(&self) -> AxisFrame {
+        AxisFrame::new(self.center, self.axis)
+    }
+
+    /// times of every collision along the ray's line, including behind its origin
+    pub(crate) fn crossings(&self, ray: Ray) -> Vec<f64> {
+        let (o, d) = self.frame().ray_to_local(ray);
+        let (major, minor) = (self.major_radius, self.minor_radius);
+
+        // start from the torus' bounding sphere, which keeps the roots small
+        // and the quartic well conditioned
+        let outer = major + minor;
+        let near = match solve_quadratic(o.length_squared() - outer * outer, 2.0 * Vec3::dot(o, d), 1.0)[..] {
+            [near, _] => near,
+            _ => return vec![],
+        };
+        let o = o + d * near;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2), with p = o + t d and |d| = 1
+        let n = Vec3::dot(o, d);
+        let m = o.length_squared() + major * major - minor * minor;
+        let r4 = 4.0 * major * major;
+
+        let roots = solve_quartic(
+            m * m - r4 * (o.x * o.x + o.y * o.y),
+            4.0 * n * m - 2.0 * r4 * (o.x * d.x + o.y * d.y),
+            4.0 * n * n + 2.0 * m - r4 * (d.x * d.x + d.y * d.y),
+            4.0 * n,
+            1.0,
+        );
+
+        roots.into_iter().map(|t| t + near).collect()
+    }
+}
+
+impl Collidable for Torus {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let t = closest(self.crossings(ray).into_iter())?;
+
+        Some(record(self, ray, t))
+    }
+}
+
+impl Boundable for Torus {
+    fn bound(&self) -> AABB {
+        let bound = disk_bound(self.center, self.axis, self.major_radius);
+        let extent = Vec3::ones() * self.minor_radius;
+
+        AABB::new(bound.min() - extent, bound.max() + extent)
+    }
+}
+
+impl SurfaceNormal for Torus {
+    fn surface_normal(&self, point: Vec3) -> Vec3 {
+        let frame = self.frame();
+        let p = frame.point_to_local(point);
+        let radial = (p.x * p.x + p.y * p.y).sqrt();
+
+        // away from the closest point on the circle through the middle of the tube
+        let core = match radial > EPSILON {
+            true  => Vec3::new(p.x, p.y, 0.0) * (self.major_radius / radial),
+            false => Vec3::new(self.major_radius, 0.0, 0.0),
+        };
+
+        frame.vector_to_world(p - core).normalize()
+    }
+}
+
+impl TextureMap for Torus {
+    fn map(&self, point: Vec3) -> UV {
+        let p = self.frame().point_to_local(point);
+        let radial = (p.x * p.x + p.y * p.y).sqrt();
+        let tube = p.z.atan2(radial - self.major_radius);
+
+        UV::new(azimuth(p), (tube + PI) / (2.0 * PI))
+    }
+}
+
+/// real roots of c0 + c1 x + c2 x^2, in increasing order
+fn solve_quadratic(c0: f64, c1: f64, c2: f64) -> Vec<f64> {
+    if c2.abs() < EPSILON {
+        return match c1.abs() < EPSILON {
+            true  => vec![],
+            false => vec![-c0 / c1],
+        };
+    }
+
+    let discriminant = c1 * c1 - 4.0 * c2 * c0;
+
+    // rounding can push the discriminant of a double root (e.g. a ray tangent
+    // to a surface) slightly below zero
+    if discriminant < -1e-12 * f64::max(c1 * c1, (4.0 * c2 * c0).abs()) {
+        return vec![];
+    }
+    let discriminant = discriminant.max(0.0);
+
+    // avoids cancellation between -c1 and the square root
+    let q = -0.5 * (c1 + c1.signum() * discriminant.sqrt());
+    let (r1, r2) = match q == 0.0 {
+        true  => (0.0, 0.0),
+        false => (q / c2, c0 / q),
+    };
+
+    vec![f64::min(r1, r2), f64::max(r1, r2)]
+}
+
+/// real roots of c0 + c1 x + c2 x^2 + x^3
+fn solve_normalized_cubic(c0: f64, c1: f64, c2: f64) -> Vec<f64> {
+    // substitute x = y - c2 / 3 to eliminate the quadratic term
+    let sq = c2 * c2;
+    let p = (c1 - sq / 3.0) / 3.0;
+    let q = (2.0 / 27.0 * c2 * sq - c2 * c1 / 3.0 + c0) / 2.0;
+
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+    let shift = c2 / 3.0;
+
+    let roots = if discriminant.abs() < EPSILON {
+        if q.abs() < EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    } else {
+        let sqrt_d = discriminant.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    roots.into_iter().map(|y| y - shift).collect()
+}
+
+/// real roots of c0 + c1 x + c2 x^2 + c3 x^3 + c4 x^4, using Ferrari's method
+fn solve_quartic(c0: f64, c1: f64, c2: f64, c3: f64, c4: f64) -> Vec<f64> {
+    let (a, b, c, d) = (c3 / c4, c2 / c4, c1 / c4, c0 / c4);
+
+    // substitute x = y - a / 4 to eliminate the cubic term
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = if r.abs() < EPSILON {
+        // y (y^3 + p y + q) = 0
+        let mut roots = solve_normalized_cubic(q, p, 0.0);
+        roots.push(0.0);
+        roots
+    } else {
+        // a root of the resolvent cubic splits the quartic into two quadratics
+        let z = solve_normalized_cubic(0.5 * r * p - 0.125 * q * q, -r, -0.5 * p)[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+
+        if u < -EPSILON || v < -EPSILON {
+            return vec![];
+        }
+
+        let (u, v) = (u.max(0.0).sqrt(), v.max(0.0).sqrt());
+        let v = if q < 0.0 { -v } else { v };
+
+        let mut roots = solve_quadratic(z - u, v, 1.0);
+        roots.extend(solve_quadratic(z + u, -v, 1.0));
+        roots
+    };
+
+    // undo the substitution, and polish the roots with newton's method
+    for root in roots.iter_mut() {
+        let mut x = *root - a / 4.0;
+
+        for _ in 0..2 {
+            let f = (((c4 * x + c3) * x + c2) * x + c1) * x + c0;
+            let df = ((4.0 * c4 * x + 3.0 * c3) * x + 2.0 * c2) * x + c1;
+
+            if df.abs() > EPSILON {
+                x -= f / df;
+            }
+        }
+
+        *root = x;
+    }
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::Face;
+
+    /// every expected root is found, and every root found is expected.
+    /// Double roots may be found once or twice
+    fn assert_roots(found: Vec<f64>, expected: &[f64]) {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+
+        for e in expected {
+            assert!(found.iter().any(|f| close(*f, *e)), "missing root {} in {:?}", e, found);
+        }
+        for f in &found {
+            assert!(expected.iter().any(|e| close(*f, *e)), "unexpected root {} in {:?}", f, found);
+        }
+    }
+
+    #[test]
+    fn quadratic_roots() {
+        // (x - 1)(x - 3)
+        assert_eq!(solve_quadratic(3.0, -4.0, 1.0), vec![1.0, 3.0]);
+        // (x - 2)^2
+        assert_roots(solve_quadratic(4.0, -4.0, 1.0), &[2.0]);
+        // x^2 + 1
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+        // linear, 2 + 4x
+        assert_roots(solve_quadratic(2.0, 4.0, 0.0), &[-0.5]);
+        assert_roots(solve_quadratic(2.0, 0.0, 0.0), &[]);
+    }
+
+    #[test]
+    fn cubic_roots() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots(solve_normalized_cubic(-6.0, 11.0, -6.0), &[1.0, 2.0, 3.0]);
+        // (x - 1)^2 (x + 2)
+        assert_roots(solve_normalized_cubic(2.0, -3.0, 0.0), &[1.0, -2.0]);
+        // (x - 1)^3
+        assert_roots(solve_normalized_cubic(-1.0, 3.0, -3.0), &[1.0]);
+        // (x - 1)(x^2 + 1)
+        assert_roots(solve_normalized_cubic(-1.0, 1.0, -1.0), &[1.0]);
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(solve_quartic(24.0, -50.0, 35.0, -10.0, 1.0), &[1.0, 2.0, 3.0, 4.0]);
+        // same roots, leading coefficient other than 1
+        assert_roots(solve_quartic(48.0, -100.0, 70.0, -20.0, 2.0), &[1.0, 2.0, 3.0, 4.0]);
+        // (x - 1)^2 (x - 3)^2
+        assert_roots(solve_quartic(9.0, -24.0, 22.0, -8.0, 1.0), &[1.0, 3.0]);
+        // x^2 (x^2 - 1), no constant term
+        assert_roots(solve_quartic(0.0, 0.0, -1.0, 0.0, 1.0), &[-1.0, 0.0, 1.0]);
+        // (x^2 + 1)(x^2 + 4)
+        assert_roots(solve_quartic(4.0, 0.0, 5.0, 0.0, 1.0), &[]);
+        // (x - 1)(x + 1)(x^2 + 1)
+        assert_roots(solve_quartic(-1.0, 0.0, 0.0, 0.0, 1.0), &[-1.0, 1.0]);
+    }
+
+    fn torus() -> Torus {
+        Torus::new(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0), 2.0, 0.5)
+    }
+
+    #[test]
+    fn torus_hits_the_near_side_of_the_tube() {
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let mut crossings = torus().crossings(ray);
+        crossings.sort_by(|a, b| a.total_cmp(b));
+        assert_roots(crossings, &[2.5, 3.5, 6.5, 7.5]);
+
+        let record = torus().collide(ray).unwrap();
+        assert!((record.t - 2.5).abs() < 1e-6);
+        assert!((record.s_normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-6);
+        assert!(record.face == Face::Outer);
+    }
+
+    #[test]
+    fn torus_misses_through_the_hole() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(torus().collide(ray).is_none());
+        assert!(torus().crossings(ray).is_empty());
+    }
+
+    #[test]
+    fn torus_tangent_to_the_tube() {
+        // grazes the top of the tube at x = -2 and x = 2
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        let record = torus().collide(ray).unwrap();
+        assert!((record.t - 3.0).abs() < 1e-3, "t = {}", record.t);
+        assert!((record.s_normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-2);
+
+        let inside = Ray::new(Vec3::new(-5.0, 0.0, 0.49), Vec3::new(1.0, 0.0, 0.0));
+        assert!(torus().collide(inside).is_some());
+
+        let above = Ray::new(Vec3::new(-5.0, 0.0, 0.51), Vec3::new(1.0, 0.0, 0.0));
+        assert!(torus().collide(above).is_none());
+    }
+}
@@ -8,11 +8,17 @@ pub struct Ray {
     pub origin: Vec3,
     /// unit vector for the direction of the ray
     pub direction: Vec3,
+    /// moment the ray is cast, within the camera's shutter interval
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Ray { origin, direction: direction.normalize() }
+        Ray::at_time(origin, direction, 0.0)
+    }
+
+    pub fn at_time(origin: Vec3, direction: Vec3, time: f64) -> Self {
+        Ray { origin, direction: direction.normalize(), time }
     }
 
     pub fn position_at(&self, time: f64) -> Vec3 {
@@ -32,14 +38,16 @@ impl Ray {
         */
         Ray {
             origin: point,
-            direction: reflect(self.direction, normal)
+            direction: reflect(self.direction, normal),
+            time: self.time,
         }
     } 
 
     pub fn refract(&self, normal: Vec3, point: Vec3, ref_ratio: f64) -> Ray {
         Ray {
             origin: point,
-            direction: refract(self.direction, normal, ref_ratio)
+            direction: refract(self.direction, normal, ref_ratio),
+            time: self.time,
         }
     }
 }
@@ -1,29 +1,43 @@
 use crate::{Element, ElementId};
-use crate::camera::Camera;
-use crate::collisions::{CollisionRecord, Collidable};
+use crate::animation::{Animation, AnimationError, ImageSequence, frame_path};
+use crate::camera::{Camera, CameraConfig};
+use crate::collisions::{CollisionRecord, collision_face, MIN_INTERSECTION_T};
+use crate::light::Samplable;
+use crate::vec3::Vec3;
 use crate::material::{MaterialType, Material};
-use crate::image::{Color, Image, ColorU8};
+use crate::image::{Color, Image, ColorU8, HdrImage};
+use crate::post_process::PostProcess;
+use crate::environment::Background;
 use crate::ray::Ray;
 use crate::scene::{Scene};
 use crate::shape::{ShapeType};
-use crate::utils::random_float;
+use crate::utils::{random_float, seed_random};
 use crate::pool::WorkerPool;
 
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+
 use js_sys::Promise;
 use rayon::prelude::*;
 use futures::channel::oneshot;
 use wasm_bindgen::JsValue;
-
-const MIN_INTERSECTION_T: f64 = 0.001;
+use serde::{Deserialize, Serialize};
 
 pub trait Render {
     fn render_scene(&self, scene: &Scene, camera: Camera, width: u32, height: u32) -> Image; 
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct RayTracerConfig {
     pub max_bounce_depth: u32,
     pub samples: u32,
-    pub background_color: Color
+    /// light arriving from outside of the scene
+    pub background: Background,
+    /// makes renders reproducible when set
+    pub seed: Option<u64>,
+    pub post_process: PostProcess,
 }
 
 impl Default for RayTracerConfig {
@@ -31,7 +45,9 @@ impl Default for RayTracerConfig {
         Self { 
             max_bounce_depth: 3,
             samples: 5,
-            background_color: Color::white() 
+            background: Background::default(),
+            seed: None,
+            post_process: PostProcess::default(),
         }
     }
 }
@@ -39,7 +55,9 @@ impl Default for RayTracerConfig {
 pub struct RayTracer {
     max_bounce_depth: u32,
     samples: u32,
-    background_color: Color
+    background: Background,
+    seed: Option<u64>,
+    post_process: PostProcess,
 }
 
 impl RayTracer {
@@ -47,33 +65,256 @@ impl RayTracer {
         Self {
             max_bounce_depth: config.max_bounce_depth,
             samples: config.samples,
-            background_color: config.background_color, 
+            background: config.background,
+            seed: config.seed,
+            post_process: config.post_process,
         }
     } 
 
-    fn compute_ray_color(&self, scene: &Scene, ray: Ray, bounce_depth: u32) -> Color {
-        if bounce_depth == self.max_bounce_depth {
+    /// Trace a path through the scene, returning the light arriving along `ray`.
+    ///
+    /// At every diffuse collision light sources are sampled directly, and the
+    /// result is combined with the light found by following the BSDF sample
+    /// using multiple importance sampling (power heuristic).
+    ///
+    /// Participating media are handled by distance sampling: a ray crossing a
+    /// medium collides with it where it scatters, and the medium's `Isotropic`
+    /// material takes the place of the BSDF. Shadow rays pass through media,
+    /// and the light they carry is scaled by the media's transmittance.
+    fn compute_ray_color(&self, scene: &Scene, ray: Ray) -> Color {
+        let mut color = Color::black();
+        // fraction of the light arriving along the current ray that reaches the camera
+        let mut throughput = Color::white();
+        let mut ray = ray;
+        // origin of the current ray and the density of the BSDF sample that
+        // produced it, None for camera rays and specular samples
+        let mut previous: Option<(Vec3, f64)> = None;
+
+        for bounce_depth in 0..self.max_bounce_depth {
+            let (element, record) = match self.compute_collision(scene, ray) {
+                Some(collision) => collision,
+                None => {
+                    let radiance = self.background.radiance(ray.direction);
+                    let weight = match previous {
+                        Some((_, bsdf_pdf)) => {
+                            let light_pdf = self.background_selection_pdf(scene) * self.background.pdf(ray.direction);
+                            power_heuristic(bsdf_pdf, light_pdf)
+                        }
+                        None => 1.0,
+                    };
+
+                    color += throughput * radiance * weight;
+                    break;
+                }
+            };
+
+            let emitted = element.material.emitted(record.uv, record.point, &record.face);
+            if !emitted.is_black() {
+                let weight = match previous {
+                    Some((origin, bsdf_pdf)) => {
+                        let selection_pdf = (1.0 - self.background_selection_pdf(scene)) * scene.light_pdf(element);
+                        let light_pdf = selection_pdf * element.shape.pdf(origin, ray.direction);
+                        power_heuristic(bsdf_pdf, light_pdf)
+                    }
+                    None => 1.0,
+                };
+
+                color += throughput * emitted * weight;
+            }
+
+            // light sampled directly counts as one more bounce
+            if bounce_depth + 1 == self.max_bounce_depth {
+                break;
+            }
+
+            color += throughput * self.sample_direct_light(scene, ray, element, &record);
+            color += throughput * self.sample_delta_lights(scene, ray, element, &record);
+
+            let sample = match element.material.sample(ray, &record) {
+                Some(sample) => sample,
+                None         => break,
+            };
+
+            throughput = throughput * sample.weight;
+            if throughput.is_black() {
+                break;
+            }
+
+            previous = match sample.specular {
+                true  => None,
+                false => Some((record.point, sample.pdf)),
+            };
+            ray = Ray::at_time(record.point, sample.direction, ray.time);
+        }
+
+        color
+    }
+
+    /// Fraction of the light travelling `distance` along the shadow ray that
+    /// arrives at its origin: zero if a surface is in the way, otherwise the
+    /// transmittance of the media in between
+    fn visibility(&self, scene: &Scene, ray: Ray, distance: f64) -> f64 {
+        if let Some((_, blocker)) = scene.collide_surfaces(ray) {
+            if blocker.t < distance * (1.0 - 1e-6) - MIN_INTERSECTION_T {
+                return 0.0;
+            }
+        }
+
+        scene.transmittance(ray, distance)
+    }
+
+    /// probability of next event estimation sampling the background rather
+    /// than a light source in the scene
+    fn background_selection_pdf(&self, scene: &Scene) -> f64 {
+        match (self.background.is_samplable(), scene.has_light_sources()) {
+            (false, _)    => 0.0,
+            (true, false) => 1.0,
+            (true, true)  => 0.5,
+        }
+    }
+
+    /// Estimate the light reflected along the ray that arrives directly from
+    /// a light source or the background (next event estimation), weighted
+    /// against BSDF sampling
+    fn sample_direct_light(&self, scene: &Scene, ray: Ray, element: &Element, record: &CollisionRecord) -> Color {
+        let background_pdf = self.background_selection_pdf(scene);
+
+        if random_float() < background_pdf {
+            return self.sample_background(scene, ray, element, record, background_pdf);
+        }
+
+        let (light, selection_pdf) = match scene.sample_light() {
+            Some(light) => light,
+            None        => return Color::black(),
+        };
+        let selection_pdf = selection_pdf * (1.0 - background_pdf);
+
+        let sample = match light.shape.sample(record.point) {
+            Some(sample) if sample.pdf > 0.0 => sample,
+            _ => return Color::black(),
+        };
+
+        let to_light = sample.point - record.point;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+
+        // nothing to trace a shadow ray for, e.g. specular materials
+        let bsdf = element.material.eval(ray, record, direction);
+        if bsdf.is_black() {
             return Color::black();
         }
 
-        if let Some((element, record)) = self.compute_collision(scene, ray) {
-            let result = element.material.resolve(ray, record);
+        let visibility = self.visibility(scene, Ray::at_time(record.point, direction, ray.time), distance);
+        if visibility == 0.0 {
+            return Color::black();
+        }
+
+        let face = collision_face(direction, sample.normal);
+        let emitted = light.material.emitted(sample.uv, sample.point, &face);
+
+        let light_pdf = sample.pdf * selection_pdf;
+        let bsdf_pdf = element.material.pdf(ray, record, direction);
+
+        emitted * bsdf * (visibility * power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
+
+    /// Light reflected along the ray from every point, spot and directional
+    /// light. Delta lights can't be hit by BSDF samples, so there is nothing
+    /// to weight against
+    fn sample_delta_lights(&self, scene: &Scene, ray: Ray, element: &Element, record: &CollisionRecord) -> Color {
+        let mut color = Color::black();
+
+        for light in &scene.lights {
+            let sample = match light.sample(record.point) {
+                Some(sample) => sample,
+                None         => continue,
+            };
 
-            // this is a hack - see DiffuseLight in material.rs
-            result.emitted_light + result.color * self.compute_ray_color(scene, result.reflected_ray, bounce_depth + 1)
-        } else {
-            self.background_color
+            let bsdf = element.material.eval(ray, record, sample.direction);
+            if bsdf.is_black() || sample.irradiance.is_black() {
+                continue;
+            }
+
+            let visibility = self.visibility(scene, Ray::at_time(record.point, sample.direction, ray.time), sample.distance);
+            if visibility == 0.0 {
+                continue;
+            }
+
+            color += sample.irradiance * bsdf * visibility;
         }
+
+        color
     }
-}
 
-impl Render for RayTracer {
-    fn render_scene(&self, scene: &Scene, camera: Camera, width: u32, height: u32) -> Image {
-        let mut image = Image::new(height, width); 
+    /// next event estimation towards the background, picked with probability `selection_pdf`
+    fn sample_background(&self, scene: &Scene, ray: Ray, element: &Element, record: &CollisionRecord, selection_pdf: f64) -> Color {
+        let sample = match self.background.sample() {
+            Some(sample) => sample,
+            None         => return Color::black(),
+        };
+
+        let bsdf = element.material.eval(ray, record, sample.direction);
+        if bsdf.is_black() {
+            return Color::black();
+        }
+
+        // the background is only visible if nothing is hit
+        let visibility = self.visibility(scene, Ray::at_time(record.point, sample.direction, ray.time), f64::INFINITY);
+        if visibility == 0.0 {
+            return Color::black();
+        }
+
+        let light_pdf = sample.pdf * selection_pdf;
+        let bsdf_pdf = element.material.pdf(ray, record, sample.direction);
+
+        sample.radiance * bsdf * (visibility * power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
+
+    /// average radiance of the rays sampled through a pixel
+    fn sample_pixel(&self, scene: &Scene, camera: &Camera, row: u32, col: u32, width: u32, height: u32) -> Color {
+        if let Some(seed) = self.seed {
+            // seed each pixel separately, so the result doesn't depend on
+            // which thread renders which pixel
+            let pixel = row as u64 * width as u64 + col as u64 + 1;
+            seed_random(seed ^ pixel.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        }
+
+        let mut acc_color = Color::black();
+
+        for _ in 0..self.samples {
+            let row_s = row as f64 + random_float();
+            let col_s = col as f64 + random_float();
+
+            // convert pixel coordinate to world coordinates
+            let world_x = col_s / (width - 1) as f64;
+            let world_y = row_s / (height - 1) as f64; 
+
+            let ray = camera.create_ray(world_x, world_y);
+
+            let color = self.compute_ray_color(scene, ray);
+
+            acc_color += color; 
+        }
+
+        Color::new(
+            acc_color.red / self.samples as f64,
+            acc_color.green / self.samples as f64,
+            acc_color.blue / self.samples as f64,
+        )
+    }
+
+    /// Render the scene without post processing, calling `progress(rows_done, rows)`
+    /// every time a row's worth of pixels has been rendered
+    pub fn render_hdr_with_progress<F>(&self, scene: &Scene, camera: Camera, width: u32, height: u32, progress: F) -> HdrImage
+    where
+        F: Fn(u32, u32) + Sync
+    {
+        let mut image = HdrImage::new(height, width); 
 
         let pixels = width * height;
 
         let indices = (0..pixels).collect::<Vec<u32>>();
+        let done = AtomicU32::new(0);
 
         let mut colors = vec![];
 
@@ -81,41 +322,106 @@ impl Render for RayTracer {
             let row = i / width;
             let col = i % width;
 
-            let mut acc_color = Color::black();
-
-            for _ in 0..self.samples {
-                let row_s = row as f64 + random_float();
-                let col_s = col as f64 + random_float();
-
-                // convert pixel coordinate to world coordinates
-                let world_x = col_s / (width - 1) as f64;
-                let world_y = row_s / (height - 1) as f64; 
-
-                let ray = camera.create_ray(world_x, world_y);
+            let color = self.sample_pixel(scene, &camera, row, col, width, height);
 
-                let color = self.compute_ray_color(scene, ray, 0);
-
-                acc_color += color; 
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if done.is_multiple_of(width) {
+                progress(done / width, height);
             }
 
-            let normalized = Color::new(
-                acc_color.red / self.samples as f64,
-                acc_color.green / self.samples as f64,
-                acc_color.blue / self.samples as f64,
-            ).gamma_corrected(); 
-
-            normalized
+            color
         }).collect_into_vec(&mut colors);
 
         indices.iter().for_each(|i| {
             let row = i / width;
             let col = i % width;
             
-            image.set_color(row, col, ColorU8::from(colors[*i as usize]))
+            image.set_color(row, col, colors[*i as usize])
         });
 
         image
     }
+
+    /// Render the scene and post process it, calling `progress(rows_done, rows)`
+    /// every time a row's worth of pixels has been rendered
+    pub fn render_scene_with_progress<F>(&self, scene: &Scene, camera: Camera, width: u32, height: u32, progress: F) -> Image
+    where
+        F: Fn(u32, u32) + Sync
+    {
+        let hdr = self.render_hdr_with_progress(scene, camera, width, height, progress);
+
+        self.post_process.to_image(&hdr)
+    }
+}
+
+/// Progress of `RayTracer::render_sequence`
+pub enum SequenceProgress<'a> {
+    /// the frame was saved by an earlier, interrupted, render
+    Skipped { frame: u32, path: &'a Path },
+    Rows { frame: u32, rows: u32, total: u32 },
+    Saved { frame: u32, path: &'a Path },
+}
+
+impl RayTracer {
+    /// Render a range of frames of an animation to numbered image files.
+    ///
+    /// Frames that were already saved are skipped, so an interrupted render is
+    /// resumed by running it again. Each image is written to a temporary file
+    /// and renamed when complete, so a partially written frame is never taken
+    /// for a finished one.
+    pub fn render_sequence<F>(
+        &self,
+        scene: &Scene,
+        camera: &CameraConfig,
+        animation: &Animation,
+        frames: RangeInclusive<u32>,
+        sequence: &ImageSequence,
+        progress: F,
+    ) -> Result<(), AnimationError>
+    where
+        F: Fn(SequenceProgress) + Sync
+    {
+        if let Some(directory) = sequence.pattern.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(directory)
+                .map_err(|error| AnimationError::Io { path: directory.to_path_buf(), error })?;
+        }
+
+        for frame in frames {
+            let path = frame_path(&sequence.pattern, frame);
+            if path.exists() {
+                progress(SequenceProgress::Skipped { frame, path: &path });
+                continue;
+            }
+
+            let (frame_camera, frame_scene) = animation.frame(frame, camera, scene)?;
+            let image = self.render_scene_with_progress(
+                &frame_scene,
+                Camera::new(frame_camera),
+                sequence.width,
+                sequence.height,
+                |rows, total| progress(SequenceProgress::Rows { frame, rows, total }),
+            );
+
+            let mut partial = path.clone().into_os_string();
+            partial.push(".partial");
+
+            image
+                .save_as(&partial, sequence.format)
+                .map_err(|error| AnimationError::Image { error })?;
+            std::fs::rename(&partial, &path)
+                .map_err(|error| AnimationError::Io { path: path.clone(), error })?;
+
+            progress(SequenceProgress::Saved { frame, path: &path });
+        }
+
+        Ok(())
+    }
+}
+
+impl Render for RayTracer {
+    fn render_scene(&self, scene: &Scene, camera: Camera, width: u32, height: u32) -> Image {
+        self.render_scene_with_progress(scene, camera, width, height, |_, _| {})
+    }
 }
 impl RayTracer {
     pub fn render_scene_wasm(self, scene: Scene, camera: Camera, width: u32, height: u32, pool: &WorkerPool) -> Result<Promise, JsValue> {
@@ -136,31 +442,8 @@ impl RayTracer {
                 indices.par_iter().map(|i| {
                     let row = i / width;
                     let col = i % width;
-        
-                    let mut acc_color = Color::black();
-        
-                    for _ in 0..self.samples {
-                        let row_s = row as f64 + random_float();
-                        let col_s = col as f64 + random_float();
-        
-                        // convert pixel coordinate to world coordinates
-                        let world_x = col_s / (width - 1) as f64;
-                        let world_y = row_s / (height - 1) as f64; 
-        
-                        let ray = camera.create_ray(world_x, world_y);
-        
-                        let color = self.compute_ray_color(&scene, ray, 0);
-        
-                        acc_color += color; 
-                    }
-        
-                    let normalized = Color::new(
-                        acc_color.red / self.samples as f64,
-                        acc_color.green / self.samples as f64,
-                        acc_color.blue / self.samples as f64,
-                    ).gamma_corrected(); 
-        
-                    normalized
+
+                    self.post_process.apply(self.sample_pixel(&scene, &camera, row, col, width, height))
                 }).collect_into_vec(&mut colors);
             });
 
@@ -188,36 +471,20 @@ impl RayTracer {
     }
 }
 
-impl RayTracer {
-    fn compute_collision(&self, scene: &Scene, ray: Ray) -> Option<(Element, CollisionRecord)> {
-        let mut c_record: Option<CollisionRecord> = None;
-        let mut c_t = f64::MAX;
-        let mut c_element: Option<Element> = None;
-
-        scene.objects.iter().for_each(|element| {
-            if let Some(record) = element.collide(ray) {
-                // update the collision record if 
-                // the ray collides earlier
-                if MIN_INTERSECTION_T < record.t && record.t < c_t { 
-                    c_t = record.t;
-                    c_record = Some(record);
-                    c_element= Some(element.clone());
-                } 
-            };
-        });
+/// weight of a sample taken with density `pdf`, when the same light could have
+/// been sampled with density `other_pdf` by another strategy
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
 
-        if c_element.is_none() {
-            return None
-        }
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
+}
 
-        Some((c_element.unwrap(), c_record.unwrap()))
+impl RayTracer {
+    fn compute_collision<'a>(&self, scene: &'a Scene, ray: Ray) -> Option<(&'a Element, CollisionRecord)> {
+        scene.collide(ray)
     } 
 
     pub fn compute_collision_element(&self, scene: &Scene, ray: Ray) -> Option<Element> {
-        match self.compute_collision(scene, ray) {
-            Some((element, _)) => Some(element),
-            None               => None
-        }
+        self.compute_collision(scene, ray).map(|(element, _)| element.clone())
     }
 }
-
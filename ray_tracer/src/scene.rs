@@ -1,18 +1,24 @@
-use crate::{PerlinTexture, Camera, CameraConfig, DiffuseLight};
-use crate::collisions::{Collidable, CollisionRecord};
+use crate::{PerlinTexture, CameraConfig, DiffuseLight};
+use crate::aabb::{AABB, Boundable};
+use crate::bvh::Bvh;
+use crate::collisions::{Collidable, CollisionRecord, MIN_INTERSECTION_T};
 use crate::image::Color;
-use crate::material::{Material, MaterialType, Dielectric, Lambertian, Metal};
+use crate::light::LightType;
+use crate::material::{Material, MaterialType, Dielectric, Lambertian, Metal, Isotropic};
 use crate::ray::Ray;
-use crate::shape::{ShapeType, Sphere, RectangleXY, RectangleXZ, RectangleYZ, Box};
+use crate::shape::{ShapeType, Sphere, RectangleXY, RectangleXZ, RectangleYZ, Box, Plane};
 use crate::texture::{TextureType, CheckeredTexture};
 use crate::utils::random_float;
 use crate::vec3::Vec3;
+use crate::volume::{ConstantMedium, Medium};
 use serde::{Deserialize, Serialize};
 
 
 #[readonly::make]
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Element {
+    /// generated when omitted from scene files
+    #[serde(default = "ElementId::new")]
     pub id: ElementId,
     pub material: MaterialType, 
     pub shape: ShapeType,
@@ -29,6 +35,15 @@ impl Element {
     pub fn set_material(&mut self, material: MaterialType) {
         self.material = material;
     }
+
+    pub fn set_shape(&mut self, shape: ShapeType) {
+        self.shape = shape;
+    }
+
+    /// whether the element is an emitter that can be sampled directly
+    pub fn is_light_source(&self) -> bool {
+        matches!(self.material, MaterialType::DiffuseLight(_)) && self.shape.is_samplable()
+    }
 }
 
 impl Collidable for Element {
@@ -37,6 +52,12 @@ impl Collidable for Element {
     }
 }
 
+impl Boundable for Element {
+    fn bound(&self) -> AABB {
+        self.shape.bound()
+    }
+}
+
 #[readonly::make]
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ElementId {
@@ -44,9 +65,13 @@ pub struct ElementId {
 }
 
 impl ElementId {
+    /// ids are kept below 2^53 so that they survive being converted to
+    /// javascript numbers and TOML integers
+    const MAX_ID: u64 = 1 << 53;
+
     pub fn new() -> ElementId {
         ElementId {
-            id: (random_float() * (u64::MAX - 1) as f64) as u64
+            id: (random_float() * (ElementId::MAX_ID - 1) as f64) as u64
         }
     } 
 }
@@ -54,7 +79,17 @@ impl ElementId {
 #[readonly::make]
 #[derive(Clone)]
 pub struct Scene {
-    pub objects: Vec<Element> 
+    pub objects: Vec<Element>,
+    /// point, spot and directional lights
+    pub lights: Vec<LightType>,
+    /// acceleration structure over `objects`
+    bvh: Bvh,
+    /// indices of the elements that are light sources
+    light_sources: Vec<usize>,
+    /// indices of the elements that are participating media
+    media: Vec<usize>,
+    /// true when an element may have been edited since the bvh was last fit
+    stale: bool,
 }
 
 impl Default for Scene {
@@ -62,13 +97,41 @@ impl Default for Scene {
 }
 
 impl Scene {
+    pub fn new(objects: Vec<Element>) -> Scene {
+        let mut scene = Scene { objects, lights: vec![], bvh: Bvh::default(), light_sources: vec![], media: vec![], stale: false };
+        scene.rebuild();
+
+        scene
+    }
+
+    pub fn with_lights(mut self, lights: Vec<LightType>) -> Scene {
+        self.lights = lights;
+        self
+    }
+
+    pub fn add_light(&mut self, light: LightType) {
+        self.lights.push(light);
+    }
+
+    pub fn add(&mut self, element: Element) {
+        self.objects.push(element);
+        self.rebuild();
+    }
+
     /// remove element by id
     pub fn remove(&mut self, id: ElementId) {
         self.objects.retain(|e| e.id != id);
+        self.rebuild();
     }
 
-    /// get element by id
+    /// get element by id.
+    /// The scene can't track what is done with the element, so after editing
+    /// the element `Scene::refit` (or `Scene::rebuild`) must be called.
+    /// Until then collisions fall back to checking every element, and
+    /// light sources aren't sampled.
     pub fn get_element_mut(&mut self, id: ElementId) -> &mut Element {
+        self.stale = true;
+
         self.objects
             .iter_mut()
             .find(|e| e.id == id)
@@ -81,12 +144,135 @@ impl Scene {
             .find(|e| e.id == id)
             .expect("ElementId not found")
     }
+
+    /// rebuild the bvh from scratch
+    pub fn rebuild(&mut self) {
+        self.bvh.rebuild(&self.objects);
+        self.find_lights();
+        self.stale = false;
+    }
+
+    /// update the bounds of the bvh after elements were edited in place.
+    /// Prefer `Scene::rebuild` if elements moved significantly.
+    pub fn refit(&mut self) {
+        self.bvh.refit(&self.objects);
+        self.find_lights();
+        self.stale = false;
+    }
+
+    fn find_lights(&mut self) {
+        self.light_sources = (0..self.objects.len())
+            .filter(|i| self.objects[*i].is_light_source())
+            .collect();
+        self.media = (0..self.objects.len())
+            .filter(|i| self.objects[*i].shape.is_medium())
+            .collect();
+    }
+
+    /// pick a light source uniformly at random, along with the probability of picking it
+    pub fn sample_light(&self) -> Option<(&Element, f64)> {
+        if self.stale || self.light_sources.is_empty() {
+            return None;
+        }
+
+        let i = usize::min((random_float() * self.light_sources.len() as f64) as usize, self.light_sources.len() - 1);
+
+        Some((&self.objects[self.light_sources[i]], 1.0 / self.light_sources.len() as f64))
+    }
+
+    pub fn has_light_sources(&self) -> bool {
+        !self.stale && !self.light_sources.is_empty()
+    }
+
+    /// probability of `sample_light` picking the element
+    pub fn light_pdf(&self, element: &Element) -> f64 {
+        if self.stale || !element.is_light_source() {
+            return 0.0;
+        }
+
+        1.0 / self.light_sources.len() as f64
+    }
+
+    /// closest collision with an element in the scene
+    pub fn collide(&self, ray: Ray) -> Option<(&Element, CollisionRecord)> {
+        self.collide_filtered(ray, |_| true)
+    }
+
+    /// closest collision with an element that isn't a participating medium,
+    /// for shadow rays (see `Scene::transmittance`)
+    pub fn collide_surfaces(&self, ray: Ray) -> Option<(&Element, CollisionRecord)> {
+        self.collide_filtered(ray, |element| !element.shape.is_medium())
+    }
+
+    /// fraction of the light that makes it through every participating medium
+    /// between the ray's origin and `distance` along it
+    pub fn transmittance(&self, ray: Ray, distance: f64) -> f64 {
+        let mut transmittance = 1.0;
+
+        let mut attenuate = |element: &Element| {
+            if transmittance > 0.0 && element.bound().hit(ray, MIN_INTERSECTION_T, distance) {
+                transmittance *= element.shape.transmittance(ray, distance);
+            }
+        };
+
+        match self.stale {
+            true  => self.objects.iter().filter(|e| e.shape.is_medium()).for_each(&mut attenuate),
+            false => self.media.iter().for_each(|i| attenuate(&self.objects[*i])),
+        }
+
+        transmittance
+    }
+
+    fn collide_filtered<F>(&self, ray: Ray, include: F) -> Option<(&Element, CollisionRecord)>
+    where
+        F: Fn(&Element) -> bool
+    {
+        if self.stale {
+            return self.collide_linear(ray, include);
+        }
+
+        self.bvh
+            .collide(ray, |i| match include(&self.objects[i]) {
+                true  => self.objects[i].collide(ray),
+                false => None,
+            })
+            .map(|(i, record)| (&self.objects[i], record))
+    }
+
+    pub(crate) fn collide_linear<F>(&self, ray: Ray, include: F) -> Option<(&Element, CollisionRecord)>
+    where
+        F: Fn(&Element) -> bool
+    {
+        let mut closest: Option<(&Element, CollisionRecord)> = None;
+
+        for element in self.objects.iter().filter(|e| include(e)) {
+            if let Some(record) = element.collide(ray) {
+                // update the collision record if 
+                // the ray collides earlier
+                let closer = match &closest {
+                    Some((_, c_record)) => record.t < c_record.t,
+                    None                => true,
+                };
+
+                if MIN_INTERSECTION_T < record.t && closer {
+                    closest = Some((element, record));
+                }
+            }
+        }
+
+        closest
+    }
 }
 
 impl Scene {
+    /// floor of the example scenes, just below their objects
+    fn ground() -> ShapeType {
+        ShapeType::Plane(Plane::new(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)).expect("the normal is not zero"))
+    }
+
     pub fn two_spheres() -> Scene {
-        Scene {
-            objects: vec![
+        Scene::new(
+            vec![
                 Element {
                     id: ElementId::new(),
                     material: MaterialType::Lambertian(Lambertian::new(Color::new(0.7, 0.2, 0.5).into())),
@@ -99,12 +285,12 @@ impl Scene {
                     shape: ShapeType::Sphere(Sphere::new(Vec3::new(0.0, 5.0, 0.0), 5.0))
                 }
             ]
-        }
+        )
     }
 
     pub fn one_sphere() -> Scene {
-        Scene {
-            objects: vec![
+        Scene::new(
+            vec![
                 Element {
                     id: ElementId::new(),
                     material: MaterialType::Metal(Metal::new(Color::new(0.2, 0.2, 0.9).into(), 0.2)),
@@ -123,12 +309,12 @@ impl Scene {
                     shape: ShapeType::Sphere(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0))
                 },
             ]
-        }
+        )
     }
 
     pub fn simple() -> Scene {
-        Scene {
-            objects: vec![
+        Scene::new(
+            vec![
                 Element {
                     id: ElementId::new(),
                     material: MaterialType::Lambertian(Lambertian::new(Color::new(0.2, 0.2, 0.2).into())),
@@ -151,13 +337,13 @@ impl Scene {
                     shape: ShapeType::Sphere(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0))
                 },
             ]
-        }
+        )
     }
 
     // TODO: make sure that the element is centered at (0, 0)
     pub fn sphere(element: Element) -> Scene {
-        Scene {
-            objects: vec![
+        Scene::new(
+            vec![
                 element,
                 // ground
                 Element {
@@ -170,29 +356,29 @@ impl Scene {
                             Color::new(0.1, 0.1, 0.1),
                         )))
                     ),
-                    shape: ShapeType::Sphere(Sphere::new(Vec3::new(0.0, -1000.5, 0.0), 1000.0))
+                    shape: Scene::ground()
                 }, 
             ]
-        }
+        )
    }
 
    pub fn element_with_background(element: Element, background_mat: MaterialType) -> Scene {
-        Scene {
-            objects: vec![
+        Scene::new(
+            vec![
                 element,
                 Element {
                     id: ElementId::new(),
                     material: background_mat, 
-                    shape: ShapeType::Sphere(Sphere::new(Vec3::new(0.0, -1000.5, 0.0), 1000.0))
+                    shape: Scene::ground()
                 }, 
             ]
-        }
+        )
  
    }
 
     pub fn materials() -> Scene {
-        Scene {
-            objects: vec![
+        Scene::new(
+            vec![
                 Element {
                     id: ElementId::new(),
                     material: MaterialType::Lambertian(Lambertian::new(
@@ -226,21 +412,19 @@ impl Scene {
                     shape: ShapeType::Sphere(Sphere::new(Vec3::new(2.0, 0.5, -1.0), 0.5))
                 },
             ]
-        }
+        )
     }
 
-    pub fn rectangles() -> (Camera, Scene) {
-        let camera = Camera::new(
-            CameraConfig {
-                origin: Vec3::new(-4.0, 3.0, 4.0),
-                look_at: Vec3::new(0.5, 0.5, 0.0),
-                aspect: 1.0,
-                ..CameraConfig::default()
-            }
-        );
+    pub fn rectangles() -> (CameraConfig, Scene) {
+        let camera = CameraConfig {
+            origin: Vec3::new(-4.0, 3.0, 4.0),
+            look_at: Vec3::new(0.5, 0.5, 0.0),
+            aspect: 1.0,
+            ..CameraConfig::default()
+        };
         
-        let scene = Scene {
-            objects: vec![
+        let scene = Scene::new(
+            vec![
                 Element {
                     id: ElementId::new(),
                     material: MaterialType::Metal(Metal::new(Color::new(8.0, 1.0, 8.0).into(), 0.0)),
@@ -264,75 +448,114 @@ impl Scene {
                             Color::new(0.1, 0.1, 0.1),
                         )))
                     ),
-                    shape: ShapeType::Sphere(Sphere::new(Vec3::new(0.0, -1000.5, 0.0), 1000.0))
+                    shape: Scene::ground()
                 }, 
             ]
-        };
+        );
 
         return (camera, scene)
    } 
 
-    pub fn cornell_box() -> (Camera, Scene) {
-        let camera = Camera::new(
-            CameraConfig {
-                origin: Vec3::new(278.0, 278.0, -800.0),
-                look_at: Vec3::new(278.0, 278.0, 0.0),
-                aspect: 1.0,
-                vertical_fov_degrees: 40.0,
-                ..CameraConfig::default()
-            }
-        );
+    pub fn cornell_box() -> (CameraConfig, Scene) {
+        let white = MaterialType::Lambertian(Lambertian::new(Color::new(0.73, 0.73, 0.73).into()));
+        let light = Element {
+            id: ElementId::new(),
+            material: MaterialType::DiffuseLight(DiffuseLight::new(Color::new(1.0, 1.0, 1.0), 15.0)),
+            shape: ShapeType::RectangleXZ(RectangleXZ::new(213.0, 343.0, 227.0, 332.0, 554.0, true))
+        };
+
+        let mut elements = Scene::cornell_walls(light);
+        elements.extend([
+            Element {
+                id: ElementId::new(),
+                material: white.clone(),
+                shape: ShapeType::Box(Box::new(Vec3::new(130.0, 0.0, 65.0), Vec3::new(295.0, 165.0, 230.0)))
+            },
+            Element {
+                id: ElementId::new(),
+                material: white,
+                shape: ShapeType::Box(Box::new(Vec3::new(265.0, 0.0, 295.0), Vec3::new(430.0, 330.0, 460.0)))
+            },
+        ]);
+
+        (Scene::cornell_camera(), Scene::new(elements))
+    }
+
+    /// Cornell box with its boxes made of smoke, one dark and one light,
+    /// lit by a larger, dimmer light
+    pub fn cornell_smoke() -> (CameraConfig, Scene) {
+        let light = Element {
+            id: ElementId::new(),
+            material: MaterialType::DiffuseLight(DiffuseLight::new(Color::new(1.0, 1.0, 1.0), 7.0)),
+            shape: ShapeType::RectangleXZ(RectangleXZ::new(113.0, 443.0, 127.0, 432.0, 554.0, true))
+        };
+
+        let mut elements = Scene::cornell_walls(light);
+        elements.extend([
+            Element {
+                id: ElementId::new(),
+                material: MaterialType::Isotropic(Isotropic::new(Color::new(1.0, 1.0, 1.0).into())),
+                shape: ShapeType::ConstantMedium(ConstantMedium::new(
+                    ShapeType::Box(Box::new(Vec3::new(130.0, 0.0, 65.0), Vec3::new(295.0, 165.0, 230.0))),
+                    0.01,
+                ))
+            },
+            Element {
+                id: ElementId::new(),
+                material: MaterialType::Isotropic(Isotropic::new(Color::new(0.0, 0.0, 0.0).into())),
+                shape: ShapeType::ConstantMedium(ConstantMedium::new(
+                    ShapeType::Box(Box::new(Vec3::new(265.0, 0.0, 295.0), Vec3::new(430.0, 330.0, 460.0))),
+                    0.01,
+                ))
+            },
+        ]);
+
+        (Scene::cornell_camera(), Scene::new(elements))
+    }
+
+    fn cornell_camera() -> CameraConfig {
+        CameraConfig {
+            origin: Vec3::new(278.0, 278.0, -800.0),
+            look_at: Vec3::new(278.0, 278.0, 0.0),
+            aspect: 1.0,
+            vertical_fov_degrees: 40.0,
+            ..CameraConfig::default()
+        }
+    }
 
+    /// the walls of a Cornell box, lit by `light`
+    fn cornell_walls(light: Element) -> Vec<Element> {
         let red = MaterialType::Lambertian(Lambertian::new(Color::new(0.65, 0.05, 0.05).into()));
         let white = MaterialType::Lambertian(Lambertian::new(Color::new(0.73, 0.73, 0.73).into()));
         let green = MaterialType::Lambertian(Lambertian::new(Color::new(0.12, 0.45, 0.15).into()));
-        let light = MaterialType::DiffuseLight(DiffuseLight::new(Color::new(1.0, 1.0, 1.0), 15.0));
-        
-        let scene = Scene {
-            objects: vec![
-                Element {
-                    id: ElementId::new(),
-                    material: green.clone(), 
-                    shape: ShapeType::RectangleYZ(RectangleYZ::new(0.0, 555.0, 0.0, 555.0, 555.0, true))
-                },
-                Element {
-                    id: ElementId::new(),
-                    material: red.clone(), 
-                    shape: ShapeType::RectangleYZ(RectangleYZ::new(0.0, 555.0, 0.0, 555.0, 0.0, false))
-                },
-                Element {
-                    id: ElementId::new(),
-                    material: light.clone(), 
-                    shape: ShapeType::RectangleXZ(RectangleXZ::new(213.0, 343.0, 227.0, 332.0, 554.0, true))
-                },
-                Element {
-                    id: ElementId::new(),
-                    material: white.clone(), 
-                    shape: ShapeType::RectangleXZ(RectangleXZ::new(0.0, 555.0, 0.0, 555.0, 0.0, false))
-                }, 
-                Element {
-                    id: ElementId::new(),
-                    material: white.clone(), 
-                    shape: ShapeType::RectangleXZ(RectangleXZ::new(0.0, 555.0, 0.0, 555.0, 555.0, true))
-                }, 
-                Element {
-                    id: ElementId::new(),
-                    material: white.clone(), 
-                    shape: ShapeType::RectangleXY(RectangleXY::new(0.0, 555.0, 0.0, 555.0, 555.0, false))
-                }, 
-                Element {
-                    id: ElementId::new(),
-                    material: white.clone(),
-                    shape: ShapeType::Box(Box::new(Vec3::new(130.0, 0.0, 65.0), Vec3::new(295.0, 165.0, 230.0)))
-                },
-                Element {
-                    id: ElementId::new(),
-                    material: white.clone(),
-                    shape: ShapeType::Box(Box::new(Vec3::new(265.0, 0.0, 295.0), Vec3::new(430.0, 330.0, 460.0)))
-                }
-            ]
-        };
 
-        (camera, scene)
-    } 
+        vec![
+            Element {
+                id: ElementId::new(),
+                material: green,
+                shape: ShapeType::RectangleYZ(RectangleYZ::new(0.0, 555.0, 0.0, 555.0, 555.0, true))
+            },
+            Element {
+                id: ElementId::new(),
+                material: red,
+                shape: ShapeType::RectangleYZ(RectangleYZ::new(0.0, 555.0, 0.0, 555.0, 0.0, false))
+            },
+            light,
+            Element {
+                id: ElementId::new(),
+                material: white.clone(),
+                shape: ShapeType::RectangleXZ(RectangleXZ::new(0.0, 555.0, 0.0, 555.0, 0.0, false))
+            },
+            Element {
+                id: ElementId::new(),
+                material: white.clone(),
+                shape: ShapeType::RectangleXZ(RectangleXZ::new(0.0, 555.0, 0.0, 555.0, 555.0, true))
+            },
+            Element {
+                id: ElementId::new(),
+                material: white,
+                shape: ShapeType::RectangleXY(RectangleXY::new(0.0, 555.0, 0.0, 555.0, 555.0, false))
+            },
+        ]
+    }
 }
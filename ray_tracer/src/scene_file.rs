@@ -0,0 +1,303 @@
+//! On-disk scene format.
+//!
+//! A scene file describes the camera, the ray tracer's settings and the
+//! elements of a scene. Files are JSON (`.json`) or TOML (`.toml`), chosen
+//! by extension. Every field of `camera` and `renderer` is optional and
+//! defaults to the values of `CameraConfig::default()` and
+//! `RayTracerConfig::default()`. Element ids are optional as well. An
+//! optional `animation` keyframes the camera and elements, see `animation`.
+//!
+//! Shapes, materials, textures and lights are written as externally tagged
+//! enums, matching the serialization used by the wasm glue:
+//!
+//! ```json
+//! {
+//!   "camera": {
+//!     "origin": { "x": 0.0, "y": 1.0, "z": 4.0 },
+//!     "look_at": { "x": 0.0, "y": 0.5, "z": 0.0 },
+//!     "vertical_fov_degrees": 30.0
+//!   },
+//!   "renderer": { "samples": 50, "max_bounce_depth": 8 },
+//!   "elements": [
+//!     {
+//!       "material": { "Lambertian": { "texture": { "SolidTexture": { "color": { "red": 0.8, "green": 0.3, "blue": 0.3 } } } } },
+//!       "shape": { "Sphere": { "center": { "x": 0.0, "y": 0.5, "z": 0.0 }, "radius": 0.5 } }
+//!     }
+//!   ],
+//!   "lights": [
+//!     { "Point": { "position": { "x": 2.0, "y": 3.0, "z": 2.0 }, "color": { "red": 1.0, "green": 1.0, "blue": 1.0 }, "intensity": 10.0 } }
+//!   ]
+//! }
+//! ```
+//!
+//! The same scene in TOML:
+//!
+//! ```toml
+//! [camera]
+//! origin = { x = 0.0, y = 1.0, z = 4.0 }
+//! look_at = { x = 0.0, y = 0.5, z = 0.0 }
+//! vertical_fov_degrees = 30.0
+//!
+//! [renderer]
+//! samples = 50
+//! max_bounce_depth = 8
+//!
+//! [[elements]]
+//! material.Lambertian.texture.SolidTexture.color = { red = 0.8, green = 0.3, blue = 0.3 }
+//! shape.Sphere = { center = { x = 0.0, y = 0.5, z = 0.0 }, radius = 0.5 }
+//!
+//! [[lights]]
+//! Point = { position = { x = 2.0, y = 3.0, z = 2.0 }, color = { red = 1.0, green = 1.0, blue = 1.0 }, intensity = 10.0 }
+//! ```
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::animation::Animation;
+use crate::camera::CameraConfig;
+use crate::light::LightType;
+use crate::renderer::RayTracerConfig;
+use crate::scene::{Element, Scene};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SceneFormat {
+    Json,
+    Toml,
+}
+
+impl SceneFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<SceneFormat> {
+        match path.as_ref().extension()?.to_str()?.to_lowercase().as_str() {
+            "json" => Some(SceneFormat::Json),
+            "toml" => Some(SceneFormat::Toml),
+            _      => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: PathBuf, error: std::io::Error },
+    UnknownFormat { path: PathBuf },
+    /// `field` is the path to the offending value, e.g. `elements[2].shape.Sphere.radius`
+    Parse { file: String, field: String, message: String },
+    Serialize { message: String },
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::UnknownFormat { path } => {
+                write!(f, "{}: unknown scene format, expected a .json or .toml file", path.display())
+            }
+            SceneError::Parse { file, field, message } => {
+                if field.is_empty() || field == "." {
+                    write!(f, "{}: {}", file, message)
+                } else {
+                    write!(f, "{}: invalid value for `{}`: {}", file, field, message)
+                }
+            }
+            SceneError::Serialize { message } => write!(f, "failed to serialize scene: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    pub camera: CameraConfig,
+    #[serde(default)]
+    pub renderer: RayTracerConfig,
+    #[serde(default)]
+    pub elements: Vec<Element>,
+    /// point, spot and directional lights
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<LightType>,
+    /// keyframes for rendering the scene as an image sequence, see `animation`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<Animation>,
+}
+
+impl SceneFile {
+    /// `file` is only used to label errors
+    pub fn from_str(source: &str, format: SceneFormat, file: &str) -> Result<SceneFile, SceneError> {
+        let parse_error = |field: String, message: String| SceneError::Parse {
+            file: file.to_string(),
+            field,
+            message,
+        };
+
+        match format {
+            SceneFormat::Json => {
+                let deserializer = &mut serde_json::Deserializer::from_str(source);
+
+                serde_path_to_error::deserialize(deserializer)
+                    .map_err(|e| parse_error(e.path().to_string(), e.into_inner().to_string()))
+            }
+            SceneFormat::Toml => {
+                let deserializer = toml::Deserializer::new(source);
+
+                serde_path_to_error::deserialize(deserializer)
+                    .map_err(|e| parse_error(e.path().to_string(), e.into_inner().message().to_string()))
+            }
+        }
+    }
+
+    pub fn to_string(&self, format: SceneFormat) -> Result<String, SceneError> {
+        let result = match format {
+            SceneFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            SceneFormat::Toml => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+        };
+
+        result.map_err(|message| SceneError::Serialize { message })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneFile, SceneError> {
+        let path = path.as_ref();
+        let format = SceneFormat::from_path(path)
+            .ok_or_else(|| SceneError::UnknownFormat { path: path.to_path_buf() })?;
+        let source = std::fs::read_to_string(path)
+            .map_err(|error| SceneError::Io { path: path.to_path_buf(), error })?;
+
+        SceneFile::from_str(&source, format, &path.display().to_string())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        let format = SceneFormat::from_path(path)
+            .ok_or_else(|| SceneError::UnknownFormat { path: path.to_path_buf() })?;
+
+        std::fs::write(path, self.to_string(format)?)
+            .map_err(|error| SceneError::Io { path: path.to_path_buf(), error })
+    }
+}
+
+impl Scene {
+    /// load a scene file, see `scene_file` for the format
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<(CameraConfig, RayTracerConfig, Scene), SceneError> {
+        let file = SceneFile::load(path)?;
+
+        Ok((file.camera, file.renderer, Scene::new(file.elements).with_lights(file.lights)))
+    }
+
+    /// save the scene, along with the camera and ray tracer settings used to render it
+    pub fn save<P: AsRef<Path>>(&self, path: P, camera: &CameraConfig, renderer: &RayTracerConfig) -> Result<(), SceneError> {
+        SceneFile {
+            camera: camera.clone(),
+            renderer: renderer.clone(),
+            elements: self.objects.clone(),
+            lights: self.lights.clone(),
+            animation: None,
+        }.save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cornell_box() -> SceneFile {
+        let (camera, scene) = Scene::cornell_box();
+
+        SceneFile {
+            camera,
+            renderer: RayTracerConfig::default(),
+            elements: scene.objects.clone(),
+            lights: scene.lights.clone(),
+            animation: None,
+        }
+    }
+
+    fn assert_round_trip(format: SceneFormat) {
+        let file = cornell_box();
+        let source = file.to_string(format).unwrap();
+
+        let parsed = SceneFile::from_str(&source, format, "cornell_box").unwrap();
+        assert_eq!(parsed.elements.len(), file.elements.len());
+        assert!(parsed.elements.iter().zip(&file.elements).all(|(a, b)| a.id == b.id));
+
+        // nothing is lost on the way
+        assert_eq!(parsed.to_string(format).unwrap(), source);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_round_trip(SceneFormat::Json);
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        assert_round_trip(SceneFormat::Toml);
+    }
+
+    /// the cornell box with the first field of the first element's shape
+    /// replaced by a string, and the path to that field
+    fn bad_scene() -> (serde_json::Value, String) {
+        let source = cornell_box().to_string(SceneFormat::Json).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&source).unwrap();
+
+        let shape = value["elements"][0]["shape"].as_object_mut().unwrap();
+        let (variant, fields) = shape.iter_mut().next().unwrap();
+        let variant = variant.clone();
+        let fields = fields.as_object_mut().unwrap();
+        let field = fields.keys().next().unwrap().clone();
+        fields.insert(field.clone(), serde_json::Value::from("not a number"));
+
+        (value, format!("elements[0].shape.{}.{}", variant, field))
+    }
+
+    fn remove_nulls(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                fields.retain(|_, v| !v.is_null());
+                fields.values_mut().for_each(remove_nulls);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+            _ => {}
+        }
+    }
+
+    fn parse_error(source: &str, format: SceneFormat) -> (String, String) {
+        match SceneFile::from_str(source, format, "bad") {
+            Err(error @ SceneError::Parse { .. }) => {
+                let message = error.to_string();
+                match error {
+                    SceneError::Parse { field, .. } => (field, message),
+                    _ => unreachable!(),
+                }
+            }
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn errors_name_the_bad_field() {
+        let (value, path) = bad_scene();
+
+        let (field, message) = parse_error(&serde_json::to_string(&value).unwrap(), SceneFormat::Json);
+        assert_eq!(field, path);
+        assert!(message.starts_with(&format!("bad: invalid value for `{}`", path)), "{}", message);
+
+        // TOML has no null, unset options are left out instead
+        let mut value = value;
+        remove_nulls(&mut value);
+        let (field, message) = parse_error(&toml::to_string(&value).unwrap(), SceneFormat::Toml);
+        assert_eq!(field, path);
+        assert!(message.starts_with(&format!("bad: invalid value for `{}`", path)), "{}", message);
+    }
+
+    #[test]
+    fn unknown_fields_are_errors() {
+        let (field, message) = parse_error("[camera]\nfocal = 2.0\n", SceneFormat::Toml);
+
+        assert_eq!(field, "camera.focal");
+        assert!(message.contains("focal"), "{}", message);
+    }
+}
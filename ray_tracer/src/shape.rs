@@ -1,18 +1,35 @@
 use std::f64::consts::PI;
+use std::fmt::Display;
 
-use crate::collisions::{CollisionRecord, Collidable, collision_face};
+use crate::collisions::{CollisionRecord, Collidable, collision_face, MIN_INTERSECTION_T};
 use crate::vec3::Vec3;
 use crate::aabb::{AABB, Boundable};
+use crate::mesh::Mesh;
+use crate::instance::Instance;
+use crate::motion::Moving;
+use crate::csg::Csg;
+use crate::volume::{ConstantMedium, HeterogeneousMedium};
+use crate::primitives::{Cylinder, Cone, Capsule, Torus};
 use crate::ray::Ray;
+use crate::utils::Onb;
 
 use serde::{Serialize, Deserialize};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct UV {
     u: f64,
     v: f64
 }
 
+impl UV {
+    pub fn new(u: f64, v: f64) -> UV {
+        UV { u, v }
+    }
+
+    pub fn u(&self) -> f64 { self.u }
+    pub fn v(&self) -> f64 { self.v }
+}
+
 pub trait TextureMap {
     fn map(&self, point: Vec3) -> UV;
 }
@@ -28,6 +45,20 @@ pub enum ShapeType {
     RectangleXZ(RectangleXZ),
     RectangleYZ(RectangleYZ),
     Box(Box),
+    Triangle(Triangle),
+    Quad(Quad),
+    Disk(Disk),
+    Plane(Plane),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Torus(Torus),
+    Mesh(Mesh),
+    Instance(Instance),
+    Moving(Moving),
+    Csg(Csg),
+    ConstantMedium(ConstantMedium),
+    HeterogeneousMedium(HeterogeneousMedium),
 }
 
 impl Collidable for ShapeType {
@@ -37,11 +68,51 @@ impl Collidable for ShapeType {
             ShapeType::RectangleXY(c) => c.collide(ray),
             ShapeType::RectangleXZ(c) => c.collide(ray),
             ShapeType::RectangleYZ(c) => c.collide(ray),
-            ShapeType::Box(c)         => c.collide(ray)
+            ShapeType::Box(c)         => c.collide(ray),
+            ShapeType::Triangle(c)    => c.collide(ray),
+            ShapeType::Quad(c)        => c.collide(ray),
+            ShapeType::Disk(c)        => c.collide(ray),
+            ShapeType::Plane(c)       => c.collide(ray),
+            ShapeType::Cylinder(c)    => c.collide(ray),
+            ShapeType::Cone(c)        => c.collide(ray),
+            ShapeType::Capsule(c)     => c.collide(ray),
+            ShapeType::Torus(c)       => c.collide(ray),
+            ShapeType::Mesh(c)        => c.collide(ray),
+            ShapeType::Instance(c)    => c.collide(ray),
+            ShapeType::Moving(c)      => c.collide(ray),
+            ShapeType::Csg(c)         => c.collide(ray),
+            ShapeType::ConstantMedium(c) => c.collide(ray),
+            ShapeType::HeterogeneousMedium(c) => c.collide(ray),
        } 
     }
 }
 
+impl Boundable for ShapeType {
+    fn bound(&self) -> AABB {
+        match self {
+            ShapeType::Sphere(b)      => b.bound(),
+            ShapeType::RectangleXY(b) => b.bound(),
+            ShapeType::RectangleXZ(b) => b.bound(),
+            ShapeType::RectangleYZ(b) => b.bound(),
+            ShapeType::Box(b)         => b.bound(),
+            ShapeType::Triangle(b)    => b.bound(),
+            ShapeType::Quad(b)        => b.bound(),
+            ShapeType::Disk(b)        => b.bound(),
+            ShapeType::Plane(b)       => b.bound(),
+            ShapeType::Cylinder(b)    => b.bound(),
+            ShapeType::Cone(b)        => b.bound(),
+            ShapeType::Capsule(b)     => b.bound(),
+            ShapeType::Torus(b)       => b.bound(),
+            ShapeType::Mesh(b)        => b.bound(),
+            ShapeType::Instance(b)    => b.bound(),
+            ShapeType::Moving(b)      => b.bound(),
+            ShapeType::Csg(b)         => b.bound(),
+            ShapeType::ConstantMedium(b) => b.bound(),
+            ShapeType::HeterogeneousMedium(b) => b.bound(),
+        }
+    }
+}
+
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Sphere {
@@ -102,8 +173,10 @@ impl SurfaceNormal for Sphere {
 
 impl Collidable for Sphere {
     fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        // the far intersection is used when the ray starts inside of the sphere
         let intersection_time = match self.intersections(ray) {
-            Some((r1, r2)) => f64::min(r1, r2),
+            Some((r1, _)) if r1 > MIN_INTERSECTION_T => r1,
+            Some((_, r2)) if r2 > MIN_INTERSECTION_T => r2,
             _ => return None,
         };
 
@@ -146,6 +219,11 @@ impl RectangleXY {
     pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, flip_normal: bool) -> RectangleXY {
         RectangleXY { x0, x1, y0, y1, k, flip_normal }
     }
+
+    /// (x0, x1, y0, y1, k)
+    pub fn extent(&self) -> (f64, f64, f64, f64, f64) {
+        (self.x0, self.x1, self.y0, self.y1, self.k)
+    }
 }
 
 impl Collidable for RectangleXY {
@@ -213,6 +291,11 @@ impl RectangleXZ {
     pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, flip_normal: bool) -> RectangleXZ {
         RectangleXZ { x0, x1, z0, z1, k, flip_normal }
     }
+
+    /// (x0, x1, z0, z1, k)
+    pub fn extent(&self) -> (f64, f64, f64, f64, f64) {
+        (self.x0, self.x1, self.z0, self.z1, self.k)
+    }
 }
 
 impl Collidable for RectangleXZ {
@@ -279,6 +362,11 @@ impl RectangleYZ {
     pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, flip_normal: bool) -> RectangleYZ {
         RectangleYZ { y0, y1, z0, z1, k, flip_normal }
     }
+
+    /// (y0, y1, z0, z1, k)
+    pub fn extent(&self) -> (f64, f64, f64, f64, f64) {
+        (self.y0, self.y1, self.z0, self.z1, self.k)
+    }
 }
 
 impl Collidable for RectangleYZ {
@@ -346,12 +434,27 @@ impl Collidable for RectangleType {
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "DeserializeBox")]
 pub struct Box {
     min: Vec3,
     max: Vec3,
+    #[serde(skip)]
     sides: Vec<RectangleType>
 }
 
+/// the sides of a box are derived from its corners
+#[derive(Deserialize)]
+struct DeserializeBox {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl From<DeserializeBox> for Box {
+    fn from(serialized: DeserializeBox) -> Box {
+        Box::new(serialized.min, serialized.max)
+    }
+}
+
 impl Box {
     pub fn new(min: Vec3, max: Vec3) -> Box {
         Box {
@@ -403,6 +506,10 @@ impl Collidable for Box {
         // to simplify the Box shape to one struct, and remove all the rectangles
         for side in &self.sides {
             if let Some(record) = side.collide(ray) {
+                if record.t <= MIN_INTERSECTION_T {
+                    continue;
+                }
+
                 if c_record.as_ref().is_none() || record.t < c_record.as_ref().unwrap().t {
                     c_record = Some(record); 
                 }
@@ -418,3 +525,561 @@ impl Boundable for Box {
         AABB::new(self.min, self.max)
     }
 }
+
+/// Triangle with optional per-vertex normals and texture coordinates.
+/// Without vertex normals the triangle is flat shaded, and without vertex
+/// texture coordinates the barycentric coordinates are used as the UV.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    #[serde(default)]
+    normals: Option<[Vec3; 3]>,
+    #[serde(default)]
+    uvs: Option<[UV; 3]>,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Vec3; 3]) -> Triangle {
+        Triangle { vertices, normals: None, uvs: None }
+    }
+
+    pub fn with_attributes(vertices: [Vec3; 3], normals: Option<[Vec3; 3]>, uvs: Option<[UV; 3]>) -> Triangle {
+        Triangle { vertices, normals, uvs }
+    }
+
+    pub fn vertices(&self) -> [Vec3; 3] { self.vertices }
+
+    /// normal of the plane the triangle lies in, wound counter-clockwise
+    pub fn geometric_normal(&self) -> Vec3 {
+        let [a, b, c] = self.vertices;
+
+        Vec3::cross(b - a, c - a).normalize()
+    }
+
+    pub fn area(&self) -> f64 {
+        let [a, b, c] = self.vertices;
+
+        Vec3::cross(b - a, c - a).length() / 2.0
+    }
+
+    /// barycentric coordinates (w0, w1, w2) of a point in the plane of the triangle
+    fn barycentric(&self, point: Vec3) -> [f64; 3] {
+        let [a, b, c] = self.vertices;
+        let normal = Vec3::cross(b - a, c - a);
+        let area = normal.length_squared();
+
+        let w1 = Vec3::dot(Vec3::cross(point - a, c - a), normal) / area;
+        let w2 = Vec3::dot(Vec3::cross(b - a, point - a), normal) / area;
+
+        [1.0 - w1 - w2, w1, w2]
+    }
+
+    fn interpolated_normal(&self, weights: [f64; 3]) -> Vec3 {
+        let geometric = self.geometric_normal();
+
+        match self.normals {
+            Some([n0, n1, n2]) => {
+                let normal = n0 * weights[0] + n1 * weights[1] + n2 * weights[2];
+
+                if normal.near_zero() {
+                    return geometric;
+                }
+
+                // the shading normal must stay on the same side as the surface
+                let normal = normal.normalize();
+                if Vec3::dot(normal, geometric) < 0.0 { -normal } else { normal }
+            }
+            None => geometric,
+        }
+    }
+
+    fn interpolated_uv(&self, weights: [f64; 3]) -> UV {
+        match self.uvs {
+            Some([t0, t1, t2]) => UV {
+                u: t0.u * weights[0] + t1.u * weights[1] + t2.u * weights[2],
+                v: t0.v * weights[0] + t1.v * weights[1] + t2.v * weights[2],
+            },
+            None => UV { u: weights[1], v: weights[2] },
+        }
+    }
+}
+
+impl Collidable for Triangle {
+    /// Möller–Trumbore intersection
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let [a, b, c] = self.vertices;
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = Vec3::cross(ray.direction, edge2);
+        let det = Vec3::dot(edge1, p);
+
+        // ray is parallel to the triangle
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let to_origin = ray.origin - a;
+
+        let w1 = Vec3::dot(to_origin, p) * inv_det;
+        if !(0.0..=1.0).contains(&w1) {
+            return None;
+        }
+
+        let q = Vec3::cross(to_origin, edge1);
+        let w2 = Vec3::dot(ray.direction, q) * inv_det;
+        if w2 < 0.0 || w1 + w2 > 1.0 {
+            return None;
+        }
+
+        let t = Vec3::dot(edge2, q) * inv_det;
+        let weights = [1.0 - w1 - w2, w1, w2];
+        let s_normal = self.interpolated_normal(weights);
+
+        Some(CollisionRecord {
+            point: ray.position_at(t),
+            s_normal,
+            t,
+            uv: self.interpolated_uv(weights),
+            // the face is determined by the true surface, not the shading normal
+            face: collision_face(ray.direction, self.geometric_normal()),
+        })
+    }
+}
+
+impl Boundable for Triangle {
+    fn bound(&self) -> AABB {
+        let [a, b, c] = self.vertices;
+        // pad the box so that axis-aligned triangles don't have a flat bound
+        let padding = Vec3::ones() * 0.0001;
+
+        let min = Vec3::new(
+            a.x.min(b.x).min(c.x),
+            a.y.min(b.y).min(c.y),
+            a.z.min(b.z).min(c.z),
+        );
+        let max = Vec3::new(
+            a.x.max(b.x).max(c.x),
+            a.y.max(b.y).max(c.y),
+            a.z.max(b.z).max(c.z),
+        );
+
+        AABB::new(min - padding, max + padding)
+    }
+}
+
+impl SurfaceNormal for Triangle {
+    fn surface_normal(&self, point: Vec3) -> Vec3 {
+        self.interpolated_normal(self.barycentric(point))
+    }
+}
+
+impl TextureMap for Triangle {
+    fn map(&self, point: Vec3) -> UV {
+        self.interpolated_uv(self.barycentric(point))
+    }
+}
+
+/// intersection of a ray with the plane through `point` with (unit) `normal`,
+/// None if the ray is parallel to the plane
+fn plane_intersection(ray: Ray, point: Vec3, normal: Vec3) -> Option<f64> {
+    let denominator = Vec3::dot(normal, ray.direction);
+
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+
+    Some(Vec3::dot(normal, point - ray.origin) / denominator)
+}
+
+/// Shapes that can't be hit because they have no area or no orientation
+#[derive(Debug)]
+pub enum ShapeError {
+    /// the edges of a quad are parallel, or one of them is zero
+    DegenerateQuad,
+    /// a disk or plane has a zero normal
+    ZeroNormal,
+}
+
+impl Display for ShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShapeError::DegenerateQuad => write!(f, "the edges of the quad don't span an area"),
+            ShapeError::ZeroNormal     => write!(f, "the normal of the shape is zero"),
+        }
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+/// whether a normal can be normalized
+fn is_valid_normal(normal: Vec3) -> bool {
+    let length = normal.length_squared();
+
+    length != 0.0 && length.is_finite()
+}
+
+/// Parallelogram with a corner at `corner`, spanned by the edges `u` and `v`.
+/// The normal is u x v, and the UV is the position along the two edges.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "DeserializeQuad")]
+pub struct Quad {
+    corner: Vec3,
+    u: Vec3,
+    v: Vec3,
+}
+
+#[derive(Deserialize)]
+struct DeserializeQuad {
+    corner: Vec3,
+    u: Vec3,
+    v: Vec3,
+}
+
+impl TryFrom<DeserializeQuad> for Quad {
+    type Error = ShapeError;
+
+    fn try_from(serialized: DeserializeQuad) -> Result<Quad, ShapeError> {
+        Quad::new(serialized.corner, serialized.u, serialized.v)
+    }
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, u: Vec3, v: Vec3) -> Result<Quad, ShapeError> {
+        if !is_valid_normal(Vec3::cross(u, v)) {
+            return Err(ShapeError::DegenerateQuad);
+        }
+
+        Ok(Quad { corner, u, v })
+    }
+
+    pub fn corner(&self) -> Vec3 { self.corner }
+    pub fn edges(&self) -> (Vec3, Vec3) { (self.u, self.v) }
+
+    pub fn area(&self) -> f64 {
+        Vec3::cross(self.u, self.v).length()
+    }
+
+    /// coordinates of a point in the plane of the quad, along its edges
+    fn coordinates(&self, point: Vec3) -> (f64, f64) {
+        let n = Vec3::cross(self.u, self.v);
+        let w = n / n.length_squared();
+        let planar = point - self.corner;
+
+        (Vec3::dot(w, Vec3::cross(planar, self.v)), Vec3::dot(w, Vec3::cross(self.u, planar)))
+    }
+}
+
+impl Collidable for Quad {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let normal = self.surface_normal(self.corner);
+        let t = plane_intersection(ray, self.corner, normal)?;
+        let point = ray.position_at(t);
+        let (alpha, beta) = self.coordinates(point);
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(CollisionRecord {
+            point,
+            s_normal: normal,
+            t,
+            uv: UV { u: alpha, v: beta },
+            face: collision_face(ray.direction, normal),
+        })
+    }
+}
+
+impl Boundable for Quad {
+    fn bound(&self) -> AABB {
+        let corners = [self.corner, self.corner + self.u, self.corner + self.v, self.corner + self.u + self.v];
+        // pad the box so that axis-aligned quads don't have a flat bound
+        let padding = Vec3::ones() * 0.0001;
+
+        let bound = corners
+            .iter()
+            .fold(AABB::empty(), |bound, p| AABB::surrounding(bound, AABB::new(*p, *p)));
+
+        AABB::new(bound.min() - padding, bound.max() + padding)
+    }
+}
+
+impl SurfaceNormal for Quad {
+    fn surface_normal(&self, _point: Vec3) -> Vec3 {
+        Vec3::cross(self.u, self.v).normalize()
+    }
+}
+
+impl TextureMap for Quad {
+    fn map(&self, point: Vec3) -> UV {
+        let (u, v) = self.coordinates(point);
+
+        UV { u, v }
+    }
+}
+
+/// Flat disk facing along `normal`. The UV maps the square around the disk
+/// to [0, 1], so images are placed on it like a decal.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "DeserializeDisk")]
+pub struct Disk {
+    center: Vec3,
+    normal: Vec3,
+    radius: f64,
+}
+
+#[derive(Deserialize)]
+struct DeserializeDisk {
+    center: Vec3,
+    normal: Vec3,
+    radius: f64,
+}
+
+impl TryFrom<DeserializeDisk> for Disk {
+    type Error = ShapeError;
+
+    fn try_from(serialized: DeserializeDisk) -> Result<Disk, ShapeError> {
+        Disk::new(serialized.center, serialized.normal, serialized.radius)
+    }
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64) -> Result<Disk, ShapeError> {
+        if !is_valid_normal(normal) {
+            return Err(ShapeError::ZeroNormal);
+        }
+
+        Ok(Disk { center, normal, radius })
+    }
+
+    pub fn center(&self) -> Vec3 { self.center }
+    pub fn radius(&self) -> f64 { self.radius }
+
+    pub fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    /// tangent directions of the disk
+    pub fn basis(&self) -> Onb {
+        Onb::from_w(self.normal)
+    }
+}
+
+impl Collidable for Disk {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let normal = self.normal.normalize();
+        let t = plane_intersection(ray, self.center, normal)?;
+        let point = ray.position_at(t);
+
+        if (point - self.center).length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        Some(CollisionRecord {
+            point,
+            s_normal: normal,
+            t,
+            uv: self.map(point),
+            face: collision_face(ray.direction, normal),
+        })
+    }
+}
+
+impl Boundable for Disk {
+    fn bound(&self) -> AABB {
+        let n = self.normal.normalize();
+        // extent of the circle along each axis, padded so that it isn't flat
+        let extent = Vec3::new(
+            self.radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        ) + Vec3::ones() * 0.0001;
+
+        AABB::new(self.center - extent, self.center + extent)
+    }
+}
+
+impl SurfaceNormal for Disk {
+    fn surface_normal(&self, _point: Vec3) -> Vec3 {
+        self.normal.normalize()
+    }
+}
+
+impl TextureMap for Disk {
+    fn map(&self, point: Vec3) -> UV {
+        let basis = self.basis();
+        let offset = point - self.center;
+
+        UV {
+            u: 0.5 + Vec3::dot(offset, basis.u) / (2.0 * self.radius),
+            v: 0.5 + Vec3::dot(offset, basis.v) / (2.0 * self.radius),
+        }
+    }
+}
+
+/// Infinite plane through `point`, facing along `normal`. The UV is the
+/// position on the plane in world units, so textures repeat every unit.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "DeserializePlane")]
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+}
+
+#[derive(Deserialize)]
+struct DeserializePlane {
+    point: Vec3,
+    normal: Vec3,
+}
+
+impl TryFrom<DeserializePlane> for Plane {
+    type Error = ShapeError;
+
+    fn try_from(serialized: DeserializePlane) -> Result<Plane, ShapeError> {
+        Plane::new(serialized.point, serialized.normal)
+    }
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Result<Plane, ShapeError> {
+        if !is_valid_normal(normal) {
+            return Err(ShapeError::ZeroNormal);
+        }
+
+        Ok(Plane { point, normal })
+    }
+
+    pub fn point(&self) -> Vec3 { self.point }
+}
+
+impl Collidable for Plane {
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let normal = self.normal.normalize();
+        let t = plane_intersection(ray, self.point, normal)?;
+        let point = ray.position_at(t);
+
+        Some(CollisionRecord {
+            point,
+            s_normal: normal,
+            t,
+            uv: self.map(point),
+            face: collision_face(ray.direction, normal),
+        })
+    }
+}
+
+impl Boundable for Plane {
+    /// unbounded, unlike every other shape
+    fn bound(&self) -> AABB {
+        AABB::new(Vec3::ones() * f64::NEG_INFINITY, Vec3::ones() * f64::INFINITY)
+    }
+}
+
+impl SurfaceNormal for Plane {
+    fn surface_normal(&self, _point: Vec3) -> Vec3 {
+        self.normal.normalize()
+    }
+}
+
+impl TextureMap for Plane {
+    fn map(&self, point: Vec3) -> UV {
+        let basis = Onb::from_w(self.normal);
+        let offset = point - self.point;
+
+        UV { u: Vec3::dot(offset, basis.u), v: Vec3::dot(offset, basis.v) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::Face;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    fn down_from(x: f64, z: f64) -> Ray {
+        Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -1.0, 0.0))
+    }
+
+    #[test]
+    fn rejects_degenerate_shapes() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+
+        assert!(matches!(Quad::new(Vec3::zeros(), x, x * 2.0), Err(ShapeError::DegenerateQuad)));
+        assert!(matches!(Quad::new(Vec3::zeros(), x, Vec3::zeros()), Err(ShapeError::DegenerateQuad)));
+        assert!(matches!(Disk::new(Vec3::zeros(), Vec3::zeros(), 1.0), Err(ShapeError::ZeroNormal)));
+        assert!(matches!(Plane::new(Vec3::zeros(), Vec3::zeros()), Err(ShapeError::ZeroNormal)));
+        assert!(matches!(Plane::new(Vec3::zeros(), x * f64::NAN), Err(ShapeError::ZeroNormal)));
+
+        // scene files go through the same checks
+        let quad = r#"{ "corner": { "x": 0, "y": 0, "z": 0 }, "u": { "x": 1, "y": 0, "z": 0 }, "v": { "x": 2, "y": 0, "z": 0 } }"#;
+        assert!(serde_json::from_str::<Quad>(quad).is_err());
+        let plane = r#"{ "point": { "x": 0, "y": 0, "z": 0 }, "normal": { "x": 0, "y": 0, "z": 0 } }"#;
+        assert!(serde_json::from_str::<Plane>(plane).is_err());
+        let plane = r#"{ "point": { "x": 0, "y": 0, "z": 0 }, "normal": { "x": 0, "y": 2, "z": 0 } }"#;
+        assert!(serde_json::from_str::<Plane>(plane).is_ok());
+    }
+
+    #[test]
+    fn quad_collisions() {
+        // 2 by 1 in the xz plane, facing up
+        let quad = Quad::new(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0), Vec3::new(2.0, 0.0, 0.0)).unwrap();
+        assert_eq!(quad.area(), 2.0);
+
+        let record = quad.collide(down_from(0.5, 0.25)).unwrap();
+        assert_eq!(record.t, 5.0);
+        assert!(close(record.point, Vec3::new(0.5, 0.0, 0.25)));
+        assert!(close(record.s_normal, Vec3::new(0.0, 1.0, 0.0)));
+        assert!(record.face == Face::Outer);
+        assert!((record.uv.u() - 0.25).abs() < 1e-12 && (record.uv.v() - 0.25).abs() < 1e-12);
+
+        let below = Ray::new(Vec3::new(1.0, -1.0, 0.5), Vec3::new(0.0, 1.0, 0.0));
+        assert!(quad.collide(below).unwrap().face == Face::Inner);
+
+        assert!(quad.collide(down_from(2.5, 0.5)).is_none());
+        assert!(quad.collide(down_from(1.0, -0.1)).is_none());
+        assert!(quad.collide(Ray::new(Vec3::new(-1.0, 0.0, 0.5), Vec3::new(1.0, 0.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn disk_collisions() {
+        let disk = Disk::new(Vec3::zeros(), Vec3::new(0.0, 3.0, 0.0), 2.0).unwrap();
+
+        let center = disk.collide(down_from(0.0, 0.0)).unwrap();
+        assert_eq!(center.t, 5.0);
+        assert!(close(center.s_normal, Vec3::new(0.0, 1.0, 0.0)));
+        assert!((center.uv.u() - 0.5).abs() < 1e-12 && (center.uv.v() - 0.5).abs() < 1e-12);
+
+        let edge = disk.collide(down_from(1.4, 1.4)).unwrap();
+        assert!((0.0..=1.0).contains(&edge.uv.u()) && (0.0..=1.0).contains(&edge.uv.v()));
+
+        assert!(disk.collide(down_from(1.5, 1.5)).is_none());
+        // behind the ray, which the scene ignores
+        assert!(disk.collide(Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, 1.0, 0.0))).unwrap().t < 0.0);
+    }
+
+    #[test]
+    fn plane_collisions() {
+        let plane = Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0)).unwrap();
+
+        let record = plane.collide(down_from(100.0, -30.0)).unwrap();
+        assert_eq!(record.t, 6.0);
+        assert!(close(record.s_normal, Vec3::new(0.0, 1.0, 0.0)));
+
+        // the UV is in world units along the plane
+        let uv = |p: Vec3| {
+            let uv = plane.map(p);
+            Vec3::new(uv.u(), uv.v(), 0.0)
+        };
+        let (a, b) = (Vec3::new(1.0, -1.0, 2.0), Vec3::new(4.0, -1.0, -2.0));
+        assert!(((uv(a) - uv(b)).length() - (a - b).length()).abs() < 1e-9);
+
+        assert!(plane.collide(Ray::new(Vec3::zeros(), Vec3::new(1.0, 0.0, 0.0))).is_none());
+        assert!(plane.collide(Ray::new(Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0))).unwrap().t < 0.0);
+        assert!(!plane.bound().is_finite());
+    }
+}
@@ -0,0 +1,295 @@
+//! Procedural daylight, using the Preetham sky model.
+//!
+//! The sky's color is given by the sun's position and the turbidity of the
+//! atmosphere (2 for a very clear sky, around 10 for hazy air). The sun itself
+//! is a small disk of bright light, dimmed and reddened by the atmosphere as it
+//! gets closer to the horizon, and the ground below the horizon is a diffuse
+//! surface lit by both. Radiance is scaled so that a white diffuse surface lit
+//! by a high sun has a radiance of about one.
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::environment::EnvironmentSample;
+use crate::image::Color;
+use crate::utils::{random_float, random_unit_vector, Onb};
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// converts the model's units (kcd/m^2) to the renderer's
+const RADIANCE_SCALE: f64 = 1.0 / 40.0;
+/// illuminance of the sun outside of the atmosphere, in klux
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+/// probability of sampling the sun rather than the whole sky
+const SUN_PROBABILITY: f64 = 0.5;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "DeserializeSky")]
+pub struct Sky {
+    /// direction towards the sun
+    sun_direction: Vec3,
+    turbidity: f64,
+    ground_albedo: Color,
+    sun_angular_radius_degrees: f64,
+    /// scale applied to the sky, sun and ground
+    intensity: f64,
+    #[serde(skip)]
+    model: Arc<SkyModel>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeserializeSky {
+    sun_direction: Vec3,
+    #[serde(default = "default_turbidity")]
+    turbidity: f64,
+    #[serde(default = "default_ground_albedo")]
+    ground_albedo: Color,
+    #[serde(default = "default_sun_angular_radius")]
+    sun_angular_radius_degrees: f64,
+    #[serde(default = "default_intensity")]
+    intensity: f64,
+}
+
+fn default_turbidity() -> f64 { 3.0 }
+fn default_ground_albedo() -> Color { Color::new(0.3, 0.3, 0.3) }
+fn default_sun_angular_radius() -> f64 { 0.27 }
+fn default_intensity() -> f64 { 1.0 }
+
+impl From<DeserializeSky> for Sky {
+    fn from(serialized: DeserializeSky) -> Sky {
+        Sky::new(serialized.sun_direction, serialized.turbidity, serialized.ground_albedo)
+            .with_sun_size(serialized.sun_angular_radius_degrees)
+            .with_intensity(serialized.intensity)
+    }
+}
+
+/// coefficients of the Perez sky distribution
+#[derive(Copy, Clone, Default)]
+struct Perez([f64; 5]);
+
+impl Perez {
+    /// relative luminance (or chromaticity) of a direction at `theta` from
+    /// the zenith and `gamma` from the sun
+    fn eval(&self, theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = self.0;
+        let cos_theta = f64::max(theta.cos(), 0.01);
+
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+}
+
+/// values derived from the parameters
+#[derive(Clone, Default)]
+struct SkyModel {
+    /// the sun, clamped to the horizon
+    sun: Vec3,
+    theta_sun: f64,
+    /// luminance and chromaticity at the zenith, divided by the corresponding
+    /// Perez function at the zenith
+    zenith: [f64; 3],
+    perez: [Perez; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    ground_radiance: Color,
+}
+
+impl Sky {
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Color) -> Sky {
+        let mut sky = Sky {
+            sun_direction,
+            turbidity,
+            ground_albedo,
+            sun_angular_radius_degrees: default_sun_angular_radius(),
+            intensity: default_intensity(),
+            model: Arc::default(),
+        };
+        sky.update();
+
+        sky
+    }
+
+    pub fn with_sun_size(mut self, angular_radius_degrees: f64) -> Sky {
+        self.sun_angular_radius_degrees = angular_radius_degrees;
+        self.update();
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Sky {
+        self.intensity = intensity;
+        self.update();
+        self
+    }
+
+    /// sun at `elevation` degrees above the horizon, and `azimuth` degrees
+    /// clockwise from -z when looking down
+    pub fn sun_at(elevation_degrees: f64, azimuth_degrees: f64) -> Vec3 {
+        let (elevation, azimuth) = (elevation_degrees.to_radians(), azimuth_degrees.to_radians());
+
+        Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos())
+    }
+
+    fn update(&mut self) {
+        let t = self.turbidity.clamp(1.0, 20.0);
+
+        let mut sun = self.sun_direction.normalize();
+        if sun.y < 0.0 {
+            sun = Vec3::new(sun.x, 0.0, sun.z).normalize();
+        }
+        let theta_sun = sun.y.clamp(-1.0, 1.0).acos();
+
+        let perez = [
+            Perez([0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703]),
+            Perez([-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452]),
+            Perez([-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]),
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let (s, s2, s3) = (theta_sun, theta_sun * theta_sun, theta_sun.powi(3));
+        let x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        let zenith = [
+            f64::max(luminance, 0.0) / perez[0].eval(0.0, theta_sun),
+            x / perez[1].eval(0.0, theta_sun),
+            y / perez[2].eval(0.0, theta_sun),
+        ];
+
+        let cos_sun_radius = self.sun_angular_radius_degrees.to_radians().cos();
+        let sun_solid_angle = 2.0 * PI * (1.0 - cos_sun_radius);
+        let sun_radiance = sun_transmittance(theta_sun, t) * (SOLAR_ILLUMINANCE / sun_solid_angle);
+
+        self.model = Arc::new(SkyModel {
+            sun,
+            theta_sun,
+            zenith,
+            perez,
+            sun_radiance,
+            cos_sun_radius,
+            ground_radiance: Color::black(),
+        });
+
+        // the ground reflects the light arriving from the sky and the sun
+        let mut irradiance = sun_radiance * (sun_solid_angle * sun.y);
+        let (steps_theta, steps_phi) = (16, 64);
+        let d_theta = 0.5 * PI / steps_theta as f64;
+        let d_phi = 2.0 * PI / steps_phi as f64;
+
+        for i in 0..steps_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..steps_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+
+                irradiance += self.sky_radiance(direction) * (theta.cos() * theta.sin() * d_theta * d_phi);
+            }
+        }
+
+        let ground_radiance = self.ground_albedo * irradiance * (1.0 / PI);
+        self.model = Arc::new(SkyModel { ground_radiance, ..(*self.model).clone() });
+    }
+
+    /// radiance of the sky alone, in the model's units, for directions above the horizon
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        let model = &self.model;
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let gamma = Vec3::dot(direction, model.sun).clamp(-1.0, 1.0).acos();
+
+        let luminance = model.zenith[0] * model.perez[0].eval(theta, gamma);
+        let x = model.zenith[1] * model.perez[1].eval(theta, gamma);
+        let y = model.zenith[2] * model.perez[2].eval(theta, gamma);
+
+        xyy_to_rgb(x, y, luminance)
+    }
+
+    fn sun_visible(&self) -> bool {
+        self.model.theta_sun < 0.5 * PI
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let model = &self.model;
+
+        let radiance = if direction.y < 0.0 {
+            model.ground_radiance
+        } else if self.sun_visible() && Vec3::dot(direction, model.sun) >= model.cos_sun_radius {
+            self.sky_radiance(direction) + model.sun_radiance
+        } else {
+            self.sky_radiance(direction)
+        };
+
+        radiance * (RADIANCE_SCALE * self.intensity)
+    }
+
+    /// sample the sun's disk or, otherwise, any direction
+    pub fn sample(&self) -> Option<EnvironmentSample> {
+        let direction = if self.sun_visible() && random_float() < SUN_PROBABILITY {
+            // uniform in the cone of directions towards the sun
+            let cos_theta = 1.0 - random_float() * (1.0 - self.model.cos_sun_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi = 2.0 * PI * random_float();
+
+            Onb::from_w(self.model.sun).local(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+        } else {
+            random_unit_vector()
+        };
+
+        Some(EnvironmentSample {
+            direction,
+            radiance: self.radiance(direction),
+            pdf: self.pdf(direction),
+        })
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        if !self.sun_visible() {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let sun_pdf = match Vec3::dot(direction, self.model.sun) >= self.model.cos_sun_radius {
+            true  => 1.0 / (2.0 * PI * (1.0 - self.model.cos_sun_radius)),
+            false => 0.0,
+        };
+
+        SUN_PROBABILITY * sun_pdf + (1.0 - SUN_PROBABILITY) / (4.0 * PI)
+    }
+}
+
+/// fraction of the sun's light passing through the atmosphere, for red,
+/// green and blue wavelengths, due to Rayleigh and aerosol scattering
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Color {
+    // relative optical mass of the atmosphere along the sun's rays
+    let mass = 1.0 / (theta_sun.cos() + 0.15 * f64::powf(93.885 - theta_sun.to_degrees(), -1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    let transmittance = |lambda: f64| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * mass).exp();
+
+        rayleigh * aerosol
+    };
+
+    Color::new(transmittance(0.65), transmittance(0.57), transmittance(0.475))
+}
+
+/// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::black();
+    }
+
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+
+    Color::new(
+        f64::max(0.0, 3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z),
+        f64::max(0.0, -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z),
+        f64::max(0.0, 0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z),
+    )
+}
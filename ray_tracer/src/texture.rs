@@ -4,6 +4,7 @@ use crate::shape::UV;
 use crate::image::Color;
 use crate::vec3::Vec3;
 use crate::perlin::Perlin;
+use crate::image_texture::ImageTexture;
 
 use serde::{Deserialize, Serialize, ser::SerializeStruct};
 
@@ -15,7 +16,8 @@ pub trait Texture {
 pub enum TextureType {
     CheckeredTexture(CheckeredTexture),
     SolidTexture(SolidTexture),
-    PerlinTexture(PerlinTexture)
+    PerlinTexture(PerlinTexture),
+    ImageTexture(ImageTexture),
 }
 
 impl Texture for TextureType {
@@ -24,6 +26,7 @@ impl Texture for TextureType {
             TextureType::CheckeredTexture(tx) => tx.value(uv, point),
             TextureType::SolidTexture(tx)     => tx.value(uv, point),
             TextureType::PerlinTexture(tx)    => tx.value(uv, point),
+            TextureType::ImageTexture(tx)     => tx.value(uv, point),
         }
     }
 }
@@ -76,6 +79,8 @@ impl From<Color> for TextureType {
 #[serde(from = "DeserializePerlinTexture")]
 pub struct PerlinTexture {
     scale: f64,
+    /// picks the noise, textures with the same seed look the same on every run
+    seed: u64,
     #[serde(skip)]
     noise_gen: Arc<Box<Perlin>>
 }
@@ -83,11 +88,13 @@ pub struct PerlinTexture {
 #[derive(Deserialize)]
 pub struct DeserializePerlinTexture {
     scale: f64,
+    #[serde(default)]
+    seed: u64,
 }
 
 impl From<DeserializePerlinTexture> for PerlinTexture {
     fn from(serialized: DeserializePerlinTexture) -> PerlinTexture {
-        PerlinTexture::new_scaled(serialized.scale)
+        PerlinTexture::seeded(serialized.scale, serialized.seed)
     }
 }
 
@@ -97,9 +104,14 @@ impl PerlinTexture {
     }
 
     pub fn new_scaled(scale: f64) -> PerlinTexture {
+        PerlinTexture::seeded(scale, 0)
+    }
+
+    pub fn seeded(scale: f64, seed: u64) -> PerlinTexture {
         PerlinTexture { 
-            noise_gen: Arc::new(Box::new(Perlin::new())),
-            scale
+            noise_gen: Arc::new(Box::new(Perlin::seeded(seed))),
+            scale,
+            seed,
         }
     }
 }
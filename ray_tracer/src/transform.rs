@@ -0,0 +1,273 @@
+//! Affine transforms, made of a scale, followed by a rotation and a translation.
+
+use crate::aabb::AABB;
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// 3x3 matrix, stored by rows
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Matrix3 {
+    rows: [[f64; 3]; 3],
+}
+
+impl Default for Matrix3 {
+    fn default() -> Self { Matrix3::identity() }
+}
+
+impl Matrix3 {
+    fn identity() -> Matrix3 {
+        Matrix3 { rows: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] }
+    }
+
+    fn diagonal(v: Vec3) -> Matrix3 {
+        Matrix3 { rows: [[v.x, 0.0, 0.0], [0.0, v.y, 0.0], [0.0, 0.0, v.z]] }
+    }
+
+    /// rotation around x, then y, then z
+    fn rotation(degrees: Vec3) -> Matrix3 {
+        let (sx, cx) = degrees.x.to_radians().sin_cos();
+        let (sy, cy) = degrees.y.to_radians().sin_cos();
+        let (sz, cz) = degrees.z.to_radians().sin_cos();
+
+        let x = Matrix3 { rows: [[1.0, 0.0, 0.0], [0.0, cx, -sx], [0.0, sx, cx]] };
+        let y = Matrix3 { rows: [[cy, 0.0, sy], [0.0, 1.0, 0.0], [-sy, 0.0, cy]] };
+        let z = Matrix3 { rows: [[cz, -sz, 0.0], [sz, cz, 0.0], [0.0, 0.0, 1.0]] };
+
+        z.mul(&y.mul(&x))
+    }
+
+    fn mul(&self, other: &Matrix3) -> Matrix3 {
+        let mut rows = [[0.0; 3]; 3];
+
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.rows[i][k] * other.rows[k][j]).sum();
+            }
+        }
+
+        Matrix3 { rows }
+    }
+
+    fn transpose(&self) -> Matrix3 {
+        let mut rows = [[0.0; 3]; 3];
+
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.rows[j][i];
+            }
+        }
+
+        Matrix3 { rows }
+    }
+
+    fn apply(&self, v: Vec3) -> Vec3 {
+        let [a, b, c] = self.rows;
+
+        Vec3::new(
+            a[0] * v.x + a[1] * v.y + a[2] * v.z,
+            b[0] * v.x + b[1] * v.y + b[2] * v.z,
+            c[0] * v.x + c[1] * v.y + c[2] * v.z,
+        )
+    }
+}
+
+/// Places an object in the world: points are scaled (along the object's own
+/// axes), rotated around x, y and then z, and finally translated.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "DeserializeTransform")]
+pub struct Transform {
+    translation: Vec3,
+    rotation_degrees: Vec3,
+    scale: Vec3,
+    /// object to world, without the translation
+    #[serde(skip)]
+    linear: Matrix3,
+    /// world to object, without the translation
+    #[serde(skip)]
+    inverse: Matrix3,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DeserializeTransform {
+    translation: Vec3,
+    rotation_degrees: Vec3,
+    scale: Vec3,
+}
+
+impl Default for DeserializeTransform {
+    fn default() -> Self {
+        DeserializeTransform {
+            translation: Vec3::zeros(),
+            rotation_degrees: Vec3::zeros(),
+            scale: Vec3::ones(),
+        }
+    }
+}
+
+impl From<DeserializeTransform> for Transform {
+    fn from(serialized: DeserializeTransform) -> Transform {
+        Transform::new(serialized.translation, serialized.rotation_degrees, serialized.scale)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self { Transform::identity() }
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation_degrees: Vec3, scale: Vec3) -> Transform {
+        let rotation = Matrix3::rotation(rotation_degrees);
+        let inverse_scale = Vec3::new(1.0 / scale.x, 1.0 / scale.y, 1.0 / scale.z);
+
+        Transform {
+            translation,
+            rotation_degrees,
+            scale,
+            linear: rotation.mul(&Matrix3::diagonal(scale)),
+            inverse: Matrix3::diagonal(inverse_scale).mul(&rotation.transpose()),
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(Vec3::zeros(), Vec3::zeros(), Vec3::ones())
+    }
+
+    pub fn translation(translation: Vec3) -> Transform {
+        Transform::new(translation, Vec3::zeros(), Vec3::ones())
+    }
+
+    pub fn rotation(rotation_degrees: Vec3) -> Transform {
+        Transform::new(Vec3::zeros(), rotation_degrees, Vec3::ones())
+    }
+
+    pub fn scaling(scale: Vec3) -> Transform {
+        Transform::new(Vec3::zeros(), Vec3::zeros(), scale)
+    }
+
+    pub fn translation_vector(&self) -> Vec3 { self.translation }
+    pub fn rotation_degrees(&self) -> Vec3 { self.rotation_degrees }
+    pub fn scale(&self) -> Vec3 { self.scale }
+
+    /// object space point to world space
+    pub fn point(&self, point: Vec3) -> Vec3 {
+        self.linear.apply(point) + self.translation
+    }
+
+    /// object space direction to world space, not normalized
+    pub fn vector(&self, vector: Vec3) -> Vec3 {
+        self.linear.apply(vector)
+    }
+
+    /// object space surface normal to world space, normalized
+    pub fn normal(&self, normal: Vec3) -> Vec3 {
+        // normals are transformed by the inverse transpose
+        self.inverse.transpose().apply(normal).normalize()
+    }
+
+    /// ratio of the world space area to the object space area of a small
+    /// piece of surface with the (object space) normal
+    pub fn area_scale(&self, normal: Vec3) -> f64 {
+        let determinant = self.scale.x * self.scale.y * self.scale.z;
+
+        determinant.abs() * self.inverse.transpose().apply(normal).length() / normal.length()
+    }
+
+    /// world space point to object space
+    pub fn inverse_point(&self, point: Vec3) -> Vec3 {
+        self.inverse.apply(point - self.translation)
+    }
+
+    /// world space direction to object space, not normalized
+    pub fn inverse_vector(&self, vector: Vec3) -> Vec3 {
+        self.inverse.apply(vector)
+    }
+
+    /// world space box containing an object space box
+    pub fn bound(&self, aabb: AABB) -> AABB {
+        if !aabb.is_finite() {
+            return AABB::new(Vec3::ones() * f64::NEG_INFINITY, Vec3::ones() * f64::INFINITY);
+        }
+
+        let (min, max) = (aabb.min(), aabb.max());
+
+        (0..8)
+            .map(|corner| Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            ))
+            .map(|corner| self.point(corner))
+            .fold(AABB::empty(), |bound, p| AABB::surrounding(bound, AABB::new(p, p)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    /// translated, rotated about every axis, and scaled unevenly
+    fn skewed() -> Transform {
+        Transform::new(Vec3::new(1.0, -2.0, 3.0), Vec3::new(30.0, 45.0, -60.0), Vec3::new(2.0, 0.5, 3.0))
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let transform = skewed();
+
+        for p in [Vec3::zeros(), Vec3::new(1.0, 2.0, 3.0), Vec3::new(-4.0, 0.5, 7.0)] {
+            assert!(close(transform.inverse_point(transform.point(p)), p));
+            assert!(close(transform.point(transform.inverse_point(p)), p));
+            assert!(close(transform.inverse_vector(transform.vector(p)), p));
+        }
+
+        // vectors aren't translated
+        assert!(close(transform.vector(Vec3::zeros()), Vec3::zeros()));
+        assert!(close(transform.point(Vec3::zeros()), Vec3::new(1.0, -2.0, 3.0)));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let transform = skewed();
+        let normal = transform.normal(Vec3::new(1.0, 1.0, 0.0));
+        assert!((normal.length() - 1.0).abs() < 1e-12);
+
+        for tangent in [Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(2.0, -2.0, 5.0)] {
+            assert!(Vec3::dot(normal, transform.vector(tangent)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn area_scale_of_scaled_quad() {
+        let (u, v) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let normal = Vec3::cross(u, v);
+
+        let scaled = Transform::scaling(Vec3::new(2.0, 3.0, 5.0));
+        assert!((scaled.area_scale(normal) - 6.0).abs() < 1e-12);
+        assert!((scaled.area_scale(Vec3::new(0.0, 0.0, -4.0)) - 6.0).abs() < 1e-12);
+
+        // the area of the transformed parallelogram, for any transform
+        let transform = skewed();
+        let area = Vec3::cross(transform.vector(u), transform.vector(v)).length();
+        assert!((transform.area_scale(normal) - area).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bound_contains_transformed_corners() {
+        let transform = skewed();
+        let bound = transform.bound(AABB::new(Vec3::zeros(), Vec3::ones()));
+
+        for corner in 0..8 {
+            let p = transform.point(Vec3::new((corner & 1) as f64, ((corner >> 1) & 1) as f64, (corner >> 2) as f64));
+
+            for i in 0..3 {
+                assert!(bound.min()[i] - 1e-9 <= p[i] && p[i] <= bound.max()[i] + 1e-9);
+            }
+        }
+    }
+}
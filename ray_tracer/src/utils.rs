@@ -1,9 +1,22 @@
-use rand::{thread_rng, Rng};
+use std::cell::RefCell;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use serde::{Serialize, Deserialize};
 use crate::vec3::Vec3;
 
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
 pub fn random_float() -> f64 {
-    thread_rng().gen()
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+/// Reseed the current thread's random number generator, so that the
+/// random numbers it produces from now on are reproducible
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
 }
 
 pub fn random_range(min: f64, max: f64) -> f64 {
@@ -33,6 +46,16 @@ pub fn random_unit_vector() -> Vec3 {
     Vec3::normalized(sample_unit_sphere())
 }
 
+/// direction on the unit hemisphere around z, distributed proportionally
+/// to the cosine of its angle with z
+pub fn random_cosine_direction() -> (f64, f64, f64) {
+    let r1 = random_float();
+    let r2 = random_float();
+    let phi = 2.0 * std::f64::consts::PI * r1;
+
+    (phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), (1.0 - r2).sqrt())
+}
+
 pub fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
     /*
     We take the incident vector, v, and compute the
@@ -100,3 +123,28 @@ pub fn perlin_t_lerp(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
 
     acc
 }
+
+/// Orthonormal basis, with w aligned to a given direction
+#[derive(Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(w: Vec3) -> Onb {
+        let w = w.normalize();
+        // pick any axis that isn't (nearly) parallel to w
+        let a = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = Vec3::cross(w, a).normalize();
+        let u = Vec3::cross(w, v);
+
+        Onb { u, v, w }
+    }
+
+    /// convert a vector from the basis' coordinates to world coordinates
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        self.u * a + self.v * b + self.w * c
+    }
+}
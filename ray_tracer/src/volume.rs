@@ -0,0 +1,664 @@
+//! Participating media, e.g. fog and smoke.
+//!
+//! A medium fills the inside of a solid boundary shape (see `csg::Solid`).
+//! Rays travelling through it scatter at a random distance, sampled in
+//! proportion to the medium's transmittance, so a medium collision is the
+//! point where the ray scatters rather than a point on a surface. Media are
+//! paired with the `Isotropic` material, which picks the scattered direction.
+//!
+//! The density of a `HeterogeneousMedium` varies across space. It is
+//! sampled with delta tracking, and its transmittance is estimated with
+//! ratio tracking, both against the medium's highest density (majorant).
+
+use std::fmt::Display;
+use std::sync::Arc;
+
+use crate::aabb::{AABB, Boundable};
+use crate::collisions::{CollisionRecord, Collidable, Face, MIN_INTERSECTION_T};
+use crate::csg::Solid;
+use crate::instance::Instance;
+use crate::perlin::Perlin;
+use crate::ray::Ray;
+use crate::shape::{ShapeType, UV};
+use crate::utils::random_float;
+use crate::vec3::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+/// Shapes that light travels through rather than bounces off of
+pub trait Medium {
+    /// fraction of the light that makes it through the medium between the
+    /// ray's origin and `distance` along the ray
+    fn transmittance(&self, ray: Ray, distance: f64) -> f64;
+}
+
+#[derive(Debug)]
+pub enum VolumeError {
+    EmptyGrid,
+    VoxelCount { expected: usize, found: usize },
+    /// noise thresholds must be in [0, 1)
+    Threshold { threshold: f64 },
+}
+
+impl Display for VolumeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VolumeError::EmptyGrid => write!(f, "voxel grids need at least one voxel along every axis"),
+            VolumeError::VoxelCount { expected, found } => {
+                write!(f, "expected {} voxel values, found {}", expected, found)
+            }
+            VolumeError::Threshold { threshold } => {
+                write!(f, "noise threshold {} is outside of [0, 1)", threshold)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VolumeError {}
+
+/// Medium of the same density everywhere inside of its boundary
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConstantMedium {
+    boundary: Arc<ShapeType>,
+    /// chance of scattering per unit of distance travelled
+    density: f64,
+}
+
+impl ConstantMedium {
+    pub fn new<S: Into<Arc<ShapeType>>>(boundary: S, density: f64) -> ConstantMedium {
+        ConstantMedium { boundary: boundary.into(), density }
+    }
+
+    pub fn boundary(&self) -> &Arc<ShapeType> { &self.boundary }
+    pub fn density(&self) -> f64 { self.density }
+}
+
+impl Collidable for ConstantMedium {
+    /// distance sampling: the ray scatters after travelling -ln(ξ) / density
+    /// inside of the boundary, which may be split into several pieces
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        if self.density <= 0.0 {
+            return None;
+        }
+
+        let mut remaining = sample_distance(self.density);
+
+        for interval in self.boundary.intervals(ray) {
+            let enter = f64::max(interval.enter.t, MIN_INTERSECTION_T);
+            let length = interval.exit.t - enter;
+
+            if length <= 0.0 {
+                continue;
+            }
+
+            if remaining < length {
+                let t = enter + remaining;
+
+                return Some(CollisionRecord {
+                    point: ray.position_at(t),
+                    // media don't have surfaces, any normal will do
+                    s_normal: -ray.direction,
+                    t,
+                    uv: UV::new(0.0, 0.0),
+                    face: Face::Outer,
+                });
+            }
+
+            remaining -= length;
+        }
+
+        None
+    }
+}
+
+impl Medium for ConstantMedium {
+    fn transmittance(&self, ray: Ray, distance: f64) -> f64 {
+        let length: f64 = self.boundary
+            .intervals(ray)
+            .iter()
+            .map(|i| f64::min(i.exit.t, distance) - f64::max(i.enter.t, MIN_INTERSECTION_T))
+            .filter(|length| *length > 0.0)
+            .sum();
+
+        (-self.density * length).exp()
+    }
+}
+
+impl Boundable for ConstantMedium {
+    fn bound(&self) -> AABB {
+        self.boundary.bound()
+    }
+}
+
+impl From<ConstantMedium> for ShapeType {
+    fn from(medium: ConstantMedium) -> Self {
+        ShapeType::ConstantMedium(medium)
+    }
+}
+
+/// distance travelled before a collision, in a medium of the given density
+fn sample_distance(density: f64) -> f64 {
+    -(1.0 - random_float()).ln() / density
+}
+
+/// Spatially varying density, scaled to [0, 1]
+#[derive(Clone, Serialize, Deserialize)]
+pub enum DensityField {
+    Noise(NoiseField),
+    Voxels(VoxelGrid),
+}
+
+impl DensityField {
+    pub fn value(&self, point: Vec3) -> f64 {
+        match self {
+            DensityField::Noise(f)  => f.value(point),
+            DensityField::Voxels(f) => f.value(point),
+        }
+    }
+
+    /// upper bound of `value`
+    pub fn max_value(&self) -> f64 {
+        match self {
+            DensityField::Noise(_)  => 1.0,
+            DensityField::Voxels(f) => f.max_value,
+        }
+    }
+
+    /// region outside of which the field is zero, None if it's unbounded
+    pub fn bound(&self) -> Option<AABB> {
+        match self {
+            DensityField::Noise(_)  => None,
+            DensityField::Voxels(f) => Some(AABB::new(f.min, f.max)),
+        }
+    }
+}
+
+/// Perlin turbulence. Values below `threshold` are cut off, which breaks the
+/// noise up into separate puffs, like clouds
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "DeserializeNoiseField")]
+pub struct NoiseField {
+    scale: f64,
+    /// number of octaves of noise
+    depth: u32,
+    threshold: f64,
+    /// picks the noise, fields with the same seed look the same on every run
+    seed: u64,
+    #[serde(skip)]
+    noise: Arc<Perlin>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DeserializeNoiseField {
+    scale: f64,
+    depth: u32,
+    threshold: f64,
+    seed: u64,
+}
+
+impl Default for DeserializeNoiseField {
+    fn default() -> Self {
+        DeserializeNoiseField { scale: 1.0, depth: 7, threshold: 0.0, seed: 0 }
+    }
+}
+
+impl TryFrom<DeserializeNoiseField> for NoiseField {
+    type Error = VolumeError;
+
+    fn try_from(serialized: DeserializeNoiseField) -> Result<NoiseField, VolumeError> {
+        NoiseField::new(serialized.scale, serialized.depth, serialized.threshold, serialized.seed)
+    }
+}
+
+impl NoiseField {
+    pub fn new(scale: f64, depth: u32, threshold: f64, seed: u64) -> Result<NoiseField, VolumeError> {
+        if !(0.0..1.0).contains(&threshold) {
+            return Err(VolumeError::Threshold { threshold });
+        }
+
+        Ok(NoiseField { scale, depth, threshold, seed, noise: Arc::new(Perlin::seeded(seed)) })
+    }
+
+    fn value(&self, point: Vec3) -> f64 {
+        let turbulence = self.noise.turbulence(point * self.scale, self.depth);
+
+        ((turbulence - self.threshold) / (1.0 - self.threshold)).clamp(0.0, 1.0)
+    }
+}
+
+impl From<NoiseField> for DensityField {
+    fn from(field: NoiseField) -> Self {
+        DensityField::Noise(field)
+    }
+}
+
+/// Densities sampled on a regular grid spanning the box from `min` to `max`,
+/// interpolated trilinearly between the centers of the voxels.
+/// `values` are ordered by x, then y, then z.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "DeserializeVoxelGrid")]
+pub struct VoxelGrid {
+    min: Vec3,
+    max: Vec3,
+    resolution: [usize; 3],
+    values: Vec<f64>,
+    #[serde(skip)]
+    max_value: f64,
+}
+
+#[derive(Deserialize)]
+struct DeserializeVoxelGrid {
+    min: Vec3,
+    max: Vec3,
+    resolution: [usize; 3],
+    values: Vec<f64>,
+}
+
+impl TryFrom<DeserializeVoxelGrid> for VoxelGrid {
+    type Error = VolumeError;
+
+    fn try_from(serialized: DeserializeVoxelGrid) -> Result<VoxelGrid, VolumeError> {
+        VoxelGrid::new(serialized.min, serialized.max, serialized.resolution, serialized.values)
+    }
+}
+
+impl VoxelGrid {
+    /// values are clamped to [0, 1]
+    pub fn new(min: Vec3, max: Vec3, resolution: [usize; 3], values: Vec<f64>) -> Result<VoxelGrid, VolumeError> {
+        if resolution.contains(&0) {
+            return Err(VolumeError::EmptyGrid);
+        }
+
+        let expected = resolution.iter().product();
+        if values.len() != expected {
+            return Err(VolumeError::VoxelCount { expected, found: values.len() });
+        }
+
+        let values = values.into_iter().map(|v| v.clamp(0.0, 1.0)).collect::<Vec<f64>>();
+        let max_value = values.iter().cloned().fold(0.0, f64::max);
+
+        Ok(VoxelGrid { min, max, resolution, values, max_value })
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [rx, ry, _] = self.resolution;
+
+        self.values[x + rx * (y + ry * z)]
+    }
+
+    fn value(&self, point: Vec3) -> f64 {
+        let mut cells = [(0, 0, 0.0); 3];
+
+        for (i, cell) in cells.iter_mut().enumerate() {
+            if point[i] < self.min[i] || self.max[i] < point[i] {
+                return 0.0;
+            }
+
+            // position in voxels, relative to the center of the first voxel
+            let last = self.resolution[i] - 1;
+            let p = (point[i] - self.min[i]) / (self.max[i] - self.min[i]) * self.resolution[i] as f64 - 0.5;
+            let p = p.clamp(0.0, last as f64);
+            let low = usize::min(p.floor() as usize, last);
+
+            *cell = (low, usize::min(low + 1, last), p - low as f64);
+        }
+
+        let [(x0, x1, u), (y0, y1, v), (z0, z1, w)] = cells;
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+        lerp(
+            lerp(
+                lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), u),
+                lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), u),
+                v,
+            ),
+            lerp(
+                lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), u),
+                lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), u),
+                v,
+            ),
+            w,
+        )
+    }
+}
+
+impl From<VoxelGrid> for DensityField {
+    fn from(field: VoxelGrid) -> Self {
+        DensityField::Voxels(field)
+    }
+}
+
+/// Medium whose density varies across space, e.g. clouds
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HeterogeneousMedium {
+    boundary: Arc<ShapeType>,
+    /// density where the field is 1
+    density: f64,
+    field: DensityField,
+}
+
+impl HeterogeneousMedium {
+    pub fn new<S, F>(boundary: S, density: f64, field: F) -> HeterogeneousMedium
+    where
+        S: Into<Arc<ShapeType>>,
+        F: Into<DensityField>,
+    {
+        HeterogeneousMedium { boundary: boundary.into(), density, field: field.into() }
+    }
+
+    pub fn boundary(&self) -> &Arc<ShapeType> { &self.boundary }
+    pub fn field(&self) -> &DensityField { &self.field }
+
+    pub fn density_at(&self, point: Vec3) -> f64 {
+        self.density * self.field.value(point)
+    }
+
+    /// highest density anywhere in the medium
+    fn majorant(&self) -> f64 {
+        self.density * self.field.max_value()
+    }
+
+    /// pieces of the ray inside of the boundary and the field, from
+    /// MIN_INTERSECTION_T up to `distance`
+    fn spans(&self, ray: Ray, distance: f64) -> Vec<(f64, f64)> {
+        let (t_min, t_max) = match self.field.bound() {
+            Some(bound) => match bound_span(bound, ray) {
+                Some(span) => span,
+                None       => return vec![],
+            },
+            None => (f64::NEG_INFINITY, f64::INFINITY),
+        };
+
+        self.boundary
+            .intervals(ray)
+            .iter()
+            .map(|i| (
+                f64::max(f64::max(i.enter.t, t_min), MIN_INTERSECTION_T),
+                f64::min(f64::min(i.exit.t, t_max), distance),
+            ))
+            .filter(|(enter, exit)| enter < exit)
+            .collect()
+    }
+}
+
+/// times the ray is inside of the box, like `AABB::hit`
+fn bound_span(bound: AABB, ray: Ray) -> Option<(f64, f64)> {
+    let (mut t_min, mut t_max) = (f64::NEG_INFINITY, f64::INFINITY);
+
+    for i in 0..3 {
+        if ray.direction[i] == 0.0 {
+            if ray.origin[i] < bound.min()[i] || bound.max()[i] < ray.origin[i] {
+                return None;
+            }
+            continue;
+        }
+
+        let t0 = (bound.min()[i] - ray.origin[i]) / ray.direction[i];
+        let t1 = (bound.max()[i] - ray.origin[i]) / ray.direction[i];
+
+        t_min = f64::max(t_min, f64::min(t0, t1));
+        t_max = f64::min(t_max, f64::max(t0, t1));
+    }
+
+    if t_min > t_max { None } else { Some((t_min, t_max)) }
+}
+
+impl Collidable for HeterogeneousMedium {
+    /// delta tracking: collisions are sampled as if the medium had the
+    /// majorant density everywhere, and each one is accepted as a real
+    /// collision with probability density / majorant
+    fn collide(&self, ray: Ray) -> Option<CollisionRecord> {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+
+        for (enter, exit) in self.spans(ray, f64::INFINITY) {
+            let mut t = enter;
+
+            loop {
+                t += sample_distance(majorant);
+                if t >= exit {
+                    break;
+                }
+
+                let point = ray.position_at(t);
+                if random_float() * majorant < self.density_at(point) {
+                    return Some(CollisionRecord {
+                        point,
+                        s_normal: -ray.direction,
+                        t,
+                        uv: UV::new(0.0, 0.0),
+                        face: Face::Outer,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Medium for HeterogeneousMedium {
+    /// ratio tracking: every tentative collision removes the fraction of the
+    /// light that a real collision would have scattered
+    fn transmittance(&self, ray: Ray, distance: f64) -> f64 {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let mut transmittance = 1.0;
+
+        for (enter, exit) in self.spans(ray, distance) {
+            let mut t = enter;
+
+            loop {
+                t += sample_distance(majorant);
+                if t >= exit {
+                    break;
+                }
+
+                transmittance *= 1.0 - self.density_at(ray.position_at(t)) / majorant;
+
+                // russian roulette, so long paths through the medium terminate
+                if transmittance < 0.1 {
+                    if random_float() < 0.5 {
+                        return 0.0;
+                    }
+                    transmittance *= 2.0;
+                }
+            }
+        }
+
+        transmittance
+    }
+}
+
+impl Boundable for HeterogeneousMedium {
+    fn bound(&self) -> AABB {
+        self.boundary.bound()
+    }
+}
+
+impl From<HeterogeneousMedium> for ShapeType {
+    fn from(medium: HeterogeneousMedium) -> Self {
+        ShapeType::HeterogeneousMedium(medium)
+    }
+}
+
+impl ShapeType {
+    /// whether light travels through the shape rather than bouncing off of it
+    pub fn is_medium(&self) -> bool {
+        match self {
+            ShapeType::Instance(s) => s.shape().is_medium(),
+            _ => matches!(self, ShapeType::ConstantMedium(_) | ShapeType::HeterogeneousMedium(_)),
+        }
+    }
+}
+
+impl Medium for ShapeType {
+    /// surfaces are handled by shadow rays, so they don't attenuate light here
+    fn transmittance(&self, ray: Ray, distance: f64) -> f64 {
+        match self {
+            ShapeType::ConstantMedium(m)      => m.transmittance(ray, distance),
+            ShapeType::HeterogeneousMedium(m) => m.transmittance(ray, distance),
+            ShapeType::Instance(m)            => m.transmittance(ray, distance),
+            _ => 1.0,
+        }
+    }
+}
+
+impl Medium for Instance {
+    /// in the shape's space, where the distance is scaled along with the medium
+    fn transmittance(&self, ray: Ray, distance: f64) -> f64 {
+        let transform = self.transform();
+        let local_ray = Ray::at_time(
+            transform.inverse_point(ray.origin),
+            transform.inverse_vector(ray.direction),
+            ray.time,
+        );
+
+        let local_distance = match distance.is_finite() {
+            true  => (transform.inverse_point(ray.position_at(distance)) - local_ray.origin).length(),
+            false => distance,
+        };
+
+        self.shape().transmittance(local_ray, local_distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Box;
+    use crate::utils::seed_random;
+
+    fn unit_box(density: f64) -> ConstantMedium {
+        ConstantMedium::new(ShapeType::Box(Box::new(Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0))), density)
+    }
+
+    /// along x, through the middle of the unit box
+    fn through_box() -> Ray {
+        Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn constant_transmittance() {
+        let medium = unit_box(2.0);
+        let ray = through_box();
+
+        assert!((medium.transmittance(ray, f64::INFINITY) - (-2.0f64).exp()).abs() < 1e-12);
+        // only the part of the ray inside of the box before `distance` counts
+        assert!((medium.transmittance(ray, 1.25) - (-0.5f64).exp()).abs() < 1e-12);
+        assert_eq!(medium.transmittance(ray, 0.5), 1.0);
+
+        let away = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(medium.transmittance(away, f64::INFINITY), 1.0);
+    }
+
+    #[test]
+    fn constant_collisions_inside_boundary() {
+        seed_random(7);
+        let medium = unit_box(2.0);
+        let ray = through_box();
+        let samples = 20000;
+
+        let mut hits = 0;
+        for _ in 0..samples {
+            if let Some(record) = medium.collide(ray) {
+                hits += 1;
+                assert!((1.0..=2.0).contains(&record.t));
+                assert!((0.0..=1.0).contains(&record.point[0]));
+                assert_eq!((record.point[1], record.point[2]), (0.5, 0.5));
+            }
+        }
+
+        // rays that scatter are the ones that aren't transmitted
+        let expected = 1.0 - medium.transmittance(ray, f64::INFINITY);
+        assert!((hits as f64 / samples as f64 - expected).abs() < 0.02);
+
+        let inside = Ray::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.0, 1.0, 1.0));
+        for _ in 0..1000 {
+            if let Some(record) = medium.collide(inside) {
+                assert!((0.0..=1.0).contains(&record.point[1]) && (0.0..=1.0).contains(&record.point[2]));
+            }
+        }
+    }
+
+    #[test]
+    fn zero_density() {
+        let medium = unit_box(0.0);
+
+        assert!(medium.collide(through_box()).is_none());
+        assert_eq!(medium.transmittance(through_box(), f64::INFINITY), 1.0);
+    }
+
+    /// 0 in the voxel at the origin, 1 in the one next to it along x
+    fn ramp() -> VoxelGrid {
+        VoxelGrid::new(Vec3::zeros(), Vec3::new(2.0, 1.0, 1.0), [2, 1, 1], vec![0.0, 1.0]).unwrap()
+    }
+
+    #[test]
+    fn voxel_grid_errors() {
+        let grid = |resolution, values| VoxelGrid::new(Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0), resolution, values);
+
+        assert!(matches!(grid([2, 0, 1], vec![]), Err(VolumeError::EmptyGrid)));
+        assert!(matches!(
+            grid([2, 2, 1], vec![0.0; 3]),
+            Err(VolumeError::VoxelCount { expected: 4, found: 3 })
+        ));
+        assert!(grid([2, 2, 1], vec![0.0; 4]).is_ok());
+    }
+
+    #[test]
+    fn voxel_grid_interpolation() {
+        let grid = ramp();
+        let at = |x: f64| grid.value(Vec3::new(x, 0.5, 0.5));
+
+        // exact at the voxel centers, linear between them, held towards the edges
+        assert_eq!(at(0.5), 0.0);
+        assert_eq!(at(1.5), 1.0);
+        assert!((at(1.0) - 0.5).abs() < 1e-12);
+        assert!((at(0.75) - 0.25).abs() < 1e-12);
+        assert_eq!(at(0.1), 0.0);
+        assert_eq!(at(1.9), 1.0);
+
+        // zero outside of the grid
+        assert_eq!(at(2.1), 0.0);
+        assert_eq!(grid.value(Vec3::new(1.5, -0.1, 0.5)), 0.0);
+        assert_eq!(grid.value(Vec3::new(1.5, 0.5, 1.1)), 0.0);
+    }
+
+    #[test]
+    fn voxel_values_are_clamped() {
+        let grid = VoxelGrid::new(Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0), [2, 1, 1], vec![-1.0, 3.0]).unwrap();
+        let field = DensityField::from(grid);
+
+        assert_eq!(field.max_value(), 1.0);
+        assert_eq!(field.value(Vec3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(field.value(Vec3::new(0.75, 0.5, 0.5)), 1.0);
+    }
+
+    #[test]
+    fn noise_threshold() {
+        assert!(matches!(NoiseField::new(1.0, 7, 1.0, 0), Err(VolumeError::Threshold { .. })));
+        assert!(matches!(NoiseField::new(1.0, 7, -0.1, 0), Err(VolumeError::Threshold { .. })));
+        assert!(NoiseField::new(1.0, 7, 0.0, 0).is_ok());
+        assert!(NoiseField::new(1.0, 7, 0.99, 0).is_ok());
+    }
+
+    #[test]
+    fn noise_seed() {
+        let points: Vec<Vec3> = (0..50).map(|i| Vec3::new(i as f64 * 0.37, i as f64 * 0.11, 1.3)).collect();
+        let values = |seed| {
+            let field = NoiseField::new(2.0, 7, 0.0, seed).unwrap();
+            points.iter().map(|p| field.value(*p)).collect::<Vec<f64>>()
+        };
+
+        assert_eq!(values(3), values(3));
+        assert_ne!(values(3), values(4));
+        assert!(values(3).iter().all(|v| (0.0..=1.0).contains(v)));
+    }
+}